@@ -0,0 +1,451 @@
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use crate::font::{ BIG_GLYPH_SIZE, NUM_SMALL_GLYPHS, SMALL_GLYPH_SIZE };
+use crate::memory::{ MemoryMap, INSTRUCTION_SIZE };
+use crate::opcodes::OpCode;
+use crate::platform::Platform;
+
+// Bytes drawn by the 16x16 sprites of the SCHIP DXY0
+const BIG_SPRITE_SIZE : usize = 32;
+
+// An instruction found at some address of the rom
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Finding
+{
+    pub address : usize,
+    pub word    : u16,
+}
+
+// What a rom does, found following its control flow from the program
+// start without running it. Code only reached through BNNN computed
+// jumps is missed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RomAnalysis
+{
+    pub size         : usize,
+    // Whether the rom fits in the program memory of the map analysed with
+    pub fits         : bool,
+    // Instructions the original CHIP-8 does not have
+    pub schip        : Vec<Finding>,
+    pub xochip       : Vec<Finding>,
+    // 0NNN calls to machine code routines of the host computer
+    pub machine_calls: Vec<Finding>,
+    // Reached words OpCode::new cannot decode
+    pub undecodable  : Vec<Finding>,
+    // Targets of 1NNN, and bases of BNNN
+    pub jump_targets : BTreeSet<usize>,
+    // Targets of 2NNN
+    pub call_targets : BTreeSet<usize>,
+    // Memory drawn by DXYN, the font glyphs apart
+    pub sprites      : Vec<Range<usize>>,
+    pub fonts        : Vec<Range<usize>>,
+    // Number of instructions reached
+    pub instructions : usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Confidence
+{
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Confidence::Low    => "low",
+            Confidence::Medium => "medium",
+            Confidence::High   => "high",
+        }
+    }
+}
+
+// Platform a rom was likely written for, and why
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detection
+{
+    // None for the original CHIP-8
+    pub platform   : Option<Platform>,
+    pub confidence : Confidence,
+    pub reasons    : Vec<String>,
+    // Instructions that will stop the rom
+    pub warnings   : Vec<String>,
+}
+
+impl Detection
+{
+    // E.g. "schip (high confidence)"
+    pub fn summary(&self) -> String
+    {
+        format!("{} ({} confidence)", self.platform.unwrap_or(Platform::CosmacVip).name(),
+                                      self.confidence.name())
+    }
+}
+
+// How control goes on after an instruction
+enum Flow
+{
+    Next,
+    // 3XNN and alike, which may skip the next instruction
+    Skip,
+    Jump(usize),
+    Call(usize),
+    Stop,
+}
+
+// Instructions worth reporting
+enum Kind
+{
+    Schip,
+    XoChip,
+    MachineCall,
+    Undecodable,
+}
+
+impl RomAnalysis
+{
+    pub fn new(rom: &[u8], map: &MemoryMap) -> Self
+    {
+        let mut analysis = RomAnalysis { size: rom.len(), fits: map.fits(rom.len()),
+                                         ..RomAnalysis::default() };
+
+        // An address is visited again when reached with another I
+        let mut visited  = BTreeSet::new();
+        let mut states   = BTreeSet::new();
+        let mut sprites  = Vec::new();
+        let mut fonts    = Vec::new();
+
+        // Addresses to visit, with the value of I when known
+        let mut pending  = vec![(map.program_start, None)];
+
+        while let Some((address, mut i)) = pending.pop()
+        {
+            let word = match word_at(rom, map, address)
+            {
+                Some(word) if states.insert((address, i)) => word,
+                _                                          => continue,
+            };
+
+            let mut found = None;
+            let next    = word_at(rom, map, address + INSTRUCTION_SIZE);
+            let mut len = INSTRUCTION_SIZE;
+            let nnn     = (word & 0xFFF) as usize;
+
+            let flow = match word
+            {
+                0x00E0                      => Flow::Next,
+                0x00EE                      => Flow::Stop,
+                0x00FD                      => { found = Some(Kind::Schip); Flow::Stop },
+                0x00C1..=0x00CF | 0x00FB | 0x00FC | 0x00FE | 0x00FF
+                                            => { found = Some(Kind::Schip); Flow::Next },
+                0x00D1..=0x00DF             => { found = Some(Kind::XoChip); Flow::Next },
+                0x0000..=0x0FFF             => { found = Some(Kind::MachineCall); Flow::Next },
+                0x1000..=0x1FFF             => Flow::Jump(nnn),
+                0x2000..=0x2FFF             => Flow::Call(nnn),
+                0xA000..=0xAFFF             => { i = Some(nnn); Flow::Next },
+                0xB000..=0xBFFF             =>
+                {
+                    analysis.jump_targets.insert(nnn);
+                    Flow::Stop
+                },
+                0xF000                      =>
+                {
+                    found = Some(Kind::XoChip);
+                    i   = next.map(usize::from);
+                    len = 2 * INSTRUCTION_SIZE;
+                    Flow::Next
+                },
+                _ if word & 0xF00F == 0x5002 || word & 0xF00F == 0x5003
+                                            => { found = Some(Kind::XoChip); Flow::Next },
+                _ if word & 0xF0FF == 0xF001 || word == 0xF002 || word & 0xF0FF == 0xF03A
+                                            => { found = Some(Kind::XoChip); Flow::Next },
+                _ if word & 0xF0FF == 0xF075 || word & 0xF0FF == 0xF085
+                                            => { found = Some(Kind::Schip); Flow::Next },
+                _ => match OpCode::new((word >> 8) as u8, word as u8)
+                {
+                    Ok(opcode) => match opcode
+                    {
+                        OpCode::_3XNN(..) | OpCode::_4XNN(..) | OpCode::_5XY0(..) |
+                        OpCode::_9XY0(..) | OpCode::_EX9E(..) | OpCode::_EXA1(..) => Flow::Skip,
+
+                        OpCode::_DXYN(_, _, n) =>
+                        {
+                            let size = match n
+                            {
+                                0 => { found = Some(Kind::Schip); BIG_SPRITE_SIZE },
+                                n => n as usize,
+                            };
+
+                            if let Some(start) = i
+                            {
+                                match start < map.program_start
+                                {
+                                    true  => fonts.push(start..start + size),
+                                    false => sprites.push(start..start + size),
+                                }
+                            }
+
+                            Flow::Next
+                        },
+
+                        OpCode::_FX29(_) =>
+                        {
+                            let start = map.font.address;
+                            fonts.push(start..start + NUM_SMALL_GLYPHS * SMALL_GLYPH_SIZE);
+                            i = None;
+                            Flow::Next
+                        },
+
+                        OpCode::_FX30(_) =>
+                        {
+                            found = Some(Kind::Schip);
+
+                            let start = map.font.address + map.font.font_set.small_glyphs().len();
+                            let size  = map.font.font_set.big_glyphs().len().max(BIG_GLYPH_SIZE);
+                            fonts.push(start..start + size);
+                            i = None;
+                            Flow::Next
+                        },
+
+                        OpCode::_FX1E(_) => { i = None; Flow::Next },
+
+                        _ => Flow::Next,
+                    },
+
+                    Err(_) => { found = Some(Kind::Undecodable); Flow::Stop },
+                },
+            };
+
+            // Instructions are only recorded the first time
+            if visited.insert(address)
+            {
+                analysis.instructions += 1;
+
+                let finding = Finding { address, word };
+
+                match found
+                {
+                    Some(Kind::Schip)       => analysis.schip.push(finding),
+                    Some(Kind::XoChip)      => analysis.xochip.push(finding),
+                    Some(Kind::MachineCall) => analysis.machine_calls.push(finding),
+                    Some(Kind::Undecodable) => analysis.undecodable.push(finding),
+                    None                    => (),
+                }
+            }
+
+            match flow
+            {
+                Flow::Next         => pending.push((address + len, i)),
+                Flow::Stop         => (),
+                Flow::Jump(target) =>
+                {
+                    analysis.jump_targets.insert(target);
+                    pending.push((target, i));
+                },
+                Flow::Call(target) =>
+                {
+                    analysis.call_targets.insert(target);
+                    pending.push((address + len, None));
+                    pending.push((target, i));
+                },
+                Flow::Skip         =>
+                {
+                    // The XO-CHIP F000 NNNN is skipped as a whole
+                    let skipped = match next
+                    {
+                        Some(0xF000) => 2 * INSTRUCTION_SIZE,
+                        _            => INSTRUCTION_SIZE,
+                    };
+
+                    pending.push((address + len, i));
+                    pending.push((address + len + skipped, i));
+                },
+            }
+        }
+
+        for findings in [ &mut analysis.schip, &mut analysis.xochip, &mut analysis.machine_calls,
+                          &mut analysis.undecodable ].iter_mut()
+        {
+            findings.sort_by_key(|finding| finding.address);
+        }
+
+        analysis.sprites = merge(sprites);
+        analysis.fonts   = merge(fonts);
+
+        analysis
+    }
+
+    // Guesses the platform the rom was written for
+    pub fn detect(&self) -> Detection
+    {
+        let chip8_size = MemoryMap::default().program_size();
+
+        let mut reasons  = Vec::new();
+        let mut warnings = Vec::new();
+
+        for finding in self.xochip.iter()
+        {
+            reasons.push(format!("XO-CHIP instruction {:04X} at {:#05X}", finding.word, finding.address));
+        }
+
+        for finding in self.schip.iter()
+        {
+            reasons.push(format!("SCHIP instruction {:04X} at {:#05X}", finding.word, finding.address));
+        }
+
+        if self.size > chip8_size
+        {
+            reasons.push(format!("{} bytes do not fit in the {} of CHIP-8", self.size, chip8_size));
+        }
+
+        for finding in self.machine_calls.iter()
+        {
+            warnings.push(format!("Machine code call {:04X} at {:#05X} cannot be emulated",
+                                  finding.word, finding.address));
+        }
+
+        // Several extended instructions are hardly a coincidence, a single
+        // one may be data read as code
+        let (platform, confidence) = match (self.xochip.len(), self.schip.len())
+        {
+            (0, 0) if self.size > chip8_size => (Some(Platform::XoChip), Confidence::Medium),
+            (0, 0) if self.undecodable.is_empty() && self.instructions > 0
+                                             => (None, Confidence::Medium),
+            (0, 0)                           => (None, Confidence::Low),
+            (0, 1)                           => (Some(Platform::Schip), Confidence::Medium),
+            (0, _)                           => (Some(Platform::Schip), Confidence::High),
+            (1, _) if self.size <= chip8_size => (Some(Platform::XoChip), Confidence::Medium),
+            (_, _)                           => (Some(Platform::XoChip), Confidence::High),
+        };
+
+        Detection { platform, confidence, reasons, warnings }
+    }
+}
+
+// Big endian word of the rom at a memory address
+fn word_at(rom: &[u8], map: &MemoryMap, address: usize) -> Option<u16>
+{
+    let offset = address.checked_sub(map.program_start)?;
+
+    match rom.get(offset..offset + INSTRUCTION_SIZE)?
+    {
+        &[msb, lsb] => Some(u16::from_be_bytes([msb, lsb])),
+        _           => None,
+    }
+}
+
+// Sorts the ranges, joining the ones overlapping or touching
+fn merge(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>>
+{
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+
+    for range in ranges
+    {
+        match merged.last_mut()
+        {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _                                     => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn analyse(source: &str) -> RomAnalysis
+    {
+        let rom = assemble(source, 0x200).unwrap();
+        RomAnalysis::new(&rom, &MemoryMap::default())
+    }
+
+    #[test]
+    fn control_flow()
+    {
+        let analysis = analyse("
+                    CALL draw
+                    SE   V0, 1
+                    JP   end
+                    DB   0xFF, 0xFF         ; skipped over
+            end:    JP   end
+            draw:   LD   I, sprite
+                    DRW  V0, V1, 3
+                    LD   F, V0
+                    DRW  V0, V1, 5
+                    RET
+                    DB   0x12, 0x34         ; never reached
+            sprite: DB   0x80, 0x40, 0x20");
+
+        assert_eq!(analysis.call_targets.iter().copied().collect::<Vec<_>>(), vec![0x20A]);
+        assert_eq!(analysis.jump_targets.iter().copied().collect::<Vec<_>>(), vec![0x208]);
+        assert_eq!(analysis.sprites, vec![0x216..0x219]);
+        assert_eq!(analysis.fonts, vec![0x000..0x050]);
+        assert_eq!(analysis.undecodable, vec![Finding { address: 0x206, word: 0xFFFF }]);
+        assert_eq!(analysis.instructions, 10);
+        assert_eq!(analysis.detect().platform, None);
+        assert!(analysis.fits);
+    }
+
+    #[test]
+    fn platforms()
+    {
+        let schip = analyse("
+                    DB   0x00, 0xFF         ; high resolution
+                    DRW  V0, V1, 0
+                    DB   0x00, 0xFD         ; exit");
+
+        assert_eq!(schip.schip.len(), 3);
+        assert_eq!(schip.detect().platform, Some(Platform::Schip));
+
+        let xochip = analyse("
+                    SE   V0, 0
+                    DB   0xF0, 0x00, 0x03, 0x00 ; long I load, skipped as a whole
+                    DB   0xF2, 0x01         ; plane 2
+                    DRW  V0, V1, 4
+            end:    JP   end");
+
+        assert_eq!(xochip.xochip.len(), 2);
+        assert_eq!(xochip.sprites, vec![0x300..0x304]);
+        assert_eq!(xochip.detect().platform, Some(Platform::XoChip));
+        assert!(xochip.undecodable.is_empty());
+
+        let machine = analyse("SYS 0x123\nCLS");
+        assert_eq!(machine.machine_calls, vec![Finding { address: 0x200, word: 0x0123 }]);
+    }
+
+    #[test]
+    fn detection()
+    {
+        let schip = analyse("DB 0x00, 0xFF\nDRW V0, V1, 0").detect();
+        assert_eq!(schip.platform, Some(Platform::Schip));
+        assert_eq!(schip.confidence, Confidence::High);
+        assert_eq!(schip.reasons.len(), 2);
+
+        let chip8 = analyse("CLS\nSYS 0x123\nend: JP end").detect();
+        assert_eq!(chip8.platform, None);
+        assert_eq!(chip8.confidence, Confidence::Medium);
+        assert_eq!(chip8.warnings, vec!["Machine code call 0123 at 0x202 cannot be emulated"]);
+
+        let mut big = assemble("end: JP end", 0x200).unwrap();
+        big.resize(4000, 0);
+
+        let big = RomAnalysis::new(&big, &MemoryMap::default()).detect();
+        assert_eq!(big.platform, Some(Platform::XoChip));
+        assert_eq!(big.confidence, Confidence::Medium);
+
+        let unknown = analyse("DB 0xFF, 0xFF").detect();
+        assert_eq!(unknown.platform, None);
+        assert_eq!(unknown.confidence, Confidence::Low);
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use crate::memory::INSTRUCTION_SIZE;
+use crate::opcodes::OpCode;
+
+// Small assembler for the usual CHIP-8 mnemonics (Cowgod's reference),
+// enough to write test roms in the repository:
+//
+//     loop:               ; labels end with a colon
+//         LD   V0, #0A    ; numbers are decimal, #hex, 0xhex or %binary
+//         DRW  V0, V1, 5
+//         JP   loop
+//     sprite:
+//         DB   %11110000, 0x90
+//
+// Labels can be used wherever a number is expected.
+
+enum Line<'a>
+{
+    Instruction(&'a str, Vec<&'a str>),
+    Bytes(Vec<&'a str>),
+}
+
+// Assembles the source into a rom loaded at the given address
+pub fn assemble(source: &str, origin: usize) -> Result<Vec<u8>, String>
+{
+    let mut labels  = HashMap::new();
+    let mut lines   = Vec::new();
+    let mut address = origin;
+
+    // First pass: find the address of every label
+    for (number, text) in source.lines().enumerate()
+    {
+        let with_line = | e: String | format!("Line {}: {}", number + 1, e);
+
+        let mut text = text.split(';').next().unwrap_or("").trim();
+
+        if let Some(colon) = text.find(':')
+        {
+            let label = text[..colon].trim();
+
+            if label.is_empty() || label.contains(char::is_whitespace)
+            {
+                return Err(with_line(format!("Invalid label '{}'", label)));
+            }
+
+            if labels.insert(label.to_uppercase(), address).is_some()
+            {
+                return Err(with_line(format!("Duplicated label '{}'", label)));
+            }
+
+            text = text[colon + 1..].trim();
+        }
+
+        if text.is_empty() { continue; }
+
+        let (mnemonic, operands) = match text.find(char::is_whitespace)
+        {
+            Some(space) => (&text[..space], text[space..].split(',').map(str::trim).collect()),
+            None        => (text, Vec::new()),
+        };
+
+        let line = match mnemonic.to_uppercase().as_str()
+        {
+            "DB" => Line::Bytes(operands),
+            _    => Line::Instruction(mnemonic, operands),
+        };
+
+        address += match &line
+        {
+            Line::Bytes(bytes)      => bytes.len(),
+            Line::Instruction(..)   => INSTRUCTION_SIZE,
+        };
+
+        lines.push((number + 1, line));
+    }
+
+    // Second pass: encode with the labels known
+    let mut rom = Vec::new();
+
+    for (number, line) in lines
+    {
+        let with_line = | e: String | format!("Line {}: {}", number, e);
+
+        match line
+        {
+            Line::Bytes(bytes) => for byte in bytes
+            {
+                rom.push(value(byte, 0xFF, &labels).map_err(with_line)? as u8);
+            },
+
+            Line::Instruction(mnemonic, operands) =>
+            {
+                let opcode = encode(mnemonic, &operands, &labels).map_err(with_line)?;
+                rom.extend_from_slice(&opcode.encode().to_be_bytes());
+            },
+        }
+    }
+
+    Ok(rom)
+}
+
+fn encode(mnemonic: &str, operands: &[&str], labels: &HashMap<String, usize>) -> Result<OpCode, String>
+{
+    use OpCode::*;
+    use Operand::*;
+
+    let operands = operands.iter()
+                           .map(|text| Operand::parse(text, labels))
+                           .collect::<Result<Vec<_>, String>>()?;
+
+    let mnemonic = mnemonic.to_uppercase();
+
+    let opcode = match (mnemonic.as_str(), operands.as_slice())
+    {
+        ("CLS",  [])                         => _00E0,
+        ("RET",  [])                         => _00EE,
+        ("SYS",  [Number(nnn)])              => _0NNN(address(*nnn)?),
+        ("JP",   [Number(nnn)])              => _1NNN(address(*nnn)?),
+        ("JP",   [Register(0), Number(nnn)]) => _BNNN(address(*nnn)?),
+        ("CALL", [Number(nnn)])              => _2NNN(address(*nnn)?),
+        ("SE",   [Register(x), Number(nn)])  => _3XNN(*x, byte(*nn)?),
+        ("SE",   [Register(x), Register(y)]) => _5XY0(*x, *y),
+        ("SNE",  [Register(x), Number(nn)])  => _4XNN(*x, byte(*nn)?),
+        ("SNE",  [Register(x), Register(y)]) => _9XY0(*x, *y),
+        ("LD",   [Register(x), Number(nn)])  => _6XNN(*x, byte(*nn)?),
+        ("LD",   [Register(x), Register(y)]) => _8XY0(*x, *y),
+        ("LD",   [I, Number(nnn)])           => _ANNN(address(*nnn)?),
+        ("LD",   [Register(x), Delay])       => _FX07(*x),
+        ("LD",   [Register(x), Key])         => _FX0A(*x),
+        ("LD",   [Delay, Register(x)])       => _FX15(*x),
+        ("LD",   [Sound, Register(x)])       => _FX18(*x),
+        ("LD",   [Font, Register(x)])        => _FX29(*x),
+        ("LD",   [BigFont, Register(x)])     => _FX30(*x),
+        ("LD",   [Bcd, Register(x)])         => _FX33(*x),
+        ("LD",   [Memory, Register(x)])      => _FX55(*x),
+        ("LD",   [Register(x), Memory])      => _FX65(*x),
+        ("ADD",  [Register(x), Number(nn)])  => _7XNN(*x, byte(*nn)?),
+        ("ADD",  [Register(x), Register(y)]) => _8XY4(*x, *y),
+        ("ADD",  [I, Register(x)])           => _FX1E(*x),
+        ("OR",   [Register(x), Register(y)]) => _8XY1(*x, *y),
+        ("AND",  [Register(x), Register(y)]) => _8XY2(*x, *y),
+        ("XOR",  [Register(x), Register(y)]) => _8XY3(*x, *y),
+        ("SUB",  [Register(x), Register(y)]) => _8XY5(*x, *y),
+        ("SHR",  [Register(x)])              => _8XY6(*x, *x),
+        ("SHR",  [Register(x), Register(y)]) => _8XY6(*x, *y),
+        ("SUBN", [Register(x), Register(y)]) => _8XY7(*x, *y),
+        ("SHL",  [Register(x)])              => _8XYE(*x, *x),
+        ("SHL",  [Register(x), Register(y)]) => _8XYE(*x, *y),
+        ("RND",  [Register(x), Number(nn)])  => _CXNN(*x, byte(*nn)?),
+        ("SKP",  [Register(x)])              => _EX9E(*x),
+        ("SKNP", [Register(x)])              => _EXA1(*x),
+
+        ("DRW",  [Register(x), Register(y), Number(n)]) if *n <= 0xF => _DXYN(*x, *y, *n as u8),
+
+        _ => return Err(format!("Invalid instruction '{}' with {} operands", mnemonic, operands.len())),
+    };
+
+    Ok(opcode)
+}
+
+#[derive(Debug)]
+enum Operand
+{
+    Register(u8),
+    Number(usize),
+    I,
+    Delay,
+    Sound,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Memory,
+}
+
+impl Operand
+{
+    fn parse(text: &str, labels: &HashMap<String, usize>) -> Result<Self, String>
+    {
+        let upper = text.to_uppercase();
+
+        let operand = match upper.as_str()
+        {
+            "I"   => Operand::I,
+            "DT"  => Operand::Delay,
+            "ST"  => Operand::Sound,
+            "K"   => Operand::Key,
+            "F"   => Operand::Font,
+            "HF"  => Operand::BigFont,
+            "B"   => Operand::Bcd,
+            "[I]" => Operand::Memory,
+
+            _ if upper.len() == 2 && upper.starts_with('V') =>
+                match u8::from_str_radix(&upper[1..], 16)
+                {
+                    Ok(x)  => Operand::Register(x),
+                    Err(_) => return Err(format!("Invalid register '{}'", text)),
+                },
+
+            _ => Operand::Number(value(text, 0xFFFF, labels)?),
+        };
+
+        Ok(operand)
+    }
+}
+
+// Parses a number or label, up to the given maximum
+fn value(text: &str, max: usize, labels: &HashMap<String, usize>) -> Result<usize, String>
+{
+    let parsed = if let Some(hex) = text.strip_prefix('#')
+    {
+        usize::from_str_radix(hex, 16).ok()
+    }
+    else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))
+    {
+        usize::from_str_radix(hex, 16).ok()
+    }
+    else if let Some(binary) = text.strip_prefix('%')
+    {
+        usize::from_str_radix(binary, 2).ok()
+    }
+    else if text.starts_with(|c: char| c.is_ascii_digit())
+    {
+        text.parse().ok()
+    }
+    else
+    {
+        match labels.get(&text.to_uppercase())
+        {
+            Some(&address) => Some(address),
+            None           => return Err(format!("Unknown label '{}'", text)),
+        }
+    };
+
+    match parsed
+    {
+        Some(number) if number <= max => Ok(number),
+        Some(number)                  => Err(format!("Value {:#X} out of range", number)),
+        None                          => Err(format!("Invalid number '{}'", text)),
+    }
+}
+
+fn address(value: usize) -> Result<u16, String>
+{
+    match value
+    {
+        0..=0xFFF => Ok(value as u16),
+        _         => Err(format!("Address {:#X} out of range", value)),
+    }
+}
+
+fn byte(value: usize) -> Result<u8, String>
+{
+    match value
+    {
+        0..=0xFF => Ok(value as u8),
+        _        => Err(format!("Byte {:#X} out of range", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn instructions() -> Result<(), String>
+    {
+        let source = "
+            start:  CLS             ; clear the screen
+                    LD   V1, #0A
+                    LD   I, sprite
+                    ADD  I, V1
+                    DRW  V0, V1, 5
+                    LD   [I], VF
+                    SHL  V2
+                    JP   V0, start
+            sprite: DB   %11110000, 0x90, 255";
+
+        let rom = assemble(source, 0x200)?;
+
+        assert_eq!(rom, vec![0x00, 0xE0, 0x61, 0x0A, 0xA2, 0x10, 0xF1, 0x1E,
+                             0xD0, 0x15, 0xFF, 0x55, 0x82, 0x2E, 0xB2, 0x00,
+                             0xF0, 0x90, 0xFF]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn errors()
+    {
+        let error = assemble("CLS\nJP nowhere", 0x200).unwrap_err();
+        assert_eq!(error, "Line 2: Unknown label 'nowhere'");
+
+        assert!(assemble("LD V0, 256", 0x200).is_err(), "Byte out of range accepted");
+        assert!(assemble("DRW V0, V1, 16", 0x200).is_err(), "Sprite height out of range accepted");
+        assert!(assemble("LD VG, 1", 0x200).is_err(), "Invalid register accepted");
+        assert!(assemble("a:\na:", 0x200).is_err(), "Duplicated label accepted");
+        assert!(assemble("NOP", 0x200).is_err(), "Unknown mnemonic accepted");
+    }
+}
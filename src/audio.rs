@@ -1,50 +1,240 @@
+use std::sync::{ Arc, Mutex };
 
+use rand::{ Rng, SeedableRng };
+use rand::rngs::StdRng;
 use sdl2::audio::{ AudioCallback, AudioSpecDesired };
 
-struct SineWave
+use crate::clock::Frequency;
+use crate::timer::TimerStatus;
+
+pub mod wav;
+mod offline;
+
+pub use offline::OfflineAudio;
+
+pub const DEFAULT_SAMPLE_RATE : i32 = 44100;
+
+const DEFAULT_TONE_FREQUENCY : f32 = 440.0;
+const DEFAULT_AMPLITUDE      : u16 = 30000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform
 {
-    tone_frequency: f32,
-    sample_rate:    u16,
-    amplitude:      u16,
+    Square,
+    Sine,
+    Triangle,
+    Noise,
 }
 
-impl SineWave
+impl Waveform
 {
-    fn samples(&self) -> Vec<i16>
+    // Value of the wave, between -1 and 1, at the given point of its
+    // cycle. Noise has no shape, so it is generated by the callback.
+    fn value(&self, phase: f32) -> f32
     {
-        let increment   = self.tone_frequency / self.sample_rate as f32;
+        use Waveform::*;
 
-        // Num the samples needed to get a full cycle to 2pi.
-        let num_samples = (1.0 / increment).ceil() as isize;
+        match self
+        {
+            Square   => if phase < 0.5 { 1.0 } else { -1.0 },
+            Sine     => (phase * 2.0 * std::f32::consts::PI).sin(),
+            Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Noise    => 0.0,
+        }
+    }
+}
 
-        let generator = | step: f32 |
-            {
-                let value = self.amplitude as f32 * (step * 2.0 * std::f32::consts::PI).sin();
-                return value as i16;
-            };
+// Sound played by the speakers. Volume goes from 0 (silence) to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tone
+{
+    pub waveform  : Waveform,
+    pub frequency : f32,
+    pub volume    : f32,
+}
 
-        let samples = (0..num_samples)
-                        .map(|x| x as f32 * increment)
-                        .map(generator)
-                        .collect::<Vec<i16>>();
+impl Tone
+{
+    fn amplitude(&self) -> u16
+    {
+        (self.volume.clamp(0.0, 1.0) * i16::MAX as f32).round() as u16
+    }
+}
 
-        return samples;
+impl Default for Tone
+{
+    fn default() -> Self
+    {
+        Tone { waveform  : Waveform::Sine,
+               frequency : DEFAULT_TONE_FREQUENCY,
+               volume    : DEFAULT_AMPLITUDE as f32 / i16::MAX as f32,
+             }
     }
 }
 
-struct SpeakersCallback
+// Length of the volume ramps applied when the tone starts and stops.
+// Cutting a wave in the middle of a cycle is heard as a click.
+const ATTACK_TIME  : f32 = 0.005;
+const RELEASE_TIME : f32 = 0.010;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ToneSettings
+{
+    tone    : Tone,
+    muted   : bool,
+    playing : bool,
+}
+
+// Handle shared between the speakers and the audio callback, which runs
+// on its own thread. Changes are picked up by the next callback call.
+#[derive(Clone)]
+pub struct ToneControl
 {
-    samples: Vec<i16>,
-    current_sample: usize,
+    settings: Arc<Mutex<ToneSettings>>,
 }
 
-impl SpeakersCallback
+impl ToneControl
+{
+    pub fn new(tone: Tone) -> Self
+    {
+        let settings = ToneSettings { tone, muted: false, playing: false };
+
+        ToneControl { settings: Arc::new(Mutex::new(settings)) }
+    }
+
+    pub fn tone(&self) -> Tone
+    {
+        self.settings().tone
+    }
+
+    pub fn set_tone(&self, tone: Tone)
+    {
+        self.update(|settings| settings.tone = tone);
+    }
+
+    pub fn set_waveform(&self, waveform: Waveform)
+    {
+        self.update(|settings| settings.tone.waveform = waveform);
+    }
+
+    pub fn set_frequency(&self, frequency: f32)
+    {
+        self.update(|settings| settings.tone.frequency = frequency);
+    }
+
+    pub fn set_volume(&self, volume: f32)
+    {
+        self.update(|settings| settings.tone.volume = volume);
+    }
+
+    pub fn is_muted(&self) -> bool
+    {
+        self.settings().muted
+    }
+
+    pub fn set_muted(&self, muted: bool)
+    {
+        self.update(|settings| settings.muted = muted);
+    }
+
+    // Returns whether the speakers are muted after the change
+    pub fn toggle_mute(&self) -> bool
+    {
+        self.update(|settings| settings.muted = !settings.muted);
+        self.is_muted()
+    }
+
+    pub fn is_playing(&self) -> bool
+    {
+        self.settings().playing
+    }
+
+    // Starts or stops the tone. The callback ramps the volume up or
+    // down instead of cutting the wave.
+    pub fn set_playing(&self, playing: bool)
+    {
+        self.update(|settings| settings.playing = playing);
+    }
+}
+
+// private
+impl ToneControl
 {
-    fn new(tone_frequency: f32, sample_rate: u16, amplitude: u16) -> SpeakersCallback
+    fn settings(&self) -> ToneSettings
     {
-        let wave = SineWave { tone_frequency, sample_rate, amplitude };
+        match self.settings.lock()
+        {
+            Ok(settings)  => *settings,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
 
-        return SpeakersCallback { samples: wave.samples(), current_sample: 0 };
+    fn update<F: FnOnce(&mut ToneSettings)>(&self, change: F)
+    {
+        match self.settings.lock()
+        {
+            Ok(mut settings) => change(&mut settings),
+            Err(poisoned)    => change(&mut poisoned.into_inner()),
+        }
+    }
+}
+
+// Generates a waveform sample by sample. The phase, in cycles, is kept
+// between calls so the wave never jumps when it loops, restarts or
+// changes its frequency.
+struct Oscillator
+{
+    phase       : f64,
+    noise_value : f32,
+    rng         : StdRng,
+}
+
+impl Oscillator
+{
+    fn new() -> Self
+    {
+        let mut rng     = StdRng::from_entropy();
+        let noise_value = rng.gen_range(-1.0, 1.0);
+
+        Oscillator { phase: 0.0, noise_value, rng }
+    }
+
+    // Returns a value between -1 and 1
+    fn next_sample(&mut self, waveform: Waveform, frequency: f32, sample_rate: u16) -> f32
+    {
+        let value = match waveform
+        {
+            Waveform::Noise => self.noise_value,
+            _               => waveform.value(self.phase as f32),
+        };
+
+        let previous_half = self.phase >= 0.5;
+        self.phase = (self.phase + frequency as f64 / sample_rate as f64).fract();
+
+        // Noise is a random value held for half a cycle
+        if waveform == Waveform::Noise && (self.phase >= 0.5) != previous_half
+        {
+            self.noise_value = self.rng.gen_range(-1.0, 1.0);
+        }
+
+        return value;
+    }
+}
+
+pub(crate) struct SpeakersCallback
+{
+    oscillator:  Oscillator,
+    sample_rate: u16,
+    gain:        f32,
+    control:     ToneControl,
+}
+
+impl SpeakersCallback
+{
+    pub(crate) fn new(control: ToneControl, sample_rate: u16) -> SpeakersCallback
+    {
+        return SpeakersCallback { oscillator: Oscillator::new(), sample_rate,
+                                  gain: 0.0, control };
     }
 }
 
@@ -54,60 +244,132 @@ impl AudioCallback for SpeakersCallback
 
     fn callback(&mut self, out: &mut [Self::Channel])
     {
+        let settings  = self.control.settings();
+        let tone      = settings.tone;
+        let audible   = settings.playing && !settings.muted;
+        let amplitude = tone.amplitude() as f32;
+
+        let attack_step  = 1.0 / (ATTACK_TIME  * self.sample_rate as f32);
+        let release_step = 1.0 / (RELEASE_TIME * self.sample_rate as f32);
+
         for x in out.iter_mut()
         {
-            if self.current_sample == self.samples.len()
+            self.gain = if audible { (self.gain + attack_step).min(1.0) }
+                        else       { (self.gain - release_step).max(0.0) };
+
+            // The oscillator only runs while it can be heard, so the
+            // wave resumes where it was left
+            if self.gain == 0.0
             {
-                self.current_sample = 0;
+                *x = 0;
+                continue;
             }
 
-            *x = self.samples[self.current_sample];
-            self.current_sample += 1;
+            let value = self.oscillator.next_sample(tone.waveform, tone.frequency, self.sample_rate);
+            *x = (value * amplitude * self.gain) as i16;
         }
     }
 }
 
+// Destination of the buzzer output
+pub trait AudioSink
+{
+    // Waveform, frequency, volume, mute and playing state of the buzzer
+    fn control(&self) -> ToneControl;
+
+    fn start(&self)
+    {
+        self.control().set_playing(true);
+    }
+
+    fn stop(&self)
+    {
+        self.control().set_playing(false);
+    }
+
+    fn is_playing(&self) -> bool
+    {
+        self.control().is_playing()
+    }
+
+    // Starts or stops playing following the sound timer. Returned by
+    // both set_value() and tick().
+    fn update(&self, sound_timer_status: &TimerStatus)
+    {
+        match sound_timer_status
+        {
+            TimerStatus::Started => self.start(),
+            TimerStatus::End     => self.stop(),
+            _                    => (),
+        }
+    }
+
+    // Called once per emulated frame. Sinks that produce the samples
+    // themselves, instead of an audio device, render the frame here.
+    fn end_frame(&mut self, _frame_rate: Frequency)
+    {
+    }
+
+    // Renderer holding the samples, for sinks that are not played on an
+    // audio device
+    fn offline(&self) -> Option<&OfflineAudio>
+    {
+        None
+    }
+}
+
 pub struct Speakers
 {
-    device: sdl2::audio::AudioDevice<SpeakersCallback>,
+    device  : sdl2::audio::AudioDevice<SpeakersCallback>,
+    control : ToneControl,
 }
 
 impl Speakers
 {
     pub fn new(context: &sdl2::Sdl) -> Result<Speakers, String>
+    {
+        Self::with_tone(context, Tone::default())
+    }
+
+    pub fn with_tone(context: &sdl2::Sdl, tone: Tone) -> Result<Speakers, String>
     {
         let audio_subsystem = context.audio()?;
 
         let desired_spec = AudioSpecDesired
         {
-            freq:     Some(44100),
+            freq:     Some(DEFAULT_SAMPLE_RATE),
             channels: Some(1),
             samples:  None
         };
 
-        let get_callback = |spec: sdl2::audio::AudioSpec|
+        let control          = ToneControl::new(tone);
+        let callback_control = control.clone();
+
+        let get_callback = move |spec: sdl2::audio::AudioSpec|
         {
-           SpeakersCallback::new(440.0, spec.freq as u16, 30000)
+           SpeakersCallback::new(callback_control, spec.freq as u16)
         };
 
         let device = audio_subsystem.open_playback(None, &desired_spec, get_callback)?;
 
-        Ok( Speakers { device } )
-    }
+        // The device is always running. Silence is generated by
+        // the callback while the tone is stopped.
+        device.resume();
 
-    pub fn start(&self)
-    {
-        self.device.resume();
+        Ok( Speakers { device, control } )
     }
 
-    pub fn stop(&self)
+    pub fn is_device_running(&self) -> bool
     {
-        self.device.pause();
+        return self.device.status() == sdl2::audio::AudioStatus::Playing;
     }
+}
 
-    pub fn is_playing(&self) -> bool
+impl AudioSink for Speakers
+{
+    fn control(&self) -> ToneControl
     {
-        return self.device.status() == sdl2::audio::AudioStatus::Playing;
+        self.control.clone()
     }
 }
 
@@ -118,14 +380,28 @@ mod tests
     use std::time::Duration;
     use crate::helpers::tests::*;
 
+    // Collects the samples of an oscillator scaled to the given amplitude
+    fn oscillator_samples(waveform: Waveform, frequency: f32, amplitude: f32, count: usize) -> Vec<i16>
+    {
+        let mut oscillator = Oscillator::new();
+
+        (0..count).map(|_| oscillator.next_sample(waveform, frequency, 44100))
+                  .map(|value| (value * amplitude) as i16)
+                  .collect()
+    }
+
+    // Largest jump between two consecutive samples
+    fn max_step(samples: &[i16]) -> i32
+    {
+        samples.windows(2)
+               .map(|pair| (pair[1] as i32 - pair[0] as i32).abs())
+               .max()
+               .unwrap_or(0)
+    }
+
     #[test]
     fn sample_generation()
     {
-        let wave = SineWave { tone_frequency: 440.0,
-                              sample_rate:    44100,
-                              amplitude:      30000
-                            };
-
         let golden_samples = vec![0, 1879, 3751, 5608, 7444, 9250, 11019, 12746, 14422, 16042, 17598,
                               19086, 20498, 21830, 23077, 24232, 25293, 26254, 27111, 27863, 28505,
                               29035, 29450, 29750, 29934, 29999, 29947, 29777, 29490, 29088, 28571,
@@ -138,11 +414,159 @@ mod tests
                               -25520, -24482, -23347, -22121, -20808, -19414, -17943, -16401,
                               -14795, -13131, -11416, -9655, -7857, -6028, -4175, -2305, -427];
 
-        let samples = wave.samples();
+        let samples = oscillator_samples(Waveform::Sine, 440.0, 30000.0, golden_samples.len());
 
         assert_eq!( samples, golden_samples);
     }
 
+    #[test]
+    fn phase_continuity()
+    {
+        // 440Hz does not fit an integer number of samples per cycle. The
+        // wave must not jump when a cycle ends, no matter how many of them.
+        let samples = oscillator_samples(Waveform::Sine, 440.0, 30000.0, 44100);
+
+        // Max slope of the sine wave per sample
+        let max_slope = (30000.0 * 2.0 * std::f32::consts::PI * 440.0 / 44100.0).ceil() as i32;
+
+        assert!(max_step(&samples) <= max_slope,
+                "Wave jumps {} between samples, max expected {}", max_step(&samples), max_slope);
+    }
+
+    #[test]
+    fn waveform_shapes()
+    {
+        // 100 samples per cycle
+        let square   = oscillator_samples(Waveform::Square, 441.0, 1000.0, 100);
+        let triangle = oscillator_samples(Waveform::Triangle, 441.0, 1000.0, 100);
+
+        assert!(square[..50].iter().all(|&x| x == 1000), "Square first half is not high");
+        assert!(square[50..].iter().all(|&x| x == -1000), "Square second half is not low");
+
+        assert_eq!(triangle[0], -1000);
+        assert_eq!(triangle[25], 0);
+        assert_eq!(triangle[50], 1000);
+        assert_eq!(triangle[75], 0);
+    }
+
+    #[test]
+    fn default_tone_amplitude()
+    {
+        let tone = Tone::default();
+
+        assert_eq!(tone.amplitude(), DEFAULT_AMPLITUDE);
+        assert_eq!(Tone { volume: 0.0, ..tone }.amplitude(), 0);
+        assert_eq!(Tone { volume: 2.0, ..tone }.amplitude(), i16::MAX as u16);
+    }
+
+    #[test]
+    fn envelope_ramps()
+    {
+        let control      = ToneControl::new(Tone { waveform: Waveform::Square,
+                                                   frequency: 441.0, volume: 1.0 });
+        let mut callback = SpeakersCallback::new(control.clone(), 44100);
+        let mut buffer   = [0; 1000];
+
+        let attack_samples  = (ATTACK_TIME  * 44100.0) as usize;
+        let release_samples = (RELEASE_TIME * 44100.0) as usize;
+
+        // Stopped speakers are silent
+        callback.callback(&mut buffer);
+        assert!(buffer.iter().all(|&x| x == 0), "Stopped speakers are not silent");
+
+        // The volume ramps up when the tone starts
+        control.set_playing(true);
+        callback.callback(&mut buffer);
+
+        assert!(buffer[0].abs() < 1000, "Tone started at full volume");
+        assert!(buffer[..attack_samples].windows(2).all(|pair| pair[1].abs() >= pair[0].abs()
+                                                            || pair[1].signum() != pair[0].signum()),
+                "Attack ramp is not increasing");
+        assert!(buffer[attack_samples + 1..].iter().all(|&x| x.abs() == i16::MAX),
+                "Tone did not reach full volume after the attack");
+
+        // And fades out when it stops, instead of cutting the wave
+        control.set_playing(false);
+        callback.callback(&mut buffer);
+
+        assert!(buffer[0].abs() > i16::MAX - 1000, "Tone cut when stopped");
+        assert!(buffer[release_samples..].iter().all(|&x| x == 0),
+                "Tone did not fade out after the release");
+    }
+
+    #[test]
+    fn restart_continuity()
+    {
+        let control      = ToneControl::new(Tone::default());
+        let mut callback = SpeakersCallback::new(control.clone(), 44100);
+        let mut stream   = Vec::new();
+        let mut buffer   = [0; 777];
+
+        // Start and stop the tone at arbitrary points of its cycle
+        for cycle in 0..20
+        {
+            control.set_playing(cycle % 3 != 0);
+            callback.callback(&mut buffer);
+            stream.extend_from_slice(&buffer);
+        }
+
+        let max_slope = (DEFAULT_AMPLITUDE as f32 * 2.0 * std::f32::consts::PI
+                            * DEFAULT_TONE_FREQUENCY / 44100.0).ceil() as i32;
+
+        assert!(max_step(&stream) <= max_slope + 1,
+                "Audio stream jumps {} between samples, max expected {}",
+                max_step(&stream), max_slope);
+    }
+
+    #[test]
+    fn tone_control()
+    {
+        let control      = ToneControl::new(Tone::default());
+        let mut callback = SpeakersCallback::new(control.clone(), 44100);
+        let mut buffer   = [0; 1000];
+
+        control.set_playing(true);
+        callback.callback(&mut buffer);
+        assert!(buffer.iter().any(|&x| x != 0), "Default tone is silent");
+
+        // Changes are picked up by the callback
+        control.set_tone(Tone { waveform: Waveform::Square, frequency: 441.0, volume: 1.0 });
+        callback.callback(&mut buffer);
+
+        assert!(buffer.iter().all(|&x| x.abs() == i16::MAX),
+                "Square wave at full volume expected");
+
+        control.set_volume(0.0);
+        callback.callback(&mut buffer);
+        assert!(buffer.iter().all(|&x| x == 0), "Zero volume is not silent");
+
+        // Muting fades the output out without losing the tone
+        control.set_volume(0.5);
+        assert!(control.toggle_mute());
+
+        callback.callback(&mut buffer);
+        assert!(buffer[500..].iter().all(|&x| x == 0), "Muted speakers are not silent");
+        assert_eq!(control.tone().volume, 0.5);
+
+        assert!(!control.toggle_mute());
+        callback.callback(&mut buffer);
+        assert!(buffer.iter().any(|&x| x != 0), "Unmuted speakers are silent");
+    }
+
+    #[test]
+    fn noise_generation()
+    {
+        let samples = oscillator_samples(Waveform::Noise, 441.0, 1000.0, 1000);
+
+        assert!(samples.iter().all(|&x| x.abs() <= 1000), "Noise exceeds the volume");
+
+        // Values are held for half a cycle
+        for chunk in samples.chunks(50)
+        {
+            assert!(chunk.iter().all(|&x| x == chunk[0]), "Noise value not held");
+        }
+    }
+
     #[test]
     fn speakers_playback() -> Result<(), String>
     {
@@ -153,6 +577,9 @@ mod tests
         let speakers = Speakers::new(&context)?;
         assert!(!speakers.is_playing());
 
+        // Stopping only silences the output, the device keeps running
+        assert!(speakers.is_device_running());
+
         speakers.start();
         assert!(speakers.is_playing());
 
@@ -166,6 +593,16 @@ mod tests
         speakers.stop();
         assert!(!speakers.is_playing());
 
+        // Sound timer driven playback
+        speakers.update(&TimerStatus::Started);
+        assert!(speakers.is_playing());
+
+        speakers.update(&TimerStatus::Running);
+        assert!(speakers.is_playing());
+
+        speakers.update(&TimerStatus::End);
+        assert!(!speakers.is_playing());
+
         Ok(())
     }
 }
@@ -0,0 +1,191 @@
+use std::path::Path;
+
+use sdl2::audio::AudioCallback;
+
+use crate::audio::{ wav, AudioSink, SpeakersCallback, Tone, ToneControl };
+use crate::clock::Frequency;
+
+// Renders the buzzer output into memory instead of an audio device.
+// Time only moves forward when samples are rendered, so the output is
+// sample accurate and does not depend on the host audio hardware.
+pub struct OfflineAudio
+{
+    control     : ToneControl,
+    callback    : SpeakersCallback,
+    sample_rate : u32,
+    samples     : Vec<i16>,
+    // Fraction of a sample left over by the previous frame
+    remainder   : f64,
+}
+
+// Public impl
+impl OfflineAudio
+{
+    pub fn new(sample_rate: u32) -> Self
+    {
+        Self::with_tone(sample_rate, Tone::default())
+    }
+
+    pub fn with_tone(sample_rate: u32, tone: Tone) -> Self
+    {
+        Self::with_control(sample_rate, ToneControl::new(tone))
+    }
+
+    // Renders the buzzer of another sink, e.g. to record the speakers.
+    // Tone, mute and playing changes made through the control are heard.
+    pub fn with_control(sample_rate: u32, control: ToneControl) -> Self
+    {
+        let callback = SpeakersCallback::new(control.clone(), sample_rate as u16);
+
+        OfflineAudio { control, callback, sample_rate,
+                       samples   : Vec::new(),
+                       remainder : 0.0,
+                     }
+    }
+
+    pub fn render(&mut self, num_samples: usize)
+    {
+        let start = self.samples.len();
+
+        self.samples.resize(start + num_samples, 0);
+        self.callback.callback(&mut self.samples[start..]);
+    }
+
+    // Renders one frame worth of samples. Frames that don't fit an
+    // integer number of samples carry the fraction over to the next one.
+    pub fn render_frame(&mut self, frame_rate: Frequency)
+    {
+        let exact_samples = self.sample_rate as f64 / frame_rate.value() + self.remainder;
+
+        // Rounding errors must not drop a sample when fractions add up
+        let num_samples = (exact_samples + 1e-6).floor();
+
+        self.remainder = (exact_samples - num_samples).max(0.0);
+        self.render(num_samples as usize);
+    }
+
+    pub fn sample_rate(&self) -> u32
+    {
+        self.sample_rate
+    }
+
+    pub fn samples(&self) -> &[i16]
+    {
+        &self.samples
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.samples.clear();
+    }
+
+    pub fn encode_wav(&self) -> Vec<u8>
+    {
+        wav::encode(self.sample_rate, &self.samples)
+    }
+
+    pub fn write_wav<P: AsRef<Path>>(&self, path: P) -> Result<(), String>
+    {
+        wav::write(path, self.sample_rate, &self.samples)
+    }
+}
+
+impl AudioSink for OfflineAudio
+{
+    fn control(&self) -> ToneControl
+    {
+        self.control.clone()
+    }
+
+    fn end_frame(&mut self, frame_rate: Frequency)
+    {
+        self.render_frame(frame_rate);
+    }
+
+    fn offline(&self) -> Option<&OfflineAudio>
+    {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::audio::Waveform;
+    use crate::clock::DEFAULT_TIMERS_FREQUENCY;
+    use crate::timer::Timer;
+
+    #[test]
+    fn frame_rendering()
+    {
+        let mut audio = OfflineAudio::new(44100);
+
+        for _ in 0..60
+        {
+            audio.render_frame(DEFAULT_TIMERS_FREQUENCY);
+        }
+
+        assert_eq!(audio.samples().len(), 44100, "One second of audio expected");
+        assert!(audio.samples().iter().all(|&x| x == 0), "Stopped buzzer is not silent");
+
+        // Frames not aligned to samples do not drift
+        let mut audio = OfflineAudio::new(48000);
+
+        for _ in 0..7
+        {
+            audio.render_frame(Frequency::new(7.0));
+        }
+
+        assert_eq!(audio.samples().len(), 48000, "Rendered samples drifted");
+    }
+
+    #[test]
+    fn sound_timer_rendering()
+    {
+        let tone      = Tone { waveform: Waveform::Square, frequency: 441.0, volume: 1.0 };
+        let mut audio = OfflineAudio::with_tone(44100, tone);
+        let mut timer = Timer::new();
+
+        // Silence, then the buzzer plays for 3 frames of 735 samples
+        audio.render_frame(DEFAULT_TIMERS_FREQUENCY);
+        audio.update(&timer.set_value(3));
+
+        for _ in 0..5
+        {
+            audio.render_frame(DEFAULT_TIMERS_FREQUENCY);
+            audio.update(&timer.tick());
+        }
+
+        let samples = audio.samples();
+        let sounding: Vec<usize> = samples.iter().enumerate()
+                                          .filter(|(_, &x)| x != 0)
+                                          .map(|(index, _)| index)
+                                          .collect();
+
+        // Sound starts exactly on the frame the timer was set and
+        // fades out in less than a frame after the timer ended
+        assert_eq!(sounding.first(), Some(&735));
+        assert!(*sounding.last().unwrap() >= 4 * 735, "Buzzer stopped too early");
+        assert!(*sounding.last().unwrap() <  5 * 735, "Buzzer stopped too late");
+        assert!(samples[735 + 500..4 * 735].iter().all(|&x| x.abs() == i16::MAX),
+                "Buzzer not at full volume while playing");
+    }
+
+    #[test]
+    fn wav_output()
+    {
+        let mut audio = OfflineAudio::new(8000);
+
+        audio.start();
+        audio.render(100);
+
+        let bytes = audio.encode_wav();
+
+        assert_eq!(bytes.len(), 44 + 100 * 2);
+        assert_eq!(bytes, wav::encode(8000, audio.samples()));
+
+        audio.clear();
+        assert!(audio.samples().is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::Path;
+
+const HEADER_SIZE     : usize = 44;
+const BITS_PER_SAMPLE : u16   = 16;
+const NUM_CHANNELS    : u16   = 1;
+
+// Encodes mono 16 bits PCM samples as a canonical RIFF/WAVE file
+pub fn encode(sample_rate: u32, samples: &[i16]) -> Vec<u8>
+{
+    let block_align = NUM_CHANNELS * BITS_PER_SAMPLE / 8;
+    let byte_rate   = sample_rate * block_align as u32;
+    let data_size   = (samples.len() * block_align as usize) as u32;
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + data_size as usize);
+
+    // RIFF chunk
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+
+    // Format chunk
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&NUM_CHANNELS.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&byte_rate.to_le_bytes());
+    bytes.extend_from_slice(&block_align.to_le_bytes());
+    bytes.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+
+    // Data chunk
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_size.to_le_bytes());
+
+    for sample in samples
+    {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    return bytes;
+}
+
+pub fn write<P: AsRef<Path>>(path: P, sample_rate: u32, samples: &[i16]) -> Result<(), String>
+{
+    match fs::write(path, encode(sample_rate, samples))
+    {
+        Ok(_)  => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn wav_encoding()
+    {
+        let samples = [0, 1, -1, i16::MAX, i16::MIN];
+        let bytes   = encode(44100, &samples);
+
+        assert_eq!(bytes.len(), HEADER_SIZE + samples.len() * 2,
+                   "Unexpected wav file size");
+
+        assert_eq!(&bytes[0..4],   b"RIFF", "Missing RIFF tag");
+        assert_eq!(&bytes[8..12],  b"WAVE", "Missing WAVE tag");
+        assert_eq!(&bytes[12..16], b"fmt ", "Missing format chunk");
+        assert_eq!(&bytes[36..40], b"data", "Missing data chunk");
+
+        let riff_size   = u32::from_le_bytes([bytes[4],  bytes[5],  bytes[6],  bytes[7]]);
+        let sample_rate = u32::from_le_bytes([bytes[24], bytes[25], bytes[26], bytes[27]]);
+        let data_size   = u32::from_le_bytes([bytes[40], bytes[41], bytes[42], bytes[43]]);
+
+        assert_eq!(riff_size as usize, bytes.len() - 8, "Wrong RIFF chunk size");
+        assert_eq!(sample_rate, 44100, "Wrong sample rate");
+        assert_eq!(data_size as usize, samples.len() * 2, "Wrong data chunk size");
+
+        // Samples are stored as little endian right after the header
+        assert_eq!(&bytes[HEADER_SIZE..HEADER_SIZE + 4], &[0x00, 0x00, 0x01, 0x00]);
+        assert_eq!(&bytes[bytes.len() - 2..], &[0x00, 0x80]);
+    }
+}
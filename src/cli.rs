@@ -0,0 +1,405 @@
+use std::path::PathBuf;
+
+use chust8::clock::FrameSync;
+use chust8::config::Config;
+use chust8::debugger::Watchpoint;
+use chust8::display::{ Palette, MAX_SCALE };
+use chust8::font::{ FontSet, FONT_NAMES };
+use chust8::input::parse_keymap;
+use chust8::loader::{ ReloadMode, STDIN_PATH };
+use chust8::platform::{ Platform, PLATFORM_NAMES };
+use chust8::quirks::{ Quirks, QUIRK_NAMES };
+use chust8::romdb::SettingsDatabase;
+use chust8::timing::TimingModel;
+use chust8::trace::TraceFilter;
+
+// Frames run by the headless command when not given, 10 seconds
+const DEFAULT_HEADLESS_FRAMES : u32 = 600;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command
+{
+    // Runs the rom in a window
+    Run,
+    // Runs the rom without window and prints the final screen
+    Headless,
+    // Runs the rom in a window, paused, tracing every instruction
+    Debug,
+    Disasm,
+    Info,
+    // Adds the roms of a community database programs.json to the settings
+    ImportDb,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options
+{
+    pub command       : Command,
+    pub rom           : String,
+    // Rom picked from a zip archive
+    pub entry         : Option<String>,
+    // Reloads the rom when its file changes
+    pub watch         : Option<ReloadMode>,
+    pub config        : Config,
+    pub frames        : u32,
+    pub trace         : Option<PathBuf>,
+    pub filter        : TraceFilter,
+    pub profile       : Option<PathBuf>,
+    // Gif, or directory of png frames and audio, recorded from the start
+    pub record        : Option<PathBuf>,
+    // Sound rendered by the headless command
+    pub wav           : Option<PathBuf>,
+    // Settings database, the default one when missing
+    pub database      : Option<PathBuf>,
+    pub use_database  : bool,
+    // Stores the settings in the database for the rom
+    pub save_settings : bool,
+    // Addresses and conditions that pause the rom
+    pub breakpoints   : Vec<usize>,
+    pub watchpoints   : Vec<Watchpoint>,
+}
+
+pub fn usage(program: &str) -> String
+{
+    format!("\
+Usage: {} [COMMAND] [OPTIONS] ROM
+       {} import-db PROGRAMS_JSON
+
+ROM is a rom file, an assembler source (.asm), a zip archive with roms, or -
+to read it from stdin.
+
+Commands:
+  run         Runs the rom in a window (default)
+  headless    Runs the rom without window and prints the final screen
+  debug       Runs the rom paused in the debugger window, tracing to stderr
+  disasm      Prints the disassembly of the rom
+  info        Prints the size, hash, likely platform and references of the rom
+  import-db   Imports rom settings from the community CHIP-8 database
+
+Options:
+  --platform NAME     Platform the rom was written for, detected when missing:
+                      {}
+  --quirks LIST       Quirks to enable, or disable with a leading '-', on top of
+                      the platform ones: {}, none
+  --font NAME         Font used by FX29 and FX30, the platform one by default:
+                      {}
+  --font-address N    Address of the font, which must end before the program
+  --ipf N             Instructions run per frame
+  --timing MODEL      How long instructions take: uniform, the same each, or vip,
+                      as long as on a COSMAC VIP with sprites waiting for vblank
+  --scale N           Window pixels per screen pixel, from 1 to {}
+  --sync MODE         How frames are paced: sleep, on a timer, or vsync, with the
+                      display refresh, which runs too fast on monitors over 60 Hz
+  --palette COLORS    Lit and unlit colors as RRGGBB,RRGGBB, or green, white, amber
+  --seed N            Seed for the random number generator
+  --keymap KEYS       default, cosmac, or the 16 keys bound to keypad keys 0 to F
+  --mute              Starts with the sound muted
+  --frames N          Frames run by the headless command, {} by default
+  --wav FILE          Writes the sound of the headless command to a wav file
+  --trace FILE        Logs every executed instruction to the file
+  --trace-pc RANGE    Only traces addresses in the range, e.g. 200-2FF
+  --trace-ops LIST    Only traces the given instructions, e.g. DXYN,FX0A
+  --profile FILE      Writes an execution profile to the file
+  --record FILE       Records the screen and the sound from the start to a .gif
+                      file, or else to a directory of png frames and an
+                      audio.wav. F8 starts and stops recordings while running
+  --break SPEC        Pauses at an address, when a condition becomes true, or at
+                      every matching instruction or write, e.g. 0x234,
+                      'V3 == 0x10', 'I in [0x300, 0x320)',
+                      'write in [0x300, 0x320)' or 'op == DXYN'. Repeatable
+  --entry NAME        Rom to run from a zip archive with several
+  --watch MODE        Reloads the rom when its file changes, restarting it with
+                      reset, or keeping the machine state when possible with keep
+  --db FILE           Rom settings database, by default {}
+  --no-db             Ignores the settings stored for the rom
+  --save-settings     Stores the given settings for the rom
+  -h, --help          Prints this help
+",
+    program, program, PLATFORM_NAMES.join(", "), QUIRK_NAMES.join(", "), FONT_NAMES.join(", "), MAX_SCALE,
+    DEFAULT_HEADLESS_FRAMES,
+    SettingsDatabase::default_path().map(|path| path.display().to_string())
+                                    .unwrap_or(String::from("none")))
+}
+
+// Parses the arguments, without the program name
+pub fn parse(args: &[String]) -> Result<Options, String>
+{
+    parse_onto(args, Config::default())
+}
+
+// Parses the arguments, changing the settings of the given config
+pub fn parse_onto(args: &[String], base: Config) -> Result<Options, String>
+{
+    let mut command       = None;
+    let mut rom           = None;
+    let mut entry         = None;
+    let mut watch         = None;
+    let mut quirks        = None;
+    let mut config        = base;
+    let mut frames        = DEFAULT_HEADLESS_FRAMES;
+    let mut trace         = None;
+    let mut filter        = TraceFilter::default();
+    let mut profile       = None;
+    let mut record        = None;
+    let mut wav           = None;
+    let mut database      = None;
+    let mut use_database  = true;
+    let mut save_settings = false;
+    let mut breakpoints   = Vec::new();
+    let mut watchpoints   = Vec::new();
+    let mut args          = args.iter();
+
+    while let Some(arg) = args.next()
+    {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+
+        match arg.as_str()
+        {
+            "-h" | "--help" => command = Some(Command::Help),
+
+            "--platform"  => config.platform  = Some(Platform::parse(value()?)?),
+            "--quirks"    => quirks           = Some(value()?.clone()),
+            "--font"      => config.font      = Some(FontSet::parse(value()?)?),
+            "--font-address" => config.font_address = Some(parse_number(arg, value()?)?),
+            "--ipf"       => config.instructions_per_frame = parse_number(arg, value()?)?,
+            "--timing"    => config.timing    = TimingModel::parse(value()?)?,
+            "--scale"     => config.scale     = parse_number(arg, value()?)?,
+            "--sync"      => config.frame_sync = FrameSync::parse(value()?)?,
+            "--palette"   => config.palette   = Some(Palette::parse(value()?)?),
+            "--seed"      => config.seed      = Some(parse_number(arg, value()?)?),
+            "--keymap"    => config.keymap    = parse_keymap(value()?)?,
+            "--mute"      => config.mute      = true,
+            "--frames"    => frames           = parse_number(arg, value()?)?,
+            "--trace"     => trace            = Some(PathBuf::from(value()?)),
+            "--trace-pc"  => filter.pc_range  = Some(TraceFilter::parse_pc_range(value()?)?),
+            "--trace-ops" => filter.opcodes   = TraceFilter::parse_opcodes(value()?),
+            "--profile"   => profile          = Some(PathBuf::from(value()?)),
+            "--record"    => record           = Some(PathBuf::from(value()?)),
+            "--wav"       => wav              = Some(PathBuf::from(value()?)),
+            "--entry"     => entry            = Some(value()?.clone()),
+            "--watch"     => watch            = Some(ReloadMode::parse(value()?)?),
+            "--db"        => database         = Some(PathBuf::from(value()?)),
+            "--no-db"     => use_database     = false,
+            "--save-settings" => save_settings    = true,
+
+            // An address, or else a condition
+            "--break"     =>
+            {
+                let spec = value()?;
+
+                match parse_number(arg, spec)
+                {
+                    Ok(address) => breakpoints.push(address),
+                    Err(_)      => watchpoints.push(Watchpoint::new(spec)?),
+                }
+            },
+
+            option if option.starts_with("--") => return Err(format!("Unknown option {}", option)),
+
+            name if command.is_none() && rom.is_none() && parse_command(name).is_some() =>
+                command = parse_command(name),
+
+            path if rom.is_none() => rom = Some(path.to_string()),
+
+            extra => return Err(format!("Unexpected argument {}", extra)),
+        }
+    }
+
+    let command = command.unwrap_or(Command::Run);
+
+    // The quirks apply on top of the platform ones, whatever the order
+    if let Some(spec) = quirks
+    {
+        config.quirks = Some(Quirks::parse(&spec, config.effective_quirks())?);
+    }
+
+    config.validate()?;
+
+    if frames == 0
+    {
+        return Err(String::from("Frames must be greater than 0"));
+    }
+
+    let rom = match (rom, command)
+    {
+        (Some(rom), _)          => rom,
+        (None, Command::Help)   => String::new(),
+        (None, _)               => return Err(String::from("Missing rom file")),
+    };
+
+    if wav.is_some() && command != Command::Headless
+    {
+        return Err(String::from("--wav is only used by the headless command"));
+    }
+
+    if watch.is_some() && rom == STDIN_PATH
+    {
+        return Err(String::from("The standard input cannot be watched"));
+    }
+
+    Ok(Options { command, rom, entry, watch, config, frames, trace, filter, profile, record, wav,
+                 database, use_database, save_settings, breakpoints, watchpoints })
+}
+
+fn parse_command(name: &str) -> Option<Command>
+{
+    match name
+    {
+        "run"       => Some(Command::Run),
+        "headless"  => Some(Command::Headless),
+        "debug"     => Some(Command::Debug),
+        "disasm"    => Some(Command::Disasm),
+        "info"      => Some(Command::Info),
+        "import-db" => Some(Command::ImportDb),
+        "help"      => Some(Command::Help),
+        _           => None,
+    }
+}
+
+// Decimal, or hexadecimal with a 0x prefix
+fn parse_number<T: std::str::FromStr + num_traits::Num>(option: &str, text: &str) -> Result<T, String>
+{
+    let parsed = match text.strip_prefix("0x")
+    {
+        Some(hex) => T::from_str_radix(hex, 16).ok(),
+        None      => text.parse().ok(),
+    };
+
+    parsed.ok_or(format!("Invalid number '{}' for {}", text, option))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use chust8::font::FontConfig;
+
+    fn parse_line(line: &str) -> Result<Options, String>
+    {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse(&args)
+    }
+
+    #[test]
+    fn commands() -> Result<(), String>
+    {
+        let options = parse_line("game.ch8")?;
+        assert_eq!(options.command, Command::Run);
+        assert_eq!(options.rom, "game.ch8");
+        assert_eq!(options.config, Config::default());
+
+        assert_eq!(parse_line("disasm game.ch8")?.command, Command::Disasm);
+        assert_eq!(parse_line("--help")?.command, Command::Help);
+
+        // A rom can be named like a command when it's the second argument
+        assert_eq!(parse_line("info run")?.rom, "run");
+
+        let options = parse_line("headless - --entry pong.ch8")?;
+        assert_eq!(options.rom, "-");
+        assert_eq!(options.entry.as_deref(), Some("pong.ch8"));
+
+        assert_eq!(parse_line("game.asm --watch keep")?.watch, Some(ReloadMode::KeepState));
+        assert_eq!(parse_line("game.ch8 --record run.gif")?.record, Some(PathBuf::from("run.gif")));
+        assert_eq!(parse_line("headless game.ch8 --wav beep.wav")?.wav, Some(PathBuf::from("beep.wav")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn settings() -> Result<(), String>
+    {
+        let options = parse_line("headless --quirks -vf-reset,wrap --platform chip8 --ipf 15 \
+                                  --scale 10 --palette white --seed 0x2A --mute --frames 60 rom.ch8")?;
+
+        let config = options.config;
+
+        assert_eq!(options.command, Command::Headless);
+        assert_eq!(options.frames, 60);
+        assert_eq!(options.wav, None);
+        assert_eq!(config.platform, Some(Platform::CosmacVip));
+        assert_eq!(config.effective_quirks().enabled(), vec!["shift-vy", "memory-increment", "wrap"]);
+        assert_eq!(config.instructions_per_frame, 15);
+        assert_eq!(config.scale, 10);
+        assert_eq!(config.palette, Some(Palette::parse("white")?));
+        assert_eq!(config.seed, Some(42));
+        assert!(config.mute);
+
+        assert_eq!(config.timing, TimingModel::Uniform);
+        assert_eq!(parse_line("--timing vip rom.ch8")?.config.timing, TimingModel::CosmacVip);
+
+        assert_eq!(config.frame_sync, FrameSync::Sleep);
+        assert_eq!(parse_line("--sync vsync rom.ch8")?.config.frame_sync, FrameSync::Vsync);
+
+        let config = parse_line("--font schip --font-address 0x50 rom.ch8")?.config;
+        assert_eq!(config.font_config(), Some(FontConfig::new(FontSet::Schip, 0x50)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn settings_database() -> Result<(), String>
+    {
+        let options = parse_line("rom.ch8")?;
+        assert!(options.use_database);
+        assert!(!options.save_settings);
+
+        let options = parse_line("--no-db --save-settings --db roms.json rom.ch8")?;
+        assert!(!options.use_database);
+        assert!(options.save_settings);
+        assert_eq!(options.database, Some(PathBuf::from("roms.json")));
+
+        // Given settings change the stored ones
+        let stored  = Config { instructions_per_frame: 30, seed: Some(1), ..Config::default() };
+        let args    = vec![String::from("--ipf"), String::from("12"), String::from("rom.ch8")];
+        let options = parse_onto(&args, stored)?;
+
+        assert_eq!(options.config.instructions_per_frame, 12);
+        assert_eq!(options.config.seed, Some(1));
+
+        assert_eq!(parse_line("import-db programs.json")?.command, Command::ImportDb);
+
+        Ok(())
+    }
+
+    #[test]
+    fn breakpoints() -> Result<(), String>
+    {
+        let args: Vec<String> = [ "debug", "--break", "0x234", "--break", "write in [0x300, 0x320)",
+                                  "--break", "516", "rom.ch8" ].iter().map(|arg| arg.to_string()).collect();
+
+        let options = parse(&args)?;
+
+        assert_eq!(options.breakpoints, vec![0x234, 516]);
+        assert_eq!(options.watchpoints, vec![Watchpoint::new("write in [0x300, 0x320)")?]);
+
+        let args: Vec<String> = [ "--break", "V3 =", "rom.ch8" ].iter().map(|arg| arg.to_string()).collect();
+        assert!(parse(&args).is_err(), "Invalid condition accepted");
+
+        Ok(())
+    }
+
+    #[test]
+    fn validation_errors()
+    {
+        let error = | line | parse_line(line).unwrap_err();
+
+        assert_eq!(error(""), "Missing rom file");
+        assert_eq!(error("rom.ch8 --ipf"), "Missing value for --ipf");
+        assert_eq!(error("rom.ch8 --ipf fast"), "Invalid number 'fast' for --ipf");
+        assert_eq!(error("rom.ch8 --fast"), "Unknown option --fast");
+        assert_eq!(error("rom.ch8 other.ch8"), "Unexpected argument other.ch8");
+
+        assert!(parse_line("rom.ch8 --ipf 0").is_err(), "Zero instructions accepted");
+        assert!(parse_line("rom.ch8 --scale 1000").is_err(), "Huge scale accepted");
+        assert!(parse_line("rom.ch8 --platform nes").is_err(), "Unknown platform accepted");
+        assert!(parse_line("rom.ch8 --quirks fast").is_err(), "Unknown quirk accepted");
+        assert!(parse_line("rom.ch8 --frames 0").is_err(), "Zero frames accepted");
+        assert!(parse_line("rom.ch8 --sync never").is_err(), "Unknown frame sync accepted");
+        assert!(parse_line("rom.ch8 --timing fast").is_err(), "Unknown timing model accepted");
+        assert!(parse_line("rom.ch8 --font arial").is_err(), "Unknown font accepted");
+        assert!(parse_line("rom.ch8 --font-address 0x1F0").is_err(), "Font over the program accepted");
+        assert!(parse_line("- --watch reset").is_err(), "Watching stdin accepted");
+        assert!(parse_line("rom.ch8 --wav beep.wav").is_err(), "Wav output of a window accepted");
+    }
+}
@@ -1,4 +1,5 @@
 
+use std::thread;
 use std::time::{ Duration, Instant };
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -11,9 +12,13 @@ impl Frequency
         Frequency { hertz }
     }
 
+    // Rounded to the closest nanosecond. Zero or negative frequencies
+    // have no period.
     pub fn period(&self) -> Duration
     {
-        Duration::from_millis((1000.0 / self.hertz) as u64)
+        if self.hertz <= 0.0 { return Duration::from_nanos(0); }
+
+        Duration::from_nanos((NANOS_PER_SECOND / self.hertz).round() as u64)
     }
 
     pub fn value(&self) -> f64
@@ -27,40 +32,314 @@ impl Frequency
     }
 }
 
+const NANOS_PER_SECOND : f64 = 1_000_000_000.0;
+
 pub const DEFAULT_CPU_FREQUENCY    : Frequency = Frequency { hertz: 500.0 };
 pub const DEFAULT_TIMERS_FREQUENCY : Frequency = Frequency { hertz: 60.0 };
 
-pub struct RateLimiter
+// Instructions run in each 60 Hz frame, close to DEFAULT_CPU_FREQUENCY
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME : u32 = 8;
+
+// Frames emulated at once to catch up after a stall. Longer stalls
+// are dropped instead of running the rom in fast forward.
+pub const DEFAULT_MAX_CATCH_UP_FRAMES : u32 = 4;
+
+// Sleeping overshoots by up to a few hundred microseconds on most
+// systems. The end of the wait yields instead, so frames start on time.
+const SPIN_THRESHOLD : Duration = Duration::from_millis(1);
+
+// Speed multipliers available at runtime, slowest first
+pub const SPEED_STEPS : [f64; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const NORMAL_SPEED_STEP : usize = 2;
+
+// How the end of a frame is waited for
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FrameSync
+{
+    // The scheduler sleeps until the next frame is due
+    #[default]
+    Sleep,
+    // Presenting the frame blocks until the display refresh, so the
+    // scheduler never sleeps and only counts the frames that are due
+    Vsync,
+}
+
+// Names accepted by FrameSync::parse()
+pub const FRAME_SYNC_NAMES : [&str; 2] = [ "sleep", "vsync" ];
+
+impl FrameSync
+{
+    pub fn parse(name: &str) -> Result<Self, String>
+    {
+        match name.to_lowercase().as_str()
+        {
+            "sleep" => Ok(FrameSync::Sleep),
+            "vsync" => Ok(FrameSync::Vsync),
+            _       => Err(format!("Unknown frame sync '{}'. Valid modes are {}",
+                                   name, FRAME_SYNC_NAMES.join(", "))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            FrameSync::Sleep => "sleep",
+            FrameSync::Vsync => "vsync",
+        }
+    }
+}
+
+// Runs the emulation in frames: a fixed amount of instructions followed
+// by a timers tick, at the timers frequency.
+pub struct FrameScheduler
 {
-    max_rate      : Frequency,
-    last_executed : Option<Instant>,
+    frame_rate             : Frequency,
+    instructions_per_frame : u32,
+    sync                   : FrameSync,
+    max_catch_up           : u32,
+    next_frame             : Option<Instant>,
+    dropped_frames         : u64,
+    speed_step             : usize,
+    paused                 : bool,
+    turbo                  : bool,
 }
 
-impl RateLimiter
+// Public impl
+impl FrameScheduler
 {
-    pub fn new(max_rate: Frequency) -> Self
+    pub fn new(instructions_per_frame: u32) -> Result<Self, String>
+    {
+        let mut scheduler = FrameScheduler
+        {
+            frame_rate             : DEFAULT_TIMERS_FREQUENCY,
+            instructions_per_frame : DEFAULT_INSTRUCTIONS_PER_FRAME,
+            sync                   : FrameSync::default(),
+            max_catch_up           : DEFAULT_MAX_CATCH_UP_FRAMES,
+            next_frame             : None,
+            dropped_frames         : 0,
+            speed_step             : NORMAL_SPEED_STEP,
+            paused                 : false,
+            turbo                  : false,
+        };
+
+        scheduler.set_instructions_per_frame(instructions_per_frame)?;
+
+        Ok(scheduler)
+    }
+
+    pub fn instructions_per_frame(&self) -> u32
+    {
+        self.instructions_per_frame
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) -> Result<(), String>
+    {
+        if instructions_per_frame == 0
+        {
+            return Err(String::from("At least one instruction per frame is required"));
+        }
+
+        self.instructions_per_frame = instructions_per_frame;
+        Ok(())
+    }
+
+    pub fn frame_rate(&self) -> Frequency
+    {
+        self.frame_rate
+    }
+
+    pub fn set_frame_rate(&mut self, frame_rate: Frequency) -> Result<(), String>
+    {
+        if frame_rate.value() <= 0.0
+        {
+            return Err(format!("Invalid frame rate {} Hz", frame_rate.value()));
+        }
+
+        self.frame_rate = frame_rate;
+        Ok(())
+    }
+
+    pub fn sync(&self) -> FrameSync
+    {
+        self.sync
+    }
+
+    pub fn set_sync(&mut self, sync: FrameSync)
+    {
+        self.sync = sync;
+    }
+
+    pub fn set_max_catch_up(&mut self, max_catch_up: u32)
+    {
+        self.max_catch_up = max_catch_up.max(1);
+    }
+
+    // Frames skipped after stalls longer than the catch up limit
+    pub fn dropped_frames(&self) -> u64
+    {
+        self.dropped_frames
+    }
+
+    // Multiplier applied to both the instructions and the timers rate
+    pub fn speed(&self) -> f64
+    {
+        SPEED_STEPS[self.speed_step]
+    }
+
+    // Moves one step in SPEED_STEPS, returning the new speed
+    pub fn speed_up(&mut self) -> f64
+    {
+        self.set_speed_step((self.speed_step + 1).min(SPEED_STEPS.len() - 1))
+    }
+
+    pub fn slow_down(&mut self) -> f64
+    {
+        self.set_speed_step(self.speed_step.saturating_sub(1))
+    }
+
+    pub fn reset_speed(&mut self)
+    {
+        self.set_speed_step(NORMAL_SPEED_STEP);
+    }
+
+    pub fn is_paused(&self) -> bool
+    {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool)
+    {
+        self.paused = paused;
+        self.restart();
+    }
+
+    // While turbo is on frames run back to back, as fast as possible
+    pub fn is_turbo(&self) -> bool
+    {
+        self.turbo
+    }
+
+    pub fn set_turbo(&mut self, turbo: bool)
+    {
+        if self.turbo != turbo
+        {
+            self.turbo = turbo;
+            self.restart();
+        }
+    }
+
+    // Current speed, e.g. to be shown to the user. None at normal speed.
+    pub fn speed_label(&self) -> Option<String>
+    {
+        if self.paused { return Some(String::from("Paused")); }
+        if self.turbo  { return Some(String::from("Turbo"));  }
+
+        match self.speed_step
+        {
+            NORMAL_SPEED_STEP => None,
+            _                 => Some(format!("{}x", self.speed())),
+        }
+    }
+
+    // Frame rate once the speed multiplier is applied
+    pub fn effective_frame_rate(&self) -> Frequency
+    {
+        let mut rate = self.frame_rate;
+        rate.set_value(self.frame_rate.value() * self.speed());
+
+        return rate;
+    }
+
+    // Forgets the frame deadline, e.g. after the emulation was paused.
+    // The next frame is due immediately.
+    pub fn restart(&mut self)
+    {
+        self.next_frame = None;
+    }
+
+    // Waits for the next frame and returns how many frames have to be
+    // emulated before presenting. Zero means the display refreshed
+    // before a new frame was due. While paused the wait lasts a frame at
+    // normal speed, so events keep being handled, and no frame is due.
+    // In turbo mode there is no wait, the caller decides how many frames
+    // to run.
+    pub fn wait_frames(&mut self) -> u32
+    {
+        if self.paused
+        {
+            thread::sleep(self.frame_rate.period());
+            return 0;
+        }
+
+        if self.turbo { return 0; }
+
+        if let (FrameSync::Sleep, Some(deadline)) = (self.sync, self.next_frame)
+        {
+            sleep_until(deadline);
+        }
+
+        self.frames_due(Instant::now())
+    }
+
+    // Number of frames whose deadline has passed at the given time. The
+    // deadlines are kept on a fixed grid so the frame rate never drifts.
+    pub fn frames_due(&mut self, now: Instant) -> u32
     {
-        RateLimiter { max_rate, last_executed: None }
+        let period   = self.effective_frame_rate().period();
+        let deadline = *self.next_frame.get_or_insert(now);
+
+        if now < deadline { return 0; }
+
+        let late       = now.duration_since(deadline).as_nanos();
+        let frames_due = late / period.as_nanos() + 1;
+
+        if frames_due > self.max_catch_up as u128
+        {
+            self.dropped_frames += (frames_due - self.max_catch_up as u128) as u64;
+            self.next_frame      = Some(now + period);
+
+            return self.max_catch_up;
+        }
+
+        self.next_frame = Some(deadline + period * frames_due as u32);
+
+        return frames_due as u32;
     }
+}
 
-    pub fn check(&mut self) -> bool
+// Private impl
+impl FrameScheduler
+{
+    fn set_speed_step(&mut self, speed_step: usize) -> f64
     {
-        if self.last_executed.is_none()
+        if self.speed_step != speed_step
         {
-            self.last_executed = Some(Instant::now());
+            self.speed_step = speed_step;
+            self.restart();
         }
 
-        let now     = Instant::now();
-        let elapsed = now.duration_since(self.last_executed.unwrap());
+        self.speed()
+    }
+}
+
+fn sleep_until(deadline: Instant)
+{
+    loop
+    {
+        let now = Instant::now();
+
+        if now >= deadline { return; }
 
-        if elapsed >= self.max_rate.period()
+        let remaining = deadline - now;
+
+        if remaining > SPIN_THRESHOLD
         {
-            self.last_executed = Some(now);
-            return true;
+            thread::sleep(remaining - SPIN_THRESHOLD);
         }
         else
         {
-            return false;
+            thread::yield_now();
         }
     }
 }
@@ -70,7 +349,6 @@ impl RateLimiter
 mod tests
 {
     use super::*;
-    use std::time::SystemTime;
 
     #[test]
     fn frequency()
@@ -90,8 +368,8 @@ mod tests
         assert_eq!(frequency.value(), 30.0,
                    "set_value() did not update value correctly");
 
-        assert_eq!(frequency.period(), Duration::from_millis(33),
-                   "15 hz does not equal to a period of 33ms");
+        assert_eq!(frequency.period(), Duration::from_nanos(33_333_333),
+                   "30 hz does not equal to a period of 33.333333ms");
 
         // Check chip8 default values
         assert_eq!(DEFAULT_CPU_FREQUENCY.value(), 500.0,
@@ -103,8 +381,8 @@ mod tests
         assert_eq!(DEFAULT_TIMERS_FREQUENCY.value(), 60.0,
                    "Unexpected default timers frequency value");
 
-        assert_eq!(DEFAULT_TIMERS_FREQUENCY.period(), Duration::from_millis(16),
-                   "Default timers period is not equal to 16ms");
+        assert_eq!(DEFAULT_TIMERS_FREQUENCY.period(), Duration::from_nanos(16_666_667),
+                   "Default timers period is not equal to 16.666667ms");
 
         // Check frequency set to zero
         frequency.set_value(0.0);
@@ -115,25 +393,125 @@ mod tests
     }
 
     #[test]
-    fn rate_limiter() -> Result<(), String>
+    fn frames_due() -> Result<(), String>
     {
-        let frequency = Frequency::new(1000.0);
-        let test_time = Duration::from_millis(100);
+        let mut scheduler = FrameScheduler::new(DEFAULT_INSTRUCTIONS_PER_FRAME)?;
+        let period        = scheduler.frame_rate().period();
+        let start         = Instant::now();
+
+        // The first frame is due immediately, the next one a period later
+        assert_eq!(scheduler.frames_due(start), 1);
+        assert_eq!(scheduler.frames_due(start + period / 2), 0);
+        assert_eq!(scheduler.frames_due(start + period), 1);
 
-        let mut counter = 1;
+        // Small stalls are caught up
+        assert_eq!(scheduler.frames_due(start + period * 4), 3);
+        assert_eq!(scheduler.dropped_frames(), 0);
 
-        let mut rate_limiter = RateLimiter::new(frequency);
+        // Long ones drop frames and restart from there
+        let after_stall = start + period * 100;
 
-        let start_time = SystemTime::now();
+        assert_eq!(scheduler.frames_due(after_stall), DEFAULT_MAX_CATCH_UP_FRAMES);
+        assert_eq!(scheduler.dropped_frames(), 96 - DEFAULT_MAX_CATCH_UP_FRAMES as u64);
+        assert_eq!(scheduler.frames_due(after_stall + period / 2), 0);
+        assert_eq!(scheduler.frames_due(after_stall + period), 1);
 
-        while SystemTime::now().duration_since(start_time).unwrap() <= test_time
+        Ok(())
+    }
+
+    #[test]
+    fn frames_do_not_drift() -> Result<(), String>
+    {
+        let mut scheduler = FrameScheduler::new(DEFAULT_INSTRUCTIONS_PER_FRAME)?;
+        let start         = Instant::now();
+        let mut frames    = scheduler.frames_due(start);
+
+        // Polling at an unrelated rate for almost a second. The 61st
+        // frame is due a few nanoseconds after the last poll.
+        for millis in 1..=1000
         {
-            if rate_limiter.check() { counter += 1; }
+            frames += scheduler.frames_due(start + Duration::from_millis(millis));
         }
 
-        let expected_value = test_time.as_millis() / frequency.period().as_millis();
-        assert_eq!(counter, expected_value, "Closure was not executed the expected \
-                   number of times given the frequency and test time");
+        assert_eq!(frames, 60, "Unexpected number of frames in a second");
+
+        Ok(())
+    }
+
+    #[test]
+    fn speed_control() -> Result<(), String>
+    {
+        let mut scheduler = FrameScheduler::new(DEFAULT_INSTRUCTIONS_PER_FRAME)?;
+
+        assert_eq!(scheduler.speed(), 1.0);
+        assert_eq!(scheduler.speed_label(), None);
+
+        assert_eq!(scheduler.slow_down(), 0.5);
+        assert_eq!(scheduler.slow_down(), 0.25);
+        assert_eq!(scheduler.slow_down(), 0.25, "Speed went below the slowest step");
+        assert_eq!(scheduler.speed_label(), Some(String::from("0.25x")));
+
+        scheduler.reset_speed();
+        assert_eq!(scheduler.speed_up(), 2.0);
+        assert_eq!(scheduler.speed_up(), 4.0);
+        assert_eq!(scheduler.speed_up(), 4.0, "Speed went above the fastest step");
+
+        // Frames are due four times as often
+        let start      = Instant::now();
+        let mut frames = scheduler.frames_due(start);
+
+        for millis in 1..=1000
+        {
+            frames += scheduler.frames_due(start + Duration::from_millis(millis));
+        }
+
+        assert_eq!(frames, 240, "Speed did not change the frame rate");
+
+        // Turbo and pause take over the speed label
+        scheduler.set_turbo(true);
+        assert_eq!(scheduler.speed_label(), Some(String::from("Turbo")));
+        assert_eq!(scheduler.wait_frames(), 0);
+
+        scheduler.set_paused(true);
+        assert_eq!(scheduler.speed_label(), Some(String::from("Paused")));
+        assert_eq!(scheduler.wait_frames(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn frame_waiting() -> Result<(), String>
+    {
+        let mut scheduler = FrameScheduler::new(DEFAULT_INSTRUCTIONS_PER_FRAME)?;
+        let period        = scheduler.frame_rate().period();
+        let start         = Instant::now();
+        let mut frames    = 0;
+
+        while frames < 7
+        {
+            frames += scheduler.wait_frames();
+        }
+
+        let elapsed = start.elapsed();
+
+        assert!(elapsed >= period * 6, "Frames were not waited for: {:?}", elapsed);
+        assert!(elapsed <  period * 9, "Frames were waited for too long: {:?}", elapsed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn scheduler_settings() -> Result<(), String>
+    {
+        let mut scheduler = FrameScheduler::new(1000)?;
+
+        assert_eq!(scheduler.instructions_per_frame(), 1000);
+        assert!(scheduler.set_instructions_per_frame(0).is_err());
+        assert!(FrameScheduler::new(0).is_err());
+
+        assert!(scheduler.set_frame_rate(Frequency::new(0.0)).is_err());
+        scheduler.set_frame_rate(Frequency::new(50.0))?;
+        assert_eq!(scheduler.frame_rate().period(), Duration::from_millis(20));
 
         Ok(())
     }
@@ -0,0 +1,159 @@
+use crate::clock::{ FrameSync, DEFAULT_INSTRUCTIONS_PER_FRAME };
+use crate::display::{ Palette, DEFAULT_SCALE, MAX_SCALE };
+use crate::font::{ FontConfig, FontSet };
+use crate::input::{ Keymap, DEFAULT_KEY_MAPPING };
+use crate::memory::MemoryMap;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::timing::TimingModel;
+
+// Settings an interpreter is created with, filled e.g. from the command
+// line. The defaults match Interpreter::new().
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config
+{
+    // Decides the memory map, the font and the default quirks
+    pub platform               : Option<Platform>,
+    // Overrides the quirks of the platform
+    pub quirks                 : Option<Quirks>,
+    // Override the font set of the platform and where it is stored
+    pub font                   : Option<FontSet>,
+    pub font_address           : Option<usize>,
+    pub instructions_per_frame : u32,
+    // Whether instructions take the same time or as long as on a COSMAC VIP
+    pub timing                 : TimingModel,
+    // Window pixels per grid pixel
+    pub scale                  : u32,
+    // Whether frames wait with a sleep or for the display refresh
+    pub frame_sync             : FrameSync,
+    // Plain colors drawn instead of the tileset
+    pub palette                : Option<Palette>,
+    // Seed for the random numbers of CXNN, random when missing
+    pub seed                   : Option<u64>,
+    pub keymap                 : Keymap,
+    pub mute                   : bool,
+}
+
+impl Default for Config
+{
+    fn default() -> Self
+    {
+        Config { platform               : None,
+                 quirks                 : None,
+                 font                   : None,
+                 font_address           : None,
+                 instructions_per_frame : DEFAULT_INSTRUCTIONS_PER_FRAME,
+                 timing                 : TimingModel::default(),
+                 scale                  : DEFAULT_SCALE,
+                 frame_sync             : FrameSync::default(),
+                 palette                : None,
+                 seed                   : None,
+                 keymap                 : DEFAULT_KEY_MAPPING,
+                 mute                   : false,
+               }
+    }
+}
+
+impl Config
+{
+    pub fn for_platform(platform: Platform) -> Self
+    {
+        Config { platform: Some(platform), ..Config::default() }
+    }
+
+    pub fn memory_map(&self) -> MemoryMap
+    {
+        self.platform.map(MemoryMap::for_platform).unwrap_or_default()
+    }
+
+    // Font set or address given explicitly, completed with the platform
+    // font. None when neither was given.
+    pub fn font_config(&self) -> Option<FontConfig>
+    {
+        if self.font.is_none() && self.font_address.is_none()
+        {
+            return None;
+        }
+
+        let platform_font = self.memory_map().font;
+
+        Some(FontConfig::new(self.font.unwrap_or(platform_font.font_set),
+                             self.font_address.unwrap_or(platform_font.address)))
+    }
+
+    // Quirks given explicitly, or else the ones of the platform
+    pub fn effective_quirks(&self) -> Quirks
+    {
+        self.quirks
+            .or(self.platform.map(Quirks::for_platform))
+            .unwrap_or_default()
+    }
+
+    pub fn validate(&self) -> Result<(), String>
+    {
+        if self.instructions_per_frame == 0
+        {
+            return Err(String::from("Instructions per frame must be greater than 0"));
+        }
+
+        if self.scale == 0 || self.scale > MAX_SCALE
+        {
+            return Err(format!("Invalid scale {}. It must be between 1 and {}", self.scale, MAX_SCALE));
+        }
+
+        let map  = self.memory_map();
+        let font = self.font_config().unwrap_or(map.font);
+
+        MemoryMap { font, ..map }.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn quirks_resolution()
+    {
+        assert_eq!(Config::default().effective_quirks(), Quirks::default());
+
+        let vip = Config::for_platform(Platform::CosmacVip);
+        assert_eq!(vip.effective_quirks(), Quirks::for_platform(Platform::CosmacVip));
+        assert_eq!(vip.memory_map(), MemoryMap::for_platform(Platform::CosmacVip));
+
+        let custom = Config { quirks: Some(Quirks::default()), ..vip };
+        assert_eq!(custom.effective_quirks(), Quirks::default());
+    }
+
+    #[test]
+    fn validation()
+    {
+        assert!(Config::default().validate().is_ok());
+
+        let no_instructions = Config { instructions_per_frame: 0, ..Config::default() };
+        assert!(no_instructions.validate().is_err());
+
+        let huge = Config { scale: MAX_SCALE + 1, ..Config::default() };
+        assert!(huge.validate().is_err());
+
+        // The font cannot overlap the program
+        let font_in_program = Config { font_address: Some(0x1F0), ..Config::default() };
+        assert!(font_in_program.validate().is_err());
+    }
+
+    #[test]
+    fn font_resolution()
+    {
+        assert_eq!(Config::default().font_config(), None);
+
+        // The part not given comes from the platform font
+        let eti     = Config::for_platform(Platform::Eti660);
+        let address = Config { font_address: Some(0x300), ..eti.clone() };
+        assert_eq!(address.font_config(), Some(FontConfig::new(FontSet::Eti660, 0x300)));
+        assert!(address.validate().is_ok());
+
+        let font_set = Config { font: Some(FontSet::Schip), ..eti };
+        assert_eq!(font_set.font_config(), Some(FontConfig::new(FontSet::Schip, 0x000)));
+    }
+}
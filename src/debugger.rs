@@ -0,0 +1,313 @@
+use std::collections::BTreeSet;
+
+use sdl2::Sdl;
+use sdl2::rect::Rect;
+
+use crate::display::{ CHAR_ADVANCE, LINE_ADVANCE };
+use crate::memory::INSTRUCTION_SIZE;
+use crate::opcodes::OpCode;
+use crate::registers::NUM_DATA_REGISTERS;
+
+mod window;
+mod watch;
+
+pub use watch::{ Comparison, Condition, Test, Value, WatchContext, Watchpoint };
+use window::DebugWindow;
+
+// Rows of the disassembly and memory panes, and bytes per memory row
+pub const DISASSEMBLY_ROWS : usize = 17;
+pub const MEMORY_ROWS      : usize = 12;
+pub const WATCH_ROWS       : usize = 4;
+pub const BYTES_PER_ROW    : usize = 16;
+
+// Layout of the window, in font pixels. The buttons are on top, the
+// disassembly and registers below them and the memory at the bottom.
+const MARGIN          : usize = 4;
+const BUTTON_WIDTH    : usize = 24;
+const BUTTON_HEIGHT   : usize = 11;
+const PANE_TOP        : usize = MARGIN + BUTTON_HEIGHT + 5;
+const REGISTERS_LEFT  : usize = 148;
+const MEMORY_TOP      : usize = PANE_TOP + DISASSEMBLY_ROWS * LINE_ADVANCE + 6;
+const WINDOW_WIDTH    : usize = MARGIN + (6 + 3 * BYTES_PER_ROW) * CHAR_ADVANCE + MARGIN;
+const WATCH_TOP       : usize = MEMORY_TOP + MEMORY_ROWS * LINE_ADVANCE + 6;
+const WINDOW_HEIGHT   : usize = WATCH_TOP + WATCH_ROWS * LINE_ADVANCE + MARGIN;
+
+const BUTTONS : [(&str, DebugAction); 3] = [ ("STEP",  DebugAction::Step),
+                                             ("RUN",   DebugAction::Run),
+                                             ("PAUSE", DebugAction::Pause) ];
+
+// What the debugger asks the interpreter to do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugAction
+{
+    // Runs a single instruction and pauses
+    Step,
+    Run,
+    Pause,
+    // Adds or removes the breakpoint at an address
+    ToggleBreakpoint(usize),
+}
+
+// Machine state shown by the debugger
+pub struct DebugView<'a>
+{
+    pub pc          : usize,
+    pub registers   : [u8; NUM_DATA_REGISTERS],
+    pub i           : u16,
+    // Return addresses, the top of the stack last
+    pub stack       : &'a [u16],
+    pub delay       : u8,
+    pub sound       : u8,
+    pub memory      : &'a [u8],
+    pub last_write  : Option<usize>,
+    pub breakpoints : &'a BTreeSet<usize>,
+    pub watchpoints : &'a [Watchpoint],
+    pub paused      : bool,
+}
+
+// Second window with the disassembly around the pc, the registers, the
+// stack, the timers and a memory viewer
+pub struct Debugger
+{
+    window       : DebugWindow,
+    // First address shown by the memory viewer
+    memory_start : usize,
+    // Addresses of the disassembly rows as last drawn, for clicks
+    rows         : Vec<usize>,
+}
+
+// Public impl
+impl Debugger
+{
+    // Opens the window to the right of the given one, the game window
+    pub fn new(context: &Sdl, beside: Rect, memory_start: usize) -> Result<Self, String>
+    {
+        let window = DebugWindow::new(context, beside, WINDOW_WIDTH, WINDOW_HEIGHT)?;
+
+        Ok(Debugger { window, memory_start: memory_start - memory_start % BYTES_PER_ROW, rows: Vec::new() })
+    }
+
+    pub fn window_id(&self) -> u32
+    {
+        self.window.id()
+    }
+
+    pub fn draw(&mut self, view: &DebugView) -> Result<(), String>
+    {
+        self.rows = disassembly_addresses(view.pc, DISASSEMBLY_ROWS);
+        self.window.draw(view, &self.rows, self.memory_start)
+    }
+
+    // Action of the button or disassembly row at a window position
+    pub fn action_at(&self, x: i32, y: i32) -> Option<DebugAction>
+    {
+        let (x, y) = self.window.to_font_pixels(x, y)?;
+        action_at(x, y, &self.rows)
+    }
+
+    // Scrolls the memory viewer by whole pages, staying within memory
+    pub fn scroll_memory(&mut self, pages: isize, memory_size: usize)
+    {
+        let page = (MEMORY_ROWS * BYTES_PER_ROW) as isize;
+        let last = memory_size.saturating_sub(1) / BYTES_PER_ROW * BYTES_PER_ROW;
+
+        self.memory_start = (self.memory_start as isize + pages * page).max(0).min(last as isize) as usize;
+    }
+}
+
+// Addresses of the disassembly rows, with the pc on the middle row when
+// it is far enough from the start of memory
+pub fn disassembly_addresses(pc: usize, rows: usize) -> Vec<usize>
+{
+    let first = pc - (pc / INSTRUCTION_SIZE).min(rows / 2) * INSTRUCTION_SIZE;
+
+    (0..rows).map(|row| first + row * INSTRUCTION_SIZE).collect()
+}
+
+// Row of the disassembly: breakpoint and pc markers, address, word and
+// its disassembly
+pub fn disassembly_line(memory: &[u8], address: usize, pc: usize, breakpoints: &BTreeSet<usize>) -> String
+{
+    let breakpoint = if breakpoints.contains(&address) { '*' } else { ' ' };
+    let current    = if address == pc { '>' } else { ' ' };
+
+    match (memory.get(address), memory.get(address + 1))
+    {
+        (Some(&msb), Some(&lsb)) =>
+        {
+            let disassembly = OpCode::new(msb, lsb).map(|opcode| opcode.disassembly())
+                                                   .unwrap_or(String::from("???"));
+
+            format!("{}{} {:04X}  {:02X}{:02X}  {}", breakpoint, current, address, msb, lsb, disassembly)
+        },
+
+        _ => format!("{}{} {:04X}", breakpoint, current, address),
+    }
+}
+
+// Row of the memory viewer: address and up to BYTES_PER_ROW bytes
+pub fn memory_line(memory: &[u8], start: usize) -> String
+{
+    let bytes = memory.iter().skip(start).take(BYTES_PER_ROW);
+
+    bytes.fold(format!("{:04X} ", start), |line, byte| line + &format!(" {:02X}", byte))
+}
+
+// Character column of a byte in memory_line()
+pub fn memory_column(offset: usize) -> usize
+{
+    6 + 3 * offset
+}
+
+// Lines of the registers pane: pc, I, data registers, timers and the
+// top of the stack, as many entries as fit next to the disassembly
+pub fn register_lines(view: &DebugView) -> Vec<String>
+{
+    let half = NUM_DATA_REGISTERS / 2;
+
+    let mut lines = vec![ format!("PC {:04X}  I {:04X}", view.pc, view.i) ];
+
+    lines.extend((0..half).map(|x| format!("V{:X} {:02X}  V{:X} {:02X}",
+                                           x, view.registers[x], x + half, view.registers[x + half])));
+
+    lines.push(format!("DT {:02X}  ST {:02X}", view.delay, view.sound));
+    lines.push(format!("SP {}", view.stack.len()));
+    lines.push(String::from("STACK"));
+
+    let room = DISASSEMBLY_ROWS.saturating_sub(lines.len());
+    lines.extend(view.stack.iter().rev().take(room).map(|address| format!("  {:04X}", address)));
+
+    lines
+}
+
+// Lines of the watchpoints pane, the first ones when they don't all fit
+pub fn watch_lines(watchpoints: &[Watchpoint]) -> Vec<String>
+{
+    let mut lines: Vec<String> = watchpoints.iter()
+                                            .map(|watchpoint| format!("WATCH {}", watchpoint.expression()))
+                                            .collect();
+
+    if lines.len() > WATCH_ROWS
+    {
+        let hidden = lines.len() - WATCH_ROWS + 1;
+
+        lines.truncate(WATCH_ROWS - 1);
+        lines.push(format!("{} MORE", hidden));
+    }
+
+    lines
+}
+
+// Private functions
+fn action_at(x: usize, y: usize, rows: &[usize]) -> Option<DebugAction>
+{
+    if (MARGIN..MARGIN + BUTTON_HEIGHT).contains(&y)
+    {
+        let index = x.checked_sub(MARGIN)? / (BUTTON_WIDTH + 4);
+        let inside = (x - MARGIN) % (BUTTON_WIDTH + 4) < BUTTON_WIDTH;
+
+        return BUTTONS.get(index).filter(|_| inside).map(|(_, action)| *action);
+    }
+
+    if (MARGIN..REGISTERS_LEFT).contains(&x) && y >= PANE_TOP
+    {
+        let row = (y - PANE_TOP) / LINE_ADVANCE;
+        return rows.get(row).map(|&address| DebugAction::ToggleBreakpoint(address));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn disassembly() -> Result<(), String>
+    {
+        assert_eq!(disassembly_addresses(0x210, 5), vec![0x20C, 0x20E, 0x210, 0x212, 0x214]);
+        assert_eq!(disassembly_addresses(0x003, 5), vec![0x001, 0x003, 0x005, 0x007, 0x009]);
+
+        let mut memory = vec![0; 0x210];
+        memory[0x200..0x204].copy_from_slice(&[0x00, 0xE0, 0xFF, 0xFF]);
+        memory[0x20F] = 0x12;
+
+        let breakpoints = [0x202].iter().copied().collect();
+
+        assert_eq!(disassembly_line(&memory, 0x200, 0x200, &breakpoints), " > 0200  00E0  CLEAR");
+        assert_eq!(disassembly_line(&memory, 0x202, 0x200, &breakpoints), "*  0202  FFFF  ???");
+        assert_eq!(disassembly_line(&memory, 0x20F, 0x200, &breakpoints), "   020F");
+
+        Ok(())
+    }
+
+    #[test]
+    fn memory_viewer()
+    {
+        let memory: Vec<u8> = (0..0x28).collect();
+
+        let line = memory_line(&memory, 0x20);
+        assert_eq!(line, "0020  20 21 22 23 24 25 26 27");
+        assert_eq!(&line[memory_column(3)..memory_column(3) + 2], "23");
+    }
+
+    #[test]
+    fn registers()
+    {
+        let mut registers = [0; NUM_DATA_REGISTERS];
+        registers[0x9]    = 0xAB;
+
+        let stack       = [0x202, 0x20A, 0x300, 0x310, 0x320, 0x330, 0x340];
+        let breakpoints = BTreeSet::new();
+
+        let view = DebugView { pc: 0x234, registers, i: 0x300, stack: &stack, delay: 0x10, sound: 0,
+                               memory: &[], last_write: None, breakpoints: &breakpoints,
+                               watchpoints: &[], paused: true };
+
+        let lines = register_lines(&view);
+
+        assert_eq!(lines[0], "PC 0234  I 0300");
+        assert_eq!(lines[2], "V1 00  V9 AB");
+        assert_eq!(lines[9], "DT 10  ST 00");
+        assert_eq!(lines[10], "SP 7");
+
+        // Only the top of the stack fits
+        assert_eq!(lines.len(), DISASSEMBLY_ROWS);
+        assert_eq!(lines[12], "  0340");
+    }
+
+    #[test]
+    fn watches() -> Result<(), String>
+    {
+        let watchpoints: Vec<Watchpoint> = [ "V0 == 1", "V1 == 1", "V2 == 1", "V3 == 1", "V4 == 1" ]
+            .iter()
+            .map(|expression| Watchpoint::new(expression))
+            .collect::<Result<_, _>>()?;
+
+        assert_eq!(watch_lines(&watchpoints[..1]), vec!["WATCH V0 == 1"]);
+
+        let lines = watch_lines(&watchpoints);
+        assert_eq!(lines.len(), WATCH_ROWS);
+        assert_eq!(lines[WATCH_ROWS - 1], "2 MORE");
+
+        Ok(())
+    }
+
+    #[test]
+    fn clicks()
+    {
+        let rows = disassembly_addresses(0x200, DISASSEMBLY_ROWS);
+
+        assert_eq!(action_at(MARGIN, MARGIN, &rows), Some(DebugAction::Step));
+        assert_eq!(action_at(MARGIN + BUTTON_WIDTH + 4, MARGIN + 2, &rows), Some(DebugAction::Run));
+        assert_eq!(action_at(MARGIN + BUTTON_WIDTH + 1, MARGIN + 2, &rows), None);
+
+        assert_eq!(action_at(MARGIN, PANE_TOP, &rows), Some(DebugAction::ToggleBreakpoint(rows[0])));
+        assert_eq!(action_at(MARGIN, PANE_TOP + 2 * LINE_ADVANCE, &rows),
+                   Some(DebugAction::ToggleBreakpoint(rows[2])));
+
+        assert_eq!(action_at(REGISTERS_LEFT, PANE_TOP, &rows), None);
+        assert_eq!(action_at(MARGIN, MEMORY_TOP + 1, &rows), None);
+    }
+}
@@ -0,0 +1,557 @@
+use std::collections::BTreeSet;
+
+use crate::opcodes::OpCode;
+use crate::trace::MachineState;
+
+// Conditions on the machine state, checked before each instruction:
+//
+//   V3 == 0x10                 registers V0-VF, I, PC, DT, ST and SP
+//   [0x300] != 0               memory byte at an address
+//   I in [0x300, 0x320)        half-open or closed ranges
+//   write in [0x300, 0x320)    any byte written by the last instruction
+//   op == DXYN                 variant of the next instruction
+//   PC == 0x234 && !(V0 > 3)   combined with &&, || and !
+//
+// Numbers are decimal or hexadecimal with a 0x prefix.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition
+{
+    Value(Value, Test),
+    Write(Test),
+    Opcode(Comparison, String),
+    Not(Box<Condition>),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value
+{
+    Number(u32),
+    Register(u8),
+    I,
+    Pc,
+    Delay,
+    Sound,
+    Sp,
+    // Memory byte at the address
+    Memory(Box<Value>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Test
+{
+    Compare(Comparison, Value),
+    // Start and end of the range, and whether the end is included
+    In(Value, Value, bool),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison
+{
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+// What conditions are evaluated against
+pub struct WatchContext<'a>
+{
+    pub pc     : usize,
+    pub state  : &'a MachineState,
+    pub memory : &'a [u8],
+    // Instruction at the pc, None when not decodable
+    pub opcode : Option<OpCode>,
+    // Addresses written by the last instruction
+    pub writes : &'a [usize],
+}
+
+// Condition that pauses the interpreter when it becomes true, or every
+// time it holds for instructions and writes
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watchpoint
+{
+    expression : String,
+    condition  : Condition,
+    // Whether the condition held at the last check
+    holds      : bool,
+}
+
+impl Watchpoint
+{
+    pub fn new(expression: &str) -> Result<Self, String>
+    {
+        let condition = Condition::parse(expression)?;
+
+        Ok(Watchpoint { expression: expression.trim().to_string(), condition, holds: false })
+    }
+
+    pub fn expression(&self) -> &str
+    {
+        &self.expression
+    }
+
+    // True when the condition holds now but did not at the last check.
+    // Instructions and writes are events, which trigger every time.
+    pub fn check(&mut self, context: &WatchContext) -> bool
+    {
+        let held   = self.holds;
+        self.holds = self.condition.eval(context);
+
+        self.holds && (!held || self.condition.is_event())
+    }
+}
+
+// Public impl
+impl Condition
+{
+    pub fn parse(text: &str) -> Result<Self, String>
+    {
+        let tokens = tokenize(text).map_err(|e| format!("Invalid condition '{}': {}", text, e))?;
+        let mut parser = Parser { tokens, position: 0 };
+
+        parser.condition()
+              .and_then(|condition| match parser.next()
+              {
+                  None        => Ok(condition),
+                  Some(token) => Err(format!("unexpected '{}'", token)),
+              })
+              .map_err(|e| format!("Invalid condition '{}': {}", text, e))
+    }
+
+    pub fn eval(&self, context: &WatchContext) -> bool
+    {
+        match self
+        {
+            Condition::Value(value, test)  => test.eval(value.eval(context), context),
+            Condition::Write(test)         => context.writes.iter().any(|&address| test.eval(address as u32, context)),
+            Condition::Not(condition)      => !condition.eval(context),
+            Condition::All(conditions)     => conditions.iter().all(|condition| condition.eval(context)),
+            Condition::Any(conditions)     => conditions.iter().any(|condition| condition.eval(context)),
+
+            Condition::Opcode(comparison, name) =>
+            {
+                let matches = context.opcode.map(|opcode| opcode.name() == name).unwrap_or(false);
+
+                match comparison
+                {
+                    Comparison::NotEqual => !matches,
+                    _                    => matches,
+                }
+            },
+        }
+    }
+
+    // Whether the condition is about the instruction run or the bytes it
+    // wrote, rather than about a state that lasts
+    pub fn is_event(&self) -> bool
+    {
+        match self
+        {
+            Condition::Value(..)           => false,
+            Condition::Write(_)            => true,
+            Condition::Opcode(..)          => true,
+            Condition::Not(condition)      => condition.is_event(),
+            Condition::All(conditions)     => conditions.iter().any(Condition::is_event),
+            Condition::Any(conditions)     => conditions.iter().any(Condition::is_event),
+        }
+    }
+}
+
+impl Value
+{
+    pub fn eval(&self, context: &WatchContext) -> u32
+    {
+        match self
+        {
+            Value::Number(number)  => *number,
+            Value::Register(x)     => context.state.registers[*x as usize] as u32,
+            Value::I               => context.state.i as u32,
+            Value::Pc              => context.pc as u32,
+            Value::Delay           => context.state.delay as u32,
+            Value::Sound           => context.state.sound as u32,
+            Value::Sp              => context.state.sp as u32,
+            Value::Memory(address) => context.memory.get(address.eval(context) as usize)
+                                                    .copied()
+                                                    .unwrap_or(0) as u32,
+        }
+    }
+}
+
+impl Test
+{
+    pub fn eval(&self, value: u32, context: &WatchContext) -> bool
+    {
+        match self
+        {
+            Test::Compare(comparison, other) => comparison.eval(value, other.eval(context)),
+
+            Test::In(start, end, inclusive) =>
+            {
+                let (start, end) = (start.eval(context), end.eval(context));
+                value >= start && (value < end || *inclusive && value == end)
+            },
+        }
+    }
+}
+
+impl Comparison
+{
+    pub fn eval(self, left: u32, right: u32) -> bool
+    {
+        match self
+        {
+            Comparison::Equal          => left == right,
+            Comparison::NotEqual       => left != right,
+            Comparison::Less           => left < right,
+            Comparison::LessOrEqual    => left <= right,
+            Comparison::Greater        => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+
+    fn parse(symbol: &str) -> Option<Self>
+    {
+        match symbol
+        {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<"  => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">"  => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _    => None,
+        }
+    }
+}
+
+// Private impl
+const SYMBOLS : [&str; 14] = [ "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", "[", "]", "," ];
+
+// Words, numbers included, and symbols
+fn tokenize(text: &str) -> Result<Vec<String>, String>
+{
+    let mut tokens = Vec::new();
+    let mut rest   = text.trim_start();
+
+    while let Some(character) = rest.chars().next()
+    {
+        let length = match SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol))
+        {
+            Some(symbol) => symbol.len(),
+            None         => rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                                .unwrap_or(rest.len()),
+        };
+
+        if length == 0
+        {
+            return Err(format!("unexpected '{}'", character));
+        }
+
+        tokens.push(rest[..length].to_string());
+        rest = rest[length..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser
+{
+    tokens   : Vec<String>,
+    position : usize,
+}
+
+impl Parser
+{
+    fn peek(&self) -> Option<&str>
+    {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String>
+    {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+
+        token
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String>
+    {
+        match self.next()
+        {
+            Some(token) if token == expected => Ok(()),
+            Some(token)                      => Err(format!("expected '{}', found '{}'", expected, token)),
+            None                             => Err(format!("expected '{}'", expected)),
+        }
+    }
+
+    // Whether the next token is the given one, consuming it if so
+    fn accept(&mut self, token: &str) -> bool
+    {
+        let found = self.peek().map(|next| next.eq_ignore_ascii_case(token)).unwrap_or(false);
+
+        if found
+        {
+            self.position += 1;
+        }
+
+        found
+    }
+
+    // condition := all ("||" all)*
+    fn condition(&mut self) -> Result<Condition, String>
+    {
+        let mut any = vec![ self.all()? ];
+
+        while self.accept("||")
+        {
+            any.push(self.all()?);
+        }
+
+        Ok(if any.len() == 1 { any.remove(0) } else { Condition::Any(any) })
+    }
+
+    // all := unary ("&&" unary)*
+    fn all(&mut self) -> Result<Condition, String>
+    {
+        let mut all = vec![ self.unary()? ];
+
+        while self.accept("&&")
+        {
+            all.push(self.unary()?);
+        }
+
+        Ok(if all.len() == 1 { all.remove(0) } else { Condition::All(all) })
+    }
+
+    // unary := "!" unary | "(" condition ")" | "write" test | "op" ("==" | "!=") NAME | value test
+    fn unary(&mut self) -> Result<Condition, String>
+    {
+        if self.accept("!")
+        {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+
+        if self.accept("(")
+        {
+            let condition = self.condition()?;
+            self.expect(")")?;
+
+            return Ok(condition);
+        }
+
+        if self.accept("write")
+        {
+            return Ok(Condition::Write(self.test()?));
+        }
+
+        if self.accept("op")
+        {
+            let comparison = match self.next().as_deref()
+            {
+                Some("==") => Comparison::Equal,
+                Some("!=") => Comparison::NotEqual,
+                _          => return Err(String::from("op is compared with == or !=")),
+            };
+
+            let name = self.next().ok_or("missing instruction after op")?;
+            return Ok(Condition::Opcode(comparison, opcode_name(&name)?));
+        }
+
+        let value = self.value()?;
+        Ok(Condition::Value(value, self.test()?))
+    }
+
+    // test := COMPARISON value | "in" ("[" value "," value (")" | "]"))
+    fn test(&mut self) -> Result<Test, String>
+    {
+        if self.accept("in")
+        {
+            self.expect("[")?;
+            let start = self.value()?;
+            self.expect(",")?;
+            let end   = self.value()?;
+
+            return match self.next().as_deref()
+            {
+                Some(")") => Ok(Test::In(start, end, false)),
+                Some("]") => Ok(Test::In(start, end, true)),
+                _         => Err(String::from("ranges end with ) or ]")),
+            };
+        }
+
+        match self.next().as_deref().and_then(Comparison::parse)
+        {
+            Some(comparison) => Ok(Test::Compare(comparison, self.value()?)),
+            None             => Err(String::from("expected a comparison or 'in'")),
+        }
+    }
+
+    // value := "[" value "]" | NUMBER | REGISTER
+    fn value(&mut self) -> Result<Value, String>
+    {
+        if self.accept("[")
+        {
+            let address = self.value()?;
+            self.expect("]")?;
+
+            return Ok(Value::Memory(Box::new(address)));
+        }
+
+        let word = self.next().ok_or("missing value")?.to_uppercase();
+
+        match word.as_str()
+        {
+            "I"  => return Ok(Value::I),
+            "PC" => return Ok(Value::Pc),
+            "DT" => return Ok(Value::Delay),
+            "ST" => return Ok(Value::Sound),
+            "SP" => return Ok(Value::Sp),
+            _    => (),
+        }
+
+        if word.len() == 2 && word.starts_with('V')
+        {
+            if let Ok(x) = u8::from_str_radix(&word[1..], 16)
+            {
+                return Ok(Value::Register(x));
+            }
+        }
+
+        let number = match word.strip_prefix("0X")
+        {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None      => word.parse(),
+        };
+
+        number.map(Value::Number).map_err(|_| format!("unknown value '{}'", word))
+    }
+}
+
+// Instruction pattern as returned by OpCode::name(), with or without the
+// leading underscore of the variant
+fn opcode_name(name: &str) -> Result<String, String>
+{
+    let name = name.trim_start_matches('_').to_uppercase();
+
+    let names: BTreeSet<&str> = (0..=u16::MAX).filter_map(|word| OpCode::new((word >> 8) as u8, word as u8).ok())
+                                               .map(|opcode| opcode.name())
+                                               .collect();
+
+    match names.contains(name.as_str())
+    {
+        true  => Ok(name),
+        false => Err(format!("unknown instruction '{}'", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::registers::NUM_DATA_REGISTERS;
+
+    fn state() -> MachineState
+    {
+        let mut registers = [0; NUM_DATA_REGISTERS];
+        registers[3]      = 0x10;
+
+        MachineState { registers, i: 0x310, sp: 1, delay: 0, sound: 5 }
+    }
+
+    fn holds(expression: &str, context: &WatchContext) -> bool
+    {
+        Condition::parse(expression).unwrap().eval(context)
+    }
+
+    #[test]
+    fn conditions()
+    {
+        let state  = state();
+        let memory = [0xAA; 0x400];
+
+        let context = WatchContext { pc: 0x234, state: &state, memory: &memory,
+                                     opcode: Some(OpCode::_DXYN(0, 1, 5)), writes: &[0x305, 0x306] };
+
+        assert!(holds("V3 == 0x10", &context));
+        assert!(holds("v3 == 16 && ST > 4", &context));
+        assert!(!holds("V3 != 0x10", &context));
+        assert!(holds("I in [0x300, 0x320)", &context));
+        assert!(!holds("I in [0x300, 0x310)", &context));
+        assert!(holds("I in [0x300, 0x310]", &context));
+        assert!(holds("I in [V0, 0x400)", &context));
+        assert!(holds("[0x3FF] == 0xAA", &context));
+        assert!(holds("[I] == 0xAA && [0x1000] == 0", &context));
+
+        assert!(holds("write in [0x300, 0x320)", &context));
+        assert!(holds("write == 0x306", &context));
+        assert!(!holds("write >= 0x320", &context));
+
+        assert!(holds("op == DXYN", &context));
+        assert!(holds("op == _DXYN || PC == 0", &context));
+        assert!(!holds("op == 2NNN", &context));
+        assert!(holds("op != 2NNN", &context));
+
+        assert!(holds("!(PC == 0x234) || SP == 1 && DT == 0", &context));
+        assert!(!holds("!(PC == 0x234 || SP == 1)", &context));
+    }
+
+    #[test]
+    fn invalid_conditions()
+    {
+        for expression in &[ "", "V3", "V3 = 1", "VG == 1", "I in [1, 2", "op == DXYZ", "(V0 == 1",
+                             "V0 == 1 V1", "V0 == 0x", "PC == 0x200 # note" ]
+        {
+            assert!(Condition::parse(expression).is_err(), "Parsed '{}'", expression);
+        }
+
+        let error = Condition::parse("V3 == 0x10 &&").unwrap_err();
+        assert_eq!(error, "Invalid condition 'V3 == 0x10 &&': missing value");
+    }
+
+    #[test]
+    fn watchpoints() -> Result<(), String>
+    {
+        let mut state  = state();
+        let mut watch  = Watchpoint::new(" V3 == 0x11 ")?;
+
+        let mut check = | state: &MachineState | watch.check(&WatchContext { pc: 0x200, state, memory: &[],
+                                                                             opcode: None, writes: &[] });
+
+        assert!(!check(&state));
+
+        // Triggers once when the condition becomes true
+        state.registers[3] = 0x11;
+        assert!(check(&state));
+        assert!(!check(&state));
+
+        state.registers[3] = 0;
+        assert!(!check(&state));
+        state.registers[3] = 0x11;
+        assert!(check(&state));
+
+        assert_eq!(Watchpoint::new(" V3 == 0x11 ")?.expression(), "V3 == 0x11");
+
+        // Instructions trigger every time they run, even back to back
+        let mut watch  = Watchpoint::new("op == DXYN && V3 == 0x11")?;
+        let mut check  = | opcode: OpCode | watch.check(&WatchContext { pc: 0x200, state: &state, memory: &[],
+                                                                        opcode: Some(opcode), writes: &[] });
+
+        assert!(check(OpCode::_DXYN(0, 1, 5)));
+        assert!(check(OpCode::_DXYN(2, 3, 5)));
+        assert!(!check(OpCode::_00E0));
+
+        // And so do writes
+        let mut watch = Watchpoint::new("write == 0x300")?;
+        let context   = WatchContext { pc: 0x200, state: &state, memory: &[], opcode: None, writes: &[0x300] };
+
+        assert!(watch.check(&context));
+        assert!(watch.check(&context));
+
+        Ok(())
+    }
+}
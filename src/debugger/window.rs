@@ -0,0 +1,198 @@
+use sdl2::Sdl;
+use sdl2::rect::Rect;
+use sdl2::pixels::Color;
+use sdl2::video::Window;
+use sdl2::render::Canvas;
+
+use crate::display::{ text_pixels, CHAR_ADVANCE, LINE_ADVANCE };
+
+use super::*;
+
+const WINDOW_TITLE : &str = "Chust8 debugger";
+
+// Window pixels per font pixel
+const SCALE : u32 = 3;
+
+// Space between the game window and the debugger, in window pixels
+const WINDOW_GAP : i32 = 8;
+
+const BACKGROUND      : Color = Color::RGB(24, 24, 32);
+const TEXT            : Color = Color::RGB(220, 220, 220);
+const ACTIVE_BUTTON   : Color = Color::RGB(60, 90, 60);
+const PC_ROW          : Color = Color::RGB(40, 70, 130);
+const BREAKPOINT      : Color = Color::RGB(230, 60, 60);
+const I_BYTE          : Color = Color::RGB(120, 100, 20);
+const LAST_WRITE_BYTE : Color = Color::RGB(130, 40, 40);
+
+pub struct DebugWindow
+{
+    canvas : Canvas<Window>,
+}
+
+// Public impl
+impl DebugWindow
+{
+    // Window of the given size in font pixels, to the right of another
+    pub fn new(context: &Sdl, beside: Rect, width: usize, height: usize) -> Result<Self, String>
+    {
+        let video = context.video()?;
+
+        let window = video.window(WINDOW_TITLE, width as u32 * SCALE, height as u32 * SCALE)
+                          .position(beside.right() + WINDOW_GAP, beside.y())
+                          .build()
+                          .map_err(|e| e.to_string())?;
+
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+
+        Ok(DebugWindow { canvas })
+    }
+
+    pub fn id(&self) -> u32
+    {
+        self.canvas.window().id()
+    }
+
+    pub fn to_font_pixels(&self, x: i32, y: i32) -> Option<(usize, usize)>
+    {
+        if x < 0 || y < 0
+        {
+            return None;
+        }
+
+        Some((x as usize / SCALE as usize, y as usize / SCALE as usize))
+    }
+
+    // Draws the buttons, the disassembly rows at the given addresses, the
+    // registers and the memory from the given address
+    pub fn draw(&mut self, view: &DebugView, rows: &[usize], memory_start: usize) -> Result<(), String>
+    {
+        self.canvas.set_draw_color(BACKGROUND);
+        self.canvas.clear();
+
+        self.draw_buttons(view.paused)?;
+        self.draw_disassembly(view, rows)?;
+
+        for (row, line) in register_lines(view).iter().enumerate()
+        {
+            self.text(line, REGISTERS_LEFT, PANE_TOP + row * LINE_ADVANCE, TEXT)?;
+        }
+
+        self.draw_memory(view, memory_start)?;
+
+        for (row, line) in watch_lines(view.watchpoints).iter().enumerate()
+        {
+            self.text(line, MARGIN, WATCH_TOP + row * LINE_ADVANCE, TEXT)?;
+        }
+
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+// Private impl
+impl DebugWindow
+{
+    // The button of the current state, running or paused, is lit
+    fn draw_buttons(&mut self, paused: bool) -> Result<(), String>
+    {
+        for (index, (label, action)) in BUTTONS.iter().enumerate()
+        {
+            let x      = MARGIN + index * (BUTTON_WIDTH + 4);
+            let active = match action
+            {
+                DebugAction::Run   => !paused,
+                DebugAction::Pause => paused,
+                _                  => false,
+            };
+
+            if active
+            {
+                self.fill(x, MARGIN, BUTTON_WIDTH, BUTTON_HEIGHT, ACTIVE_BUTTON)?;
+            }
+
+            self.canvas.set_draw_color(TEXT);
+            self.canvas.draw_rect(to_rect(x, MARGIN, BUTTON_WIDTH, BUTTON_HEIGHT))?;
+
+            let text_x = x + (BUTTON_WIDTH + 1 - label.len() * CHAR_ADVANCE) / 2;
+            self.text(label, text_x, MARGIN + 3, TEXT)?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_disassembly(&mut self, view: &DebugView, rows: &[usize]) -> Result<(), String>
+    {
+        for (row, &address) in rows.iter().enumerate()
+        {
+            let y = PANE_TOP + row * LINE_ADVANCE;
+
+            if address == view.pc
+            {
+                self.fill(MARGIN - 1, y - 1, REGISTERS_LEFT - MARGIN - 4, LINE_ADVANCE, PC_ROW)?;
+            }
+
+            let line = disassembly_line(view.memory, address, view.pc, view.breakpoints);
+            self.text(&line, MARGIN, y, TEXT)?;
+
+            if view.breakpoints.contains(&address)
+            {
+                self.text("*", MARGIN, y, BREAKPOINT)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Rows of memory with the bytes at I and of the last write highlighted
+    fn draw_memory(&mut self, view: &DebugView, memory_start: usize) -> Result<(), String>
+    {
+        let highlights = [ (Some(view.i as usize), I_BYTE), (view.last_write, LAST_WRITE_BYTE) ];
+
+        for row in 0..MEMORY_ROWS
+        {
+            let start = memory_start + row * BYTES_PER_ROW;
+            let y     = MEMORY_TOP + row * LINE_ADVANCE;
+
+            if start >= view.memory.len()
+            {
+                break;
+            }
+
+            for (address, color) in highlights.iter()
+            {
+                if let Some(offset) = address.and_then(|address| address.checked_sub(start))
+                                             .filter(|&offset| offset < BYTES_PER_ROW)
+                {
+                    let x = MARGIN + memory_column(offset) * CHAR_ADVANCE;
+                    self.fill(x - 1, y - 1, 2 * CHAR_ADVANCE + 1, LINE_ADVANCE, *color)?;
+                }
+            }
+
+            self.text(&memory_line(view.memory, start), MARGIN, y, TEXT)?;
+        }
+
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str, x: usize, y: usize, color: Color) -> Result<(), String>
+    {
+        let rects: Vec<Rect> = text_pixels(text).into_iter()
+                                                .map(|(dx, dy)| to_rect(x + dx, y + dy, 1, 1))
+                                                .collect();
+
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rects(&rects)
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color) -> Result<(), String>
+    {
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rect(to_rect(x, y, width, height))
+    }
+}
+
+// Window rect of a rect in font pixels
+fn to_rect(x: usize, y: usize, width: usize, height: usize) -> Rect
+{
+    Rect::new(x as i32 * SCALE as i32, y as i32 * SCALE as i32, width as u32 * SCALE, height as u32 * SCALE)
+}
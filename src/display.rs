@@ -2,25 +2,44 @@
 use sdl2::rect::Rect;
 use sdl2::pixels::Color;
 use sdl2::video::{ Window, WindowContext };
-use sdl2::render::{ TextureCreator, Canvas };
+use sdl2::render::{ BlendMode, TextureCreator, Canvas };
 
-// TODO: this should be customizable
-const DISPLAY_WIDTH  : u32 = 1280;
-const DISPLAY_HEIGHT : u32 = 640;
+// Window pixels per grid pixel
+pub const DEFAULT_SCALE : u32 = 20;
+pub const MAX_SCALE     : u32 = 64;
 
 const DISPLAY_TITLE : &'static str = "Chust8";
 
+// Overlay colors, and window pixels per overlay font pixel for every
+// HUD_PIXEL_DIVISOR window pixels per grid pixel
+const HUD_BACKGROUND    : Color = Color::RGBA(0, 0, 0, 176);
+const HUD_TEXT          : Color = Color::RGB(230, 230, 230);
+const HUD_KEY_PRESSED   : Color = Color::RGB(255, 200, 0);
+const HUD_PIXEL_DIVISOR : u32   = 8;
+// Space around the overlay, and between text and keypad, in font pixels
+const HUD_MARGIN        : usize = 2;
+
 mod grid;
 mod tileset;
+mod palette;
+mod hud;
 
-use grid::{ GridEditor, PixelGrid };
+pub use grid::{ GridEditor, PixelGrid, SpriteMode, GRID_WIDTH, GRID_HEIGHT };
+pub use palette::Palette;
+pub use hud::{ HudInfo, RateMeter, text_pixels, CHAR_ADVANCE, LINE_ADVANCE };
+use hud::KEYPAD_LAYOUT;
 use tileset::{ Tileset, TileType };
 
 pub struct Display
 {
     canvas      : Canvas<Window>,
     grid_editor : GridEditor,
-    tileset     : Tileset
+    tileset     : Tileset,
+    status      : Option<String>,
+    // Plain colors drawn instead of the tileset
+    palette     : Option<Palette>,
+    // Machine state drawn over the grid
+    hud         : Option<HudInfo>,
 }
 
 impl Display
@@ -28,12 +47,26 @@ impl Display
     pub fn from_context(context: &sdl2::Sdl) -> Result<Self, String>
     {
         let window = Self::default_window(&context)?;
-        Self::from_window(window)
+        Self::from_window(window, false)
     }
 
-    pub fn from_window(window: Window) -> Result<Self, String>
+    // Display whose window is the grid size times the given scale
+    pub fn with_scale(context: &sdl2::Sdl, scale: u32, vsync: bool) -> Result<Self, String>
     {
-        let canvas = match window.into_canvas().present_vsync().build()
+        let window = Self::scaled_window(&context, scale)?;
+        Self::from_window(window, vsync)
+    }
+
+    // With vsync, presenting blocks until the display refresh
+    pub fn from_window(window: Window, vsync: bool) -> Result<Self, String>
+    {
+        let builder = match vsync
+        {
+            true  => window.into_canvas().present_vsync(),
+            false => window.into_canvas(),
+        };
+
+        let canvas = match builder.build()
         {
             Ok(canvas) => canvas,
             Err(error) => return Err(error.to_string()),
@@ -42,10 +75,17 @@ impl Display
         let grid_editor = GridEditor::new();
         let tileset     = Tileset::new(&canvas)?;
 
-        Ok(Display { canvas, grid_editor, tileset, })
+        Ok(Display { canvas, grid_editor, tileset, status: None, palette: None, hud: None })
     }
 
     pub fn update(&mut self) -> Result<(), String>
+    {
+        let grid = self.grid_editor.grid().clone();
+        self.present(&grid)
+    }
+
+    // Draws a grid other than the one owned by the display
+    pub fn present(&mut self, grid: &PixelGrid) -> Result<(), String>
     {
         // Compute the rect that will contain the sprites
         // according to the current window's size
@@ -56,15 +96,12 @@ impl Display
         let on_tile  = self.tileset.tile(TileType::On);
         let off_tile = self.tileset.tile(TileType::Off);
 
-        let grid = self.grid_editor.grid();
-
         // Loop through the grid and update the textures
         for col in 0..grid.width()
         {
             for row in 0..grid.height()
             {
                 let pixel = grid.at(row, col)?;
-                let tile  = if pixel { on_tile } else { off_tile };
 
                 // Compute the tile where the sprite will be rended.
                 // First the rescaled size is computed, and then the
@@ -77,19 +114,56 @@ impl Display
                 rescaled_tile.set_x(new_x as i32);
                 rescaled_tile.set_y(new_y as i32);
 
-                self.canvas.copy(&texture, Some(*tile), Some(rescaled_tile))?;
+                match self.palette
+                {
+                    Some(palette) =>
+                    {
+                        self.canvas.set_draw_color(palette.color(pixel));
+                        self.canvas.fill_rect(rescaled_tile)?;
+                    },
+
+                    None =>
+                    {
+                        let tile = if pixel { on_tile } else { off_tile };
+                        self.canvas.copy(&texture, Some(*tile), Some(rescaled_tile))?;
+                    },
+                }
             }
         }
 
+        if let Some(hud) = self.hud.take()
+        {
+            self.draw_hud(&hud)?;
+            self.hud = Some(hud);
+        }
+
         self.canvas.present();
         Ok(())
     }
 
+    // Shows the overlay with the given machine state, or hides it
+    pub fn set_hud(&mut self, hud: Option<HudInfo>)
+    {
+        self.hud = hud;
+    }
+
     pub fn default_window(context: &sdl2::Sdl) -> Result<Window, String>
     {
+        Self::scaled_window(context, DEFAULT_SCALE)
+    }
+
+    pub fn scaled_window(context: &sdl2::Sdl, scale: u32) -> Result<Window, String>
+    {
+        if scale == 0 || scale > MAX_SCALE
+        {
+            return Err(format!("Invalid scale {}. It must be between 1 and {}", scale, MAX_SCALE));
+        }
+
         let video_subsystem = context.video()?;
+        let width           = GRID_WIDTH as u32 * scale;
+        let height          = GRID_HEIGHT as u32 * scale;
 
-        match video_subsystem.window(DISPLAY_TITLE, DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        match video_subsystem.window(DISPLAY_TITLE, width, height)
                                         .position_centered().build()
         {
             Ok(window) => Ok(window),
@@ -97,10 +171,54 @@ impl Display
         }
     }
 
+    // Shows a short status, e.g. the emulation speed, in the window title
+    pub fn set_status(&mut self, status: Option<String>) -> Result<(), String>
+    {
+        if self.status == status { return Ok(()); }
+
+        let title = match &status
+        {
+            Some(text) => format!("{} - {}", DISPLAY_TITLE, text),
+            None       => String::from(DISPLAY_TITLE),
+        };
+
+        self.canvas.window_mut().set_title(&title).map_err(|e| e.to_string())?;
+        self.status = status;
+
+        Ok(())
+    }
+
+    // Identifies the window in window events
+    pub fn window_id(&self) -> u32
+    {
+        self.canvas.window().id()
+    }
+
+    // Position and size of the window on the screen
+    pub fn window_rect(&self) -> Rect
+    {
+        let window          = self.canvas.window();
+        let (x, y)          = window.position();
+        let (width, height) = window.size();
+
+        Rect::new(x, y, width, height)
+    }
+
+    // Draws pixels with plain colors, or with the tileset when None
+    pub fn set_palette(&mut self, palette: Option<Palette>)
+    {
+        self.palette = palette;
+    }
+
     pub fn mut_editor(&mut self) -> &mut GridEditor
     {
         &mut self.grid_editor
     }
+
+    pub fn grid(&self) -> &PixelGrid
+    {
+        self.grid_editor.grid()
+    }
 }
 
 // private impl
@@ -114,6 +232,73 @@ impl Display
         Rect::new(0, 0, viewport.width() / grid.width() as u32,
                         viewport.height() / grid.height() as u32)
     }
+
+    // Draws the text lines and the keypad of the overlay over a dark
+    // background, in the top left corner
+    fn draw_hud(&mut self, hud: &HudInfo) -> Result<(), String>
+    {
+        let pixel = (self.display_cell_size().width() / HUD_PIXEL_DIVISOR).max(1);
+        let lines = hud.lines();
+
+        let text_width  = lines.iter().map(|line| line.len()).max().unwrap_or(0) * CHAR_ADVANCE;
+        let text_height = lines.len() * LINE_ADVANCE;
+
+        // Keys are squares a line tall, one pixel apart
+        let key_size     = LINE_ADVANCE + 2;
+        let keypad_x     = HUD_MARGIN + text_width + HUD_MARGIN;
+        let keypad_width = 4 * (key_size + 1);
+
+        let width  = keypad_x + keypad_width + HUD_MARGIN;
+        let height = HUD_MARGIN + text_height.max(keypad_width) + HUD_MARGIN;
+
+        let to_rect = | x: usize, y: usize, w: usize, h: usize |
+            Rect::new(x as i32 * pixel as i32, y as i32 * pixel as i32, w as u32 * pixel, h as u32 * pixel);
+
+        let text_rects = | text: &str, x: usize, y: usize | -> Vec<Rect>
+        {
+            text_pixels(text).into_iter().map(|(dx, dy)| to_rect(x + dx, y + dy, 1, 1)).collect()
+        };
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(HUD_BACKGROUND);
+        self.canvas.fill_rect(to_rect(0, 0, width, height))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+
+        let mut text = Vec::new();
+
+        for (row, line) in lines.iter().enumerate()
+        {
+            text.extend(text_rects(line, HUD_MARGIN, HUD_MARGIN + row * LINE_ADVANCE));
+        }
+
+        let mut pressed = Vec::new();
+        let mut labels  = Vec::new();
+
+        for (index, &key) in KEYPAD_LAYOUT.iter().enumerate()
+        {
+            let x     = keypad_x + (index % 4) * (key_size + 1);
+            let y     = HUD_MARGIN + (index / 4) * (key_size + 1);
+            let label = text_rects(&format!("{:X}", key), x + 3, y + 2);
+
+            match hud.is_key_pressed(key)
+            {
+                true  => { pressed.push(to_rect(x, y, key_size, key_size)); labels.extend(label); },
+                false => { self.canvas.set_draw_color(HUD_TEXT);
+                           self.canvas.draw_rect(to_rect(x, y, key_size, key_size))?;
+                           text.extend(label); },
+            }
+        }
+
+        self.canvas.set_draw_color(HUD_TEXT);
+        self.canvas.fill_rects(&text)?;
+
+        self.canvas.set_draw_color(HUD_KEY_PRESSED);
+        self.canvas.fill_rects(&pressed)?;
+
+        // Labels of pressed keys are cut out of their squares
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.fill_rects(&labels)
+    }
 }
 
 #[cfg(test)]
@@ -130,7 +315,7 @@ mod tests
 
         let context = sdl2::init()?;
         let window  = Display::default_window(&context)?;
-        let display = Display::from_window(window)?;
+        let display = Display::from_window(window, false)?;
 
         Ok(())
     }
@@ -142,7 +327,7 @@ mod tests
 
         let context     = sdl2::init()?;
         let window      = Display::default_window(&context)?;
-        let mut display = Display::from_window(window)?;
+        let mut display = Display::from_window(window, false)?;
 
         // Set random pixels and display the result
         let mut rng  = rand::thread_rng();
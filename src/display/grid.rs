@@ -1,14 +1,24 @@
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpriteMode
+{
+    // Sprite pixels beyond the screen edges are discarded
+    Clip,
+    // Sprite pixels beyond the screen edges reappear on the opposite side
+    Wrap,
+}
+
 pub struct GridEditor
 {
-    grid: PixelGrid
+    grid        : PixelGrid,
+    sprite_mode : SpriteMode,
 }
 
 impl GridEditor
 {
     pub fn new() -> Self
     {
-        GridEditor { grid: PixelGrid::new() }
+        GridEditor { grid: PixelGrid::new(), sprite_mode: SpriteMode::Clip }
     }
 
     pub fn clear(&mut self)
@@ -16,23 +26,38 @@ impl GridEditor
         self.grid.data.iter_mut().for_each(|pixel| *pixel = false);
     }
 
-    pub fn write_byte(&mut self, row: u8, col: u8, byte: u8) -> Result<(), String>
+    // XORs the sprite rows into the grid, starting at the given coordinates.
+    // The starting point always wraps around the screen, while the rest of
+    // the sprite is clipped or wrapped depending on the sprite mode.
+    // Returns whether any lit pixel was turned off.
+    pub fn draw_sprite(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool
     {
-        let bits = bits_big_endian(byte);
+        let origin_col = x as usize % GRID_WIDTH;
+        let origin_row = y as usize % GRID_HEIGHT;
 
-        // TODO: recheck this
-        for (index, bit) in bits.iter().enumerate()
+        let mut collision = false;
+
+        for (offset, byte) in sprite.iter().enumerate()
         {
-            let big_endian_index = (7 - index) as usize;
-            let col_index        = (col as usize + big_endian_index) % GRID_WIDTH;
-            let row_index        = row as usize % GRID_HEIGHT;
+            collision |= self.draw_row(origin_row + offset, origin_col, *byte);
+        }
 
-            let current_value = self.grid.at(row_index, col_index)?;
+        return collision;
+    }
 
-            self.grid.set(row_index, col_index, current_value ^ bit)?;
-        }
+    pub fn write_byte(&mut self, row: u8, col: u8, byte: u8) -> bool
+    {
+        self.draw_sprite(col, row, &[byte])
+    }
 
-        Ok(())
+    pub fn sprite_mode(&self) -> SpriteMode
+    {
+        self.sprite_mode
+    }
+
+    pub fn set_sprite_mode(&mut self, mode: SpriteMode)
+    {
+        self.sprite_mode = mode;
     }
 
     pub fn grid(&self) -> &PixelGrid
@@ -46,29 +71,66 @@ impl GridEditor
     }
 }
 
-// TODO: this function returns the values in little endian order actually.
-// Have to fix that
-// Helper function to split all the bits in a byte following a 
-// big endian order
-fn bits_big_endian(mut byte : u8) -> [bool; 8]
+impl Default for GridEditor
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+// private
+impl GridEditor
+{
+    fn draw_row(&mut self, row: usize, col: usize, byte: u8) -> bool
+    {
+        let mut collision = false;
+
+        for (index, bit) in bits_big_endian(byte).iter().enumerate()
+        {
+            if !bit { continue; }
+
+            let (row_index, col_index) = match self.sprite_mode
+            {
+                SpriteMode::Wrap => (row % GRID_HEIGHT, (col + index) % GRID_WIDTH),
+                SpriteMode::Clip =>
+                {
+                    if row >= GRID_HEIGHT || col + index >= GRID_WIDTH { continue; }
+                    (row, col + index)
+                }
+            };
+
+            let pixel = &mut self.grid.data[row_index * GRID_WIDTH + col_index];
+
+            collision |= *pixel;
+            *pixel    ^= true;
+        }
+
+        return collision;
+    }
+}
+
+// Helper function to split all the bits in a byte following a
+// big endian order, i.e. the most significant bit comes first
+fn bits_big_endian(byte : u8) -> [bool; 8]
 {
     let mut bits = [false; 8];
 
     // Iterate through all the bits in given byte
     for bit_index in 0..8
     {
-        bits[bit_index] = (byte % 2) == 1;
-        byte /= 2;
+        bits[bit_index] = (byte >> (7 - bit_index)) & 0x1 == 1;
     }
 
     return bits;
 }
 
-const GRID_WIDTH  : usize = 64;
-const GRID_HEIGHT : usize = 32;
+pub const GRID_WIDTH  : usize = 64;
+pub const GRID_HEIGHT : usize = 32;
 
 type InternalStorage = [bool; GRID_WIDTH * GRID_HEIGHT];
 
+#[derive(Clone)]
 pub struct PixelGrid
 {
     data : InternalStorage,
@@ -103,6 +165,24 @@ impl PixelGrid
         Ok(())
     }
 
+    // One line per row, '#' for lit pixels and '.' for the rest
+    pub fn to_ascii(&self) -> String
+    {
+        self.data.chunks(GRID_WIDTH)
+            .map(|row| row.iter().map(|&pixel| if pixel { '#' } else { '.' }).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // 64 bits FNV-1a hash of the pixels, stable across builds and platforms
+    pub fn checksum(&self) -> u64
+    {
+        self.data.iter().fold(0xCBF2_9CE4_8422_2325, | hash, &pixel |
+        {
+            (hash ^ pixel as u64).wrapping_mul(0x0100_0000_01B3)
+        })
+    }
+
     // TODO: should these be set in the constructor?
     pub fn width(&self) -> usize
     {
@@ -142,6 +222,7 @@ mod tests
 {
     use super::*;
     use rand::Rng;
+    use crate::font::DEFAULT_SPRITES;
 
     #[test]
     fn grid_init()
@@ -235,16 +316,15 @@ mod tests
         let byte = 0b11001010;
         let splitted_bits = bits_big_endian(byte);
 
-        // for_each_bit gives the bits in a little endian order
-        // so the indexes are inversed.
-        assert_eq!(splitted_bits[7], true,  "Unexpected value at position 7");
-        assert_eq!(splitted_bits[6], true,  "Unexpected value at position 6");
-        assert_eq!(splitted_bits[5], false, "Unexpected value at position 5");
-        assert_eq!(splitted_bits[4], false, "Unexpected value at position 4");
-        assert_eq!(splitted_bits[3], true,  "Unexpected value at position 3");
-        assert_eq!(splitted_bits[2], false, "Unexpected value at position 2");
+        // The most significant bit is the first one
+        assert_eq!(splitted_bits[0], true,  "Unexpected value at position 0");
         assert_eq!(splitted_bits[1], true,  "Unexpected value at position 1");
-        assert_eq!(splitted_bits[0], false, "Unexpected value at position 0");
+        assert_eq!(splitted_bits[2], false, "Unexpected value at position 2");
+        assert_eq!(splitted_bits[3], false, "Unexpected value at position 3");
+        assert_eq!(splitted_bits[4], true,  "Unexpected value at position 4");
+        assert_eq!(splitted_bits[5], false, "Unexpected value at position 5");
+        assert_eq!(splitted_bits[6], true,  "Unexpected value at position 6");
+        assert_eq!(splitted_bits[7], false, "Unexpected value at position 7");
     }
 
     #[test]
@@ -272,9 +352,136 @@ mod tests
         Ok(())
     }
 
+    // Renders a region of the grid as ascii art, one string per row
+    fn render(grid: &PixelGrid, row: usize, col: usize, width: usize, height: usize) -> Vec<String>
+    {
+        (row..row + height)
+            .map(|r| (col..col + width)
+                        .map(|c| if grid.at(r, c).unwrap() { '#' } else { '.' })
+                        .collect())
+            .collect()
+    }
+
     #[test]
-    fn grid_editor_write() -> Result<(), String>
+    fn grid_editor_write()
     {
-        Ok(())
+        let mut grid_editor = GridEditor::new();
+
+        // The most significant bit is drawn on the left
+        assert!(!grid_editor.write_byte(3, 8, 0b11001010));
+        assert_eq!(render(grid_editor.grid(), 3, 8, 8, 1), vec!["##..#.#."]);
+
+        // Writing it again erases it and reports the collision
+        assert!(grid_editor.write_byte(3, 8, 0b11001010));
+        assert!(grid_editor.grid().peek().iter().all(|pixel| !pixel));
+    }
+
+    #[test]
+    fn draw_font_sprites()
+    {
+        let mut grid_editor = GridEditor::new();
+
+        let zero = &DEFAULT_SPRITES[0..5];
+        let one  = &DEFAULT_SPRITES[5..10];
+
+        assert!(!grid_editor.draw_sprite(0, 0, zero));
+        assert!(!grid_editor.draw_sprite(10, 20, one));
+
+        assert_eq!(render(grid_editor.grid(), 0, 0, 4, 5),
+                   vec!["####",
+                        "#..#",
+                        "#..#",
+                        "#..#",
+                        "####"]);
+
+        assert_eq!(render(grid_editor.grid(), 20, 10, 4, 5),
+                   vec!["..#.",
+                        ".##.",
+                        "..#.",
+                        "..#.",
+                        ".###"]);
+
+        // Every glyph in the font has to be drawn row by row, msb first,
+        // and fully erased when drawn twice
+        for (glyph, sprite) in DEFAULT_SPRITES.chunks(5).enumerate()
+        {
+            grid_editor.clear();
+
+            let x = ((glyph % 8) * 8) as u8;
+            let y = ((glyph / 8) * 6) as u8;
+
+            assert!(!grid_editor.draw_sprite(x, y, sprite),
+                    "Unexpected collision drawing glyph {:X}", glyph);
+
+            for (row, byte) in sprite.iter().enumerate()
+            {
+                let expected = (0..8).map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                                     .collect::<String>();
+
+                assert_eq!(render(grid_editor.grid(), y as usize + row, x as usize, 8, 1),
+                           vec![expected], "Glyph {:X} row {} drawn incorrectly", glyph, row);
+            }
+
+            assert!(grid_editor.draw_sprite(x, y, sprite),
+                    "Missing collision redrawing glyph {:X}", glyph);
+
+            assert!(grid_editor.grid().peek().iter().all(|pixel| !pixel),
+                    "Glyph {:X} was not erased when drawn twice", glyph);
+        }
+    }
+
+    #[test]
+    fn draw_sprite_collision()
+    {
+        let mut grid_editor = GridEditor::new();
+
+        assert!(!grid_editor.draw_sprite(0, 0, &[0b11110000]));
+
+        // Overlapping only unlit pixels is not a collision
+        assert!(!grid_editor.draw_sprite(4, 0, &[0b11110000]));
+        assert_eq!(render(grid_editor.grid(), 0, 0, 8, 1), vec!["########"]);
+
+        // Turning off a single pixel is
+        assert!(grid_editor.draw_sprite(7, 0, &[0b10000000]));
+        assert_eq!(render(grid_editor.grid(), 0, 0, 8, 1), vec!["#######."]);
+    }
+
+    #[test]
+    fn draw_sprite_clip()
+    {
+        let mut grid_editor = GridEditor::new();
+        assert_eq!(grid_editor.sprite_mode(), SpriteMode::Clip);
+
+        // Horizontal clipping
+        assert!(!grid_editor.draw_sprite(60, 0, &[0xFF]));
+        assert_eq!(render(grid_editor.grid(), 0, 56, 8, 1), vec!["....####"]);
+        assert_eq!(render(grid_editor.grid(), 0, 0, 4, 1),  vec!["...."]);
+
+        // Vertical clipping
+        assert!(!grid_editor.draw_sprite(0, 30, &[0x80, 0x80, 0x80, 0x80]));
+        assert_eq!(render(grid_editor.grid(), 30, 0, 1, 2), vec!["#", "#"]);
+        assert_eq!(render(grid_editor.grid(), 1, 0, 1, 2),  vec![".", "."]);
+
+        // The starting coordinates always wrap
+        grid_editor.clear();
+        assert!(!grid_editor.draw_sprite(66, 33, &[0x80]));
+        assert!(grid_editor.grid().at(1, 2).unwrap());
+    }
+
+    #[test]
+    fn draw_sprite_wrap()
+    {
+        let mut grid_editor = GridEditor::new();
+        grid_editor.set_sprite_mode(SpriteMode::Wrap);
+
+        // Horizontal wrapping
+        assert!(!grid_editor.draw_sprite(60, 0, &[0xFF]));
+        assert_eq!(render(grid_editor.grid(), 0, 56, 8, 1), vec!["....####"]);
+        assert_eq!(render(grid_editor.grid(), 0, 0, 4, 1),  vec!["####"]);
+
+        // Vertical wrapping, colliding with the previous sprite
+        assert!(grid_editor.draw_sprite(0, 30, &[0x80, 0x80, 0x80, 0x80]));
+        assert_eq!(render(grid_editor.grid(), 30, 0, 1, 2), vec!["#", "#"]);
+        assert_eq!(render(grid_editor.grid(), 0, 0, 1, 2),  vec![".", "#"]);
     }
 }
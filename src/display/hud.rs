@@ -0,0 +1,254 @@
+use std::time::{ Duration, Instant };
+
+use crate::registers::NUM_DATA_REGISTERS;
+
+// Glyphs of the overlay font, 3 pixels wide and 5 tall. Each row keeps
+// its leftmost pixel in bit 2.
+pub const GLYPH_WIDTH  : usize = 3;
+pub const GLYPH_HEIGHT : usize = 5;
+
+// Pixels from a glyph or a line to the next one
+pub const CHAR_ADVANCE : usize = GLYPH_WIDTH + 1;
+pub const LINE_ADVANCE : usize = GLYPH_HEIGHT + 2;
+
+const UNKNOWN_GLYPH : [u8; GLYPH_HEIGHT] = [ 0b111, 0b001, 0b011, 0b000, 0b010 ];
+
+const GLYPHS : [(char, [u8; GLYPH_HEIGHT]); 56] =
+[
+    ('0', [ 0b111, 0b101, 0b101, 0b101, 0b111 ]),
+    ('1', [ 0b010, 0b110, 0b010, 0b010, 0b111 ]),
+    ('2', [ 0b111, 0b001, 0b111, 0b100, 0b111 ]),
+    ('3', [ 0b111, 0b001, 0b011, 0b001, 0b111 ]),
+    ('4', [ 0b101, 0b101, 0b111, 0b001, 0b001 ]),
+    ('5', [ 0b111, 0b100, 0b111, 0b001, 0b111 ]),
+    ('6', [ 0b111, 0b100, 0b111, 0b101, 0b111 ]),
+    ('7', [ 0b111, 0b001, 0b010, 0b010, 0b010 ]),
+    ('8', [ 0b111, 0b101, 0b111, 0b101, 0b111 ]),
+    ('9', [ 0b111, 0b101, 0b111, 0b001, 0b111 ]),
+    ('A', [ 0b010, 0b101, 0b111, 0b101, 0b101 ]),
+    ('B', [ 0b110, 0b101, 0b110, 0b101, 0b110 ]),
+    ('C', [ 0b011, 0b100, 0b100, 0b100, 0b011 ]),
+    ('D', [ 0b110, 0b101, 0b101, 0b101, 0b110 ]),
+    ('E', [ 0b111, 0b100, 0b110, 0b100, 0b111 ]),
+    ('F', [ 0b111, 0b100, 0b110, 0b100, 0b100 ]),
+    ('G', [ 0b011, 0b100, 0b101, 0b101, 0b011 ]),
+    ('H', [ 0b101, 0b101, 0b111, 0b101, 0b101 ]),
+    ('I', [ 0b111, 0b010, 0b010, 0b010, 0b111 ]),
+    ('J', [ 0b001, 0b001, 0b001, 0b101, 0b010 ]),
+    ('K', [ 0b101, 0b101, 0b110, 0b101, 0b101 ]),
+    ('L', [ 0b100, 0b100, 0b100, 0b100, 0b111 ]),
+    ('M', [ 0b101, 0b111, 0b111, 0b101, 0b101 ]),
+    ('N', [ 0b110, 0b101, 0b101, 0b101, 0b101 ]),
+    ('O', [ 0b010, 0b101, 0b101, 0b101, 0b010 ]),
+    ('P', [ 0b110, 0b101, 0b110, 0b100, 0b100 ]),
+    ('Q', [ 0b010, 0b101, 0b101, 0b110, 0b011 ]),
+    ('R', [ 0b110, 0b101, 0b110, 0b101, 0b101 ]),
+    ('S', [ 0b011, 0b100, 0b010, 0b001, 0b110 ]),
+    ('T', [ 0b111, 0b010, 0b010, 0b010, 0b010 ]),
+    ('U', [ 0b101, 0b101, 0b101, 0b101, 0b111 ]),
+    ('V', [ 0b101, 0b101, 0b101, 0b101, 0b010 ]),
+    ('W', [ 0b101, 0b101, 0b111, 0b111, 0b101 ]),
+    ('X', [ 0b101, 0b101, 0b010, 0b101, 0b101 ]),
+    ('Y', [ 0b101, 0b101, 0b010, 0b010, 0b010 ]),
+    ('Z', [ 0b111, 0b001, 0b010, 0b100, 0b111 ]),
+    (' ', [ 0b000, 0b000, 0b000, 0b000, 0b000 ]),
+    ('.', [ 0b000, 0b000, 0b000, 0b000, 0b010 ]),
+    (',', [ 0b000, 0b000, 0b000, 0b010, 0b100 ]),
+    (':', [ 0b000, 0b010, 0b000, 0b010, 0b000 ]),
+    ('-', [ 0b000, 0b000, 0b111, 0b000, 0b000 ]),
+    ('_', [ 0b000, 0b000, 0b000, 0b000, 0b111 ]),
+    ('[', [ 0b110, 0b100, 0b100, 0b100, 0b110 ]),
+    (']', [ 0b011, 0b001, 0b001, 0b001, 0b011 ]),
+    ('/', [ 0b001, 0b001, 0b010, 0b100, 0b100 ]),
+    ('*', [ 0b000, 0b101, 0b010, 0b101, 0b000 ]),
+    ('+', [ 0b000, 0b010, 0b111, 0b010, 0b000 ]),
+    ('=', [ 0b000, 0b111, 0b000, 0b111, 0b000 ]),
+    ('<', [ 0b001, 0b010, 0b100, 0b010, 0b001 ]),
+    ('>', [ 0b100, 0b010, 0b001, 0b010, 0b100 ]),
+    ('(', [ 0b010, 0b100, 0b100, 0b100, 0b010 ]),
+    (')', [ 0b010, 0b001, 0b001, 0b001, 0b010 ]),
+    ('!', [ 0b010, 0b010, 0b010, 0b000, 0b010 ]),
+    ('&', [ 0b010, 0b101, 0b010, 0b101, 0b011 ]),
+    ('|', [ 0b010, 0b010, 0b010, 0b010, 0b010 ]),
+    ('?', UNKNOWN_GLYPH),
+];
+
+// Keypad keys as laid out on the COSMAC VIP, row by row
+pub const KEYPAD_LAYOUT : [u8; 16] = [ 0x1, 0x2, 0x3, 0xC,
+                                       0x4, 0x5, 0x6, 0xD,
+                                       0x7, 0x8, 0x9, 0xE,
+                                       0xA, 0x0, 0xB, 0xF ];
+
+// What the overlay shows of the running machine
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HudInfo
+{
+    pub fps         : f64,
+    // Instructions per second
+    pub ips         : f64,
+    pub pc          : usize,
+    // Word at the program counter and its disassembly
+    pub instruction : String,
+    pub registers   : [u8; NUM_DATA_REGISTERS],
+    pub i           : u16,
+    pub delay       : u8,
+    pub sound       : u8,
+    // One bit per pressed keypad key
+    pub keys        : u16,
+}
+
+impl HudInfo
+{
+    // Text of the overlay, one line per entry
+    pub fn lines(&self) -> Vec<String>
+    {
+        let registers = | range: std::ops::Range<usize> | range
+            .map(|index| format!("V{:X} {:02X}", index, self.registers[index]))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        vec![ format!("FPS {:.0}  IPS {:.0}", self.fps, self.ips),
+              format!("PC {:03X}  {}", self.pc, self.instruction),
+              registers(0..NUM_DATA_REGISTERS / 2),
+              registers(NUM_DATA_REGISTERS / 2..NUM_DATA_REGISTERS),
+              format!("I {:03X}  DT {:02X}  ST {:02X}", self.i, self.delay, self.sound) ]
+    }
+
+    pub fn is_key_pressed(&self, key: u8) -> bool
+    {
+        self.keys & (1 << key) != 0
+    }
+}
+
+// Lit pixels of the text in the overlay font, as (x, y) offsets from its
+// top left corner. Lowercase letters are drawn as uppercase.
+pub fn text_pixels(text: &str) -> Vec<(usize, usize)>
+{
+    let mut pixels = Vec::new();
+
+    for (index, character) in text.chars().enumerate()
+    {
+        let glyph = glyph(character.to_ascii_uppercase());
+
+        for (y, row) in glyph.iter().enumerate()
+        {
+            for x in 0..GLYPH_WIDTH
+            {
+                if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0
+                {
+                    pixels.push((index * CHAR_ADVANCE + x, y));
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+// Frames and instructions per second, measured over one second periods
+pub struct RateMeter
+{
+    start        : Instant,
+    frames       : u32,
+    instructions : u64,
+    fps          : f64,
+    ips          : f64,
+}
+
+impl RateMeter
+{
+    const PERIOD : Duration = Duration::from_secs(1);
+
+    pub fn new() -> Self
+    {
+        RateMeter { start: Instant::now(), frames: 0, instructions: 0, fps: 0.0, ips: 0.0 }
+    }
+
+    // Counts a frame, given the instructions executed since the start
+    pub fn frame(&mut self, instructions: u64)
+    {
+        self.frames += 1;
+
+        let elapsed = self.start.elapsed();
+
+        if elapsed < Self::PERIOD
+        {
+            return;
+        }
+
+        let seconds = elapsed.as_secs_f64();
+
+        self.fps          = self.frames as f64 / seconds;
+        self.ips          = instructions.saturating_sub(self.instructions) as f64 / seconds;
+        self.frames       = 0;
+        self.instructions = instructions;
+        self.start        = Instant::now();
+    }
+
+    pub fn fps(&self) -> f64
+    {
+        self.fps
+    }
+
+    pub fn ips(&self) -> f64
+    {
+        self.ips
+    }
+}
+
+impl Default for RateMeter
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+fn glyph(character: char) -> [u8; GLYPH_HEIGHT]
+{
+    GLYPHS.iter()
+          .find(|(glyph_char, _)| *glyph_char == character)
+          .map(|(_, glyph)| *glyph)
+          .unwrap_or(UNKNOWN_GLYPH)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn text_rendering()
+    {
+        // The 1 glyph, then an L moved one character to the right
+        let pixels = text_pixels("1l");
+
+        assert!(pixels.contains(&(1, 0)));
+        assert!(pixels.contains(&(CHAR_ADVANCE, 4)));
+        assert!(pixels.contains(&(CHAR_ADVANCE + 2, 4)));
+        assert_eq!(pixels.len(), 8 + 7);
+
+        assert!(text_pixels("  ").is_empty());
+        assert_eq!(text_pixels("~"), text_pixels("?"));
+    }
+
+    #[test]
+    fn hud_lines()
+    {
+        let mut registers = [0; NUM_DATA_REGISTERS];
+        registers[0xF]    = 0x01;
+
+        let info = HudInfo { fps: 59.9, ips: 480.0, pc: 0x234, instruction: String::from("00E0 CLEAR"),
+                             registers, i: 0x2A0, delay: 0x3C, sound: 0, keys: 1 << 0xA };
+
+        let lines = info.lines();
+
+        assert_eq!(lines[0], "FPS 60  IPS 480");
+        assert_eq!(lines[1], "PC 234  00E0 CLEAR");
+        assert_eq!(lines[3], "V8 00 V9 00 VA 00 VB 00 VC 00 VD 00 VE 00 VF 01");
+        assert_eq!(lines[4], "I 2A0  DT 3C  ST 00");
+
+        assert!(info.is_key_pressed(0xA));
+        assert!(!info.is_key_pressed(0x0));
+    }
+}
@@ -0,0 +1,108 @@
+use sdl2::pixels::Color;
+
+// Colors used when a pixel grid has to be rendered outside of the
+// tileset, e.g. when recording. Defaults match the tileset greens.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette
+{
+    pub on  : Color,
+    pub off : Color,
+}
+
+impl Palette
+{
+    pub fn new(on: Color, off: Color) -> Self
+    {
+        Palette { on, off }
+    }
+
+    pub fn color(&self, pixel: bool) -> Color
+    {
+        if pixel { self.on } else { self.off }
+    }
+
+    // Lit and unlit colors in the format read by parse()
+    pub fn spec(&self) -> String
+    {
+        let hex = | color: Color | format!("{:02X}{:02X}{:02X}", color.r, color.g, color.b);
+        format!("{},{}", hex(self.on), hex(self.off))
+    }
+
+    // Reads a palette name, or the lit and unlit colors in hexadecimal,
+    // e.g. "amber" or "FFFFFF,000000"
+    pub fn parse(spec: &str) -> Result<Self, String>
+    {
+        if let Some((_, palette)) = PRESETS.iter().find(|(name, _)| *name == spec)
+        {
+            return Ok(*palette);
+        }
+
+        let colors: Vec<&str> = spec.split(',').map(str::trim).collect();
+
+        match colors.as_slice()
+        {
+            [on, off] => Ok(Palette::new(parse_color(on)?, parse_color(off)?)),
+            _         => Err(format!("Invalid palette '{}'. Use ON,OFF colors like FFFFFF,000000 \
+                                      or one of {}", spec, preset_names())),
+        }
+    }
+}
+
+const PRESETS : [(&str, Palette); 3] =
+[
+    ("green", Palette { on: Color { r: 0x7B, g: 0xDA, b: 0x86, a: 0xFF },
+                        off: Color { r: 0x17, g: 0x3D, b: 0x24, a: 0xFF } }),
+    ("white", Palette { on: Color { r: 0xFF, g: 0xFF, b: 0xFF, a: 0xFF },
+                        off: Color { r: 0x00, g: 0x00, b: 0x00, a: 0xFF } }),
+    ("amber", Palette { on: Color { r: 0xFF, g: 0xB0, b: 0x00, a: 0xFF },
+                        off: Color { r: 0x2B, g: 0x1A, b: 0x00, a: 0xFF } }),
+];
+
+fn preset_names() -> String
+{
+    PRESETS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+}
+
+// Color written as RRGGBB, optionally starting with '#'
+fn parse_color(text: &str) -> Result<Color, String>
+{
+    let hex = text.strip_prefix('#').unwrap_or(text);
+
+    match (hex.len(), u32::from_str_radix(hex, 16))
+    {
+        (6, Ok(rgb)) => Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)),
+        _            => Err(format!("Invalid color '{}'", text)),
+    }
+}
+
+impl Default for Palette
+{
+    fn default() -> Self
+    {
+        PRESETS[0].1
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parsing() -> Result<(), String>
+    {
+        assert_eq!(Palette::parse("green")?, Palette::default());
+
+        let palette = Palette::parse("#FF8000, 102030")?;
+        assert_eq!(palette.on,  Color::RGB(0xFF, 0x80, 0x00));
+        assert_eq!(palette.off, Color::RGB(0x10, 0x20, 0x30));
+
+        assert_eq!(Palette::parse(&palette.spec())?, palette);
+
+        assert!(Palette::parse("FF8000").is_err(), "Single color accepted");
+        assert!(Palette::parse("FF80,000000").is_err(), "Short color accepted");
+        assert!(Palette::parse("purple").is_err(), "Unknown preset accepted");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+use crate::platform::Platform;
+
+pub const SMALL_GLYPH_SIZE     : usize = 5;
+pub const BIG_GLYPH_SIZE       : usize = 10;
+pub const NUM_SMALL_GLYPHS     : usize = 0xF + 1;
+pub const DEFAULT_FONT_ADDRESS : usize = 0x000;
+
+// Names accepted by FontSet::parse()
+pub const FONT_NAMES : [&str; 5] = [ "chip48", "vip", "dream6800", "eti660", "schip" ];
+
+type SmallFont = [u8; NUM_SMALL_GLYPHS * SMALL_GLYPH_SIZE];
+
+// 4x5 font used by CHIP-48 and SCHIP. Most modern interpreters ship it.
+pub static DEFAULT_SPRITES : SmallFont =
+[
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// Font found in the COSMAC VIP interpreter ROM
+static COSMAC_VIP_SPRITES : SmallFont =
+[
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0x70, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// 3x5 font of the DREAM 6800 monitor
+static DREAM_6800_SPRITES : SmallFont =
+[
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+// 3x5 font of the ETI-660 interpreter
+static ETI_660_SPRITES : SmallFont =
+[
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+// 8x10 digits of the SCHIP 1.1 interpreter. Only 0-9 are defined.
+static SCHIP_BIG_SPRITES : [u8; 10 * BIG_GLYPH_SIZE] =
+[
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FontSet
+{
+    Chip48,
+    CosmacVip,
+    Dream6800,
+    Eti660,
+    // CHIP-48 small font plus the big SCHIP digits
+    Schip,
+}
+
+impl FontSet
+{
+    pub fn parse(name: &str) -> Result<Self, String>
+    {
+        use FontSet::*;
+
+        match name.to_lowercase().as_str()
+        {
+            "chip48"                => Ok(Chip48),
+            "vip" | "cosmac-vip"    => Ok(CosmacVip),
+            "dream6800"             => Ok(Dream6800),
+            "eti660"                => Ok(Eti660),
+            "schip" | "superchip"   => Ok(Schip),
+            _                       => Err(format!("Unknown font '{}'. Valid fonts are {}",
+                                                   name, FONT_NAMES.join(", "))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        use FontSet::*;
+
+        match self
+        {
+            Chip48    => "chip48",
+            CosmacVip => "vip",
+            Dream6800 => "dream6800",
+            Eti660    => "eti660",
+            Schip     => "schip",
+        }
+    }
+
+    pub fn for_platform(platform: Platform) -> Self
+    {
+        use Platform::*;
+
+        match platform
+        {
+            CosmacVip      => FontSet::CosmacVip,
+            Dream6800      => FontSet::Dream6800,
+            Eti660         => FontSet::Eti660,
+            Schip | XoChip => FontSet::Schip,
+        }
+    }
+
+    pub fn small_glyphs(&self) -> &'static [u8]
+    {
+        use FontSet::*;
+
+        match self
+        {
+            Chip48 | Schip => &DEFAULT_SPRITES,
+            CosmacVip      => &COSMAC_VIP_SPRITES,
+            Dream6800      => &DREAM_6800_SPRITES,
+            Eti660         => &ETI_660_SPRITES,
+        }
+    }
+
+    pub fn big_glyphs(&self) -> &'static [u8]
+    {
+        match self
+        {
+            FontSet::Schip => &SCHIP_BIG_SPRITES,
+            _              => &[],
+        }
+    }
+}
+
+// Which font is loaded in system memory and where. The big glyphs,
+// if any, are stored right after the small ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontConfig
+{
+    pub font_set : FontSet,
+    pub address  : usize,
+}
+
+impl FontConfig
+{
+    pub fn new(font_set: FontSet, address: usize) -> Self
+    {
+        FontConfig { font_set, address }
+    }
+
+    pub fn for_platform(platform: Platform) -> Self
+    {
+        Self::new(FontSet::for_platform(platform), DEFAULT_FONT_ADDRESS)
+    }
+
+    pub fn size(&self) -> usize
+    {
+        self.font_set.small_glyphs().len() + self.font_set.big_glyphs().len()
+    }
+
+    // Address used by FX29. Only the lowest nibble selects the glyph.
+    pub fn small_glyph_address(&self, digit: u8) -> u16
+    {
+        (self.address + (digit & 0xF) as usize * SMALL_GLYPH_SIZE) as u16
+    }
+
+    // Address used by the SCHIP FX30 instruction
+    pub fn big_glyph_address(&self, digit: u8) -> Result<u16, String>
+    {
+        let num_big_glyphs = self.font_set.big_glyphs().len() / BIG_GLYPH_SIZE;
+
+        if digit as usize >= num_big_glyphs
+        {
+            return Err(format!("Font {:?} has no big glyph for digit {:X}",
+                               self.font_set, digit));
+        }
+
+        let big_font_address = self.address + self.font_set.small_glyphs().len();
+
+        Ok((big_font_address + digit as usize * BIG_GLYPH_SIZE) as u16)
+    }
+}
+
+impl Default for FontConfig
+{
+    fn default() -> Self
+    {
+        Self::new(FontSet::Chip48, DEFAULT_FONT_ADDRESS)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn glyph_addresses() -> Result<(), String>
+    {
+        let font = FontConfig::new(FontSet::Schip, 0x50);
+
+        assert_eq!(font.small_glyph_address(0x0), 0x50);
+        assert_eq!(font.small_glyph_address(0xA), 0x50 + 0xA * 5);
+
+        // Only the lowest nibble is taken into account
+        assert_eq!(font.small_glyph_address(0x1F), font.small_glyph_address(0xF));
+
+        // Big glyphs come right after the small ones
+        assert_eq!(font.big_glyph_address(0)?, 0x50 + 80);
+        assert_eq!(font.big_glyph_address(9)?, 0x50 + 80 + 90);
+        assert!(font.big_glyph_address(0xA).is_err());
+
+        assert_eq!(font.size(), 180);
+
+        Ok(())
+    }
+
+    #[test]
+    fn font_without_big_glyphs()
+    {
+        for font_set in [FontSet::Chip48, FontSet::CosmacVip,
+                         FontSet::Dream6800, FontSet::Eti660].iter()
+        {
+            let font = FontConfig::new(*font_set, 0);
+
+            assert_eq!(font.size(), NUM_SMALL_GLYPHS * SMALL_GLYPH_SIZE,
+                       "Unexpected size for {:?}", font_set);
+            assert!(font.big_glyph_address(0).is_err(),
+                    "{:?} should not have big glyphs", font_set);
+        }
+    }
+
+    #[test]
+    fn platform_fonts()
+    {
+        assert_eq!(FontSet::for_platform(Platform::CosmacVip), FontSet::CosmacVip);
+        assert_eq!(FontSet::for_platform(Platform::Dream6800), FontSet::Dream6800);
+        assert_eq!(FontSet::for_platform(Platform::Eti660),    FontSet::Eti660);
+        assert_eq!(FontSet::for_platform(Platform::Schip),     FontSet::Schip);
+        assert_eq!(FontSet::for_platform(Platform::XoChip),    FontSet::Schip);
+    }
+
+    #[test]
+    fn names() -> Result<(), String>
+    {
+        for &name in FONT_NAMES.iter()
+        {
+            assert_eq!(FontSet::parse(name)?.name(), name);
+        }
+
+        assert_eq!(FontSet::parse("SCHIP")?, FontSet::Schip);
+        assert!(FontSet::parse("comic-sans").is_err());
+
+        Ok(())
+    }
+}
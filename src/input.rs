@@ -1,31 +1,109 @@
 
 use sdl2::keyboard::{ Scancode, KeyboardState };
+use sdl2::event::{ Event, WindowEvent };
+use sdl2::mouse::MouseButton;
 use sdl2::EventPump;
 
-const NUM_KEYS_KEYPAD : u8 = 16;
+pub const NUM_KEYS_KEYPAD : u8 = 16;
 
-type Keymap = [Scancode; NUM_KEYS_KEYPAD as usize];
+// Keyboard key bound to each keypad key, from 0 to F
+pub type Keymap = [Scancode; NUM_KEYS_KEYPAD as usize];
 
-const DEFAULT_KEY_MAPPING : Keymap = [ Scancode::Q, Scancode::W, Scancode::E, Scancode::R, Scancode::T,
+pub const DEFAULT_KEY_MAPPING : Keymap = [ Scancode::Q, Scancode::W, Scancode::E, Scancode::R, Scancode::T,
                                        Scancode::Y, Scancode::U, Scancode::I, Scancode::O, Scancode::P,
                                        Scancode::A, Scancode::S, Scancode::D, Scancode::F, Scancode::G,
                                        Scancode::H
                                      ];
 
+// The COSMAC VIP keypad layout on the left of the keyboard:
+//   1 2 3 C      1 2 3 4
+//   4 5 6 D      Q W E R
+//   7 8 9 E      A S D F
+//   A 0 B F      Z X C V
+pub const COSMAC_KEY_MAPPING : Keymap = [ Scancode::X, Scancode::Num1, Scancode::Num2, Scancode::Num3,
+                                          Scancode::Q, Scancode::W, Scancode::E, Scancode::A,
+                                          Scancode::S, Scancode::D, Scancode::Z, Scancode::C,
+                                          Scancode::Num4, Scancode::R, Scancode::F, Scancode::V
+                                        ];
+
+// Emulator actions bound to keys outside the keypad
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hotkey
+{
+    Mute,
+    // Window closed
+    Quit,
+    TogglePause,
+    // Runs a single frame while paused
+    FrameAdvance,
+    SpeedUp,
+    SlowDown,
+    NormalSpeed,
+    // Uncapped speed while held
+    Turbo,
+    // Restarts the rom from its power-on state
+    Reset,
+    // Reads the rom file again and restarts it
+    ReloadRom,
+    // Shows or hides the overlay with the machine state
+    ToggleHud,
+    // Opens or closes the debugger window
+    ToggleDebugger,
+    // Runs a single instruction and pauses
+    Step,
+    // Scrolls the memory viewer of the debugger
+    MemoryPageUp,
+    MemoryPageDown,
+    // Starts or stops recording the screen and the buzzer
+    ToggleRecording,
+}
+
+// Mouse and window events, tagged with the id of their window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowInput
+{
+    // Left button pressed at a position in the window
+    Click { window: u32, x: i32, y: i32 },
+    Close { window: u32 },
+}
+
+const DEFAULT_HOTKEYS : [(Scancode, Hotkey); 15] = [ (Scancode::M,        Hotkey::Mute),
+                                                    (Scancode::Space,    Hotkey::TogglePause),
+                                                    (Scancode::N,        Hotkey::FrameAdvance),
+                                                    (Scancode::Equals,   Hotkey::SpeedUp),
+                                                    (Scancode::Minus,    Hotkey::SlowDown),
+                                                    (Scancode::Num0,     Hotkey::NormalSpeed),
+                                                    (Scancode::Tab,      Hotkey::Turbo),
+                                                    (Scancode::F5,       Hotkey::Reset),
+                                                    (Scancode::F6,       Hotkey::ReloadRom),
+                                                    (Scancode::F1,       Hotkey::ToggleHud),
+                                                    (Scancode::F2,       Hotkey::ToggleDebugger),
+                                                    (Scancode::F7,       Hotkey::Step),
+                                                    (Scancode::PageUp,   Hotkey::MemoryPageUp),
+                                                    (Scancode::PageDown, Hotkey::MemoryPageDown),
+                                                    (Scancode::F8,       Hotkey::ToggleRecording),
+                                                  ];
+
 pub struct Keypad
 {
     events : EventPump,
     keymap : Keymap,
+    // Window events read by poll_hotkeys(), see take_window_input()
+    input  : Vec<WindowInput>,
 }
 
 impl Keypad
 {
     pub fn new(context : &sdl2::Sdl) -> Result<Self, String>
+    {
+        Self::with_keymap(context, DEFAULT_KEY_MAPPING)
+    }
+
+    pub fn with_keymap(context : &sdl2::Sdl, keymap : Keymap) -> Result<Self, String>
     {
         let events = context.event_pump()?;
-        let keymap = DEFAULT_KEY_MAPPING;
 
-        Ok( Keypad { events, keymap } )
+        Ok( Keypad { events, keymap, input: Vec::new() } )
     }
 
     pub fn wait_for_key_pressed(&self) -> u8
@@ -50,10 +128,113 @@ impl Keypad
         return keyboard.is_scancode_pressed(scan_code);
     }
 
+    // State of the whole keypad, one bit per key
+    pub fn pressed_keys(&self) -> u16
+    {
+        (0..NUM_KEYS_KEYPAD).filter(|&hex| self.is_key_pressed(hex))
+                            .fold(0, |keys, hex| keys | 1 << hex)
+    }
+
     pub fn to_scancode(&self, hex: u8) -> Scancode
     {
         return self.keymap[hex as usize];
     }
+
+    // Whether the key bound to a hotkey is currently held down
+    pub fn is_hotkey_held(&self, hotkey: Hotkey) -> bool
+    {
+        let keyboard = self.events.keyboard_state();
+
+        DEFAULT_HOTKEYS.iter()
+            .filter(|(_, bound)| *bound == hotkey)
+            .any(|(scancode, _)| keyboard.is_scancode_pressed(*scancode))
+    }
+
+    // Drains the pending events, returning the hotkeys pressed since the
+    // last call. Held keys are only reported once.
+    pub fn poll_hotkeys(&mut self) -> Vec<Hotkey>
+    {
+        let mut hotkeys = Vec::new();
+
+        for event in self.events.poll_iter()
+        {
+            match event
+            {
+                Event::Quit { .. } => hotkeys.push(Hotkey::Quit),
+
+                Event::KeyDown { scancode: Some(scancode), repeat: false, .. } =>
+                    hotkeys.extend(to_hotkey(scancode)),
+
+                Event::MouseButtonDown { window_id, mouse_btn: MouseButton::Left, x, y, .. } =>
+                    self.input.push(WindowInput::Click { window: window_id, x, y }),
+
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } =>
+                    self.input.push(WindowInput::Close { window: window_id }),
+
+                _ => (),
+            }
+        }
+
+        return hotkeys;
+    }
+
+    // Clicks and window closings seen by poll_hotkeys() since the last call
+    pub fn take_window_input(&mut self) -> Vec<WindowInput>
+    {
+        std::mem::take(&mut self.input)
+    }
+}
+
+// Reads a keymap name, "default" or "cosmac", or the 16 keyboard keys
+// bound to the keypad keys 0 to F, separated by commas
+pub fn parse_keymap(spec: &str) -> Result<Keymap, String>
+{
+    match spec
+    {
+        "default" => return Ok(DEFAULT_KEY_MAPPING),
+        "cosmac"  => return Ok(COSMAC_KEY_MAPPING),
+        _         => (),
+    }
+
+    let names: Vec<&str> = spec.split(',').map(str::trim).collect();
+
+    if names.len() != NUM_KEYS_KEYPAD as usize
+    {
+        return Err(format!("Invalid keymap '{}'. Use 'default', 'cosmac' or {} comma separated keys",
+                           spec, NUM_KEYS_KEYPAD));
+    }
+
+    let mut keymap = DEFAULT_KEY_MAPPING;
+
+    for (key, name) in keymap.iter_mut().zip(names)
+    {
+        *key = Scancode::from_name(name).ok_or(format!("Unknown key '{}'", name))?;
+    }
+
+    Ok(keymap)
+}
+
+// Keymap in the format read by parse_keymap()
+pub fn keymap_spec(keymap: &Keymap) -> String
+{
+    if *keymap == DEFAULT_KEY_MAPPING
+    {
+        return String::from("default");
+    }
+
+    if *keymap == COSMAC_KEY_MAPPING
+    {
+        return String::from("cosmac");
+    }
+
+    keymap.iter().map(|key| key.name()).collect::<Vec<_>>().join(",")
+}
+
+fn to_hotkey(scancode: Scancode) -> Option<Hotkey>
+{
+    DEFAULT_HOTKEYS.iter()
+        .find(|(key, _)| *key == scancode)
+        .map(|(_, hotkey)| *hotkey)
 }
 
 #[cfg(test)]
@@ -77,4 +258,32 @@ mod tests
 
         Ok(())
     }
+
+    #[test]
+    fn hotkeys_out_of_keypad()
+    {
+        assert_eq!(to_hotkey(Scancode::M), Some(Hotkey::Mute));
+        assert_eq!(to_hotkey(Scancode::Q), None);
+
+        // Hotkeys cannot be keypad keys
+        for (scancode, _) in DEFAULT_HOTKEYS.iter()
+        {
+            assert!(!DEFAULT_KEY_MAPPING.contains(scancode),
+                    "Hotkey {:?} is also a keypad key", scancode);
+        }
+    }
+
+    #[test]
+    fn keymap_parsing() -> Result<(), String>
+    {
+        assert_eq!(parse_keymap("cosmac")?, COSMAC_KEY_MAPPING);
+
+        let keymap = parse_keymap("X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,V")?;
+        assert_eq!(keymap, COSMAC_KEY_MAPPING);
+
+        assert!(parse_keymap("X,1,2").is_err(), "Short keymap accepted");
+        assert!(parse_keymap("X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,Nope").is_err(), "Unknown key accepted");
+
+        Ok(())
+    }
 }
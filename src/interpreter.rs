@@ -1,134 +1,1305 @@
 
-use std::fs;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
 
+use rand::{ SeedableRng, rngs::StdRng };
 use sdl2::Sdl;
 
-use crate::memory::{ Ram, ProgramCounter };
+use crate::memory::{ Ram, ProgramCounter, MemoryMap, AddressPolicy, INSTRUCTION_SIZE };
 use crate::opcodes::OpCode;
-use crate::display::Display;
-use crate::input::Keypad;
-use crate::audio::Speakers;
-use crate::registers::{ IRegister, DataRegister, AllDataRegisters };
+use crate::display::{ Display, GridEditor, HudInfo, Palette, PixelGrid, RateMeter, SpriteMode };
+use crate::input::{ Keypad, Hotkey, WindowInput, NUM_KEYS_KEYPAD };
+use crate::config::Config;
+use crate::analysis::{ Detection, RomAnalysis };
+use crate::loader::{ self, ReloadMode, RomFile };
+use crate::quirks::Quirks;
+use crate::platform::Platform;
+use crate::audio::{ AudioSink, OfflineAudio, Speakers, ToneControl, DEFAULT_SAMPLE_RATE };
+use crate::registers::{ IRegister, DataRegister, AllDataRegisters, NUM_DATA_REGISTERS };
 use crate::stack::Stack;
-use crate::timer::Timer;
+use crate::timer::{ Timer, TimerStatus };
 use crate::clock::*;
+use crate::recorder::{ Recorder, RecordingFormat, DEFAULT_RECORDING_SCALE };
+use crate::font::FontConfig;
+use crate::timing::{ self, TimingModel, VIP_AVAILABLE_CYCLES };
+use crate::trace::{ MachineState, Tracer };
+use crate::debugger::{ DebugAction, DebugView, Debugger, WatchContext, Watchpoint };
+use crate::profile::Profiler;
 
-//mod instructions;
+mod instructions;
+
+// How long notices stay in the window title
+const NOTICE_DURATION   : Duration = Duration::from_secs(2);
+
+// How often the rom file is checked for changes with hot reload
+const ROM_POLL_INTERVAL : Duration = Duration::from_millis(500);
+
+// Window and keyboard. Missing when running headless.
+struct Frontend
+{
+    context  : Sdl,
+    display  : Display,
+    keypad   : Keypad,
+    // Debugger window, while open
+    debugger : Option<Debugger>,
+}
 
 pub struct Interpreter
 {
     ram            : Ram,
     pc             : ProgramCounter,
-    context        : Sdl,
-    display        : Display,
-    keypad         : Keypad,
-    speakers       : Speakers,
+    frontend       : Option<Frontend>,
+    screen         : GridEditor,
+    // One bit per keypad key, set while the key is held
+    keys           : u16,
+    audio          : Box<dyn AudioSink>,
+    rng            : StdRng,
     i_register     : IRegister,
     data_registers : AllDataRegisters,
     stack          : Stack,
-    cpu_limiter    : RateLimiter,
-    timer_limiter  : RateLimiter,
+    scheduler      : FrameScheduler,
     delay_timer    : Timer,
     sound_timer    : Timer,
+    recorder       : Option<Recorder>,
+    // Colors of the recordings, and where the record hotkey saves them
+    palette        : Palette,
+    record_to      : Option<RecordingFormat>,
+    running        : bool,
+    timing         : TimingModel,
+    quirks         : Quirks,
+    // Machine cycles run so far, and the ones taken from the next
+    // frame by an instruction that did not fit in the current one
+    cycles         : u64,
+    cycle_debt     : u32,
+    tracer         : Option<Tracer>,
+    // Instructions executed since the interpreter was created
+    instructions   : u64,
+    profiler       : Option<Profiler>,
+    // Loaded rom, and the file it was read from
+    rom            : Vec<u8>,
+    rom_file       : Option<RomFile>,
+    // Reloads the rom when its file changes, see check_rom_file()
+    hot_reload     : Option<ReloadMode>,
+    rom_modified   : Option<SystemTime>,
+    rom_polled     : Instant,
+    // Message shown for a while in the window, e.g. after a reset
+    notice         : Option<(String, Instant)>,
+    // Whether load_rom() picks the platform from the rom contents, and
+    // what it found the last time
+    detect_platform: bool,
+    detection      : Option<Detection>,
+    // Quirks, and memory map or font, chosen explicitly, kept whatever
+    // the platform detected
+    quirks_given   : bool,
+    map_given      : bool,
+    // Overlay with the machine state, and the rates it shows
+    hud_visible    : bool,
+    meter          : RateMeter,
+    // Addresses that pause the interpreter when reached, and the one it
+    // is stopped at, which runs when resumed
+    breakpoints    : BTreeSet<usize>,
+    break_address  : Option<usize>,
+    // Conditions that pause the interpreter when they become true
+    watchpoints    : Vec<Watchpoint>,
 }
 
-// Public
-impl Interpreter
-{
-    pub fn new() -> Result<Self, String>
+// Public
+impl Interpreter
+{
+    pub fn new() -> Result<Self, String>
+    {
+        Self::with_memory_map(MemoryMap::default())
+    }
+
+    pub fn with_memory_map(map: MemoryMap) -> Result<Self, String>
+    {
+        let context  = sdl2::init()?;
+        let display  = Display::from_context(&context)?;
+        let keypad   = Keypad::new(&context)?;
+        let speakers = Speakers::new(&context)?;
+        let given    = map != MemoryMap::default();
+
+        let mut interpreter = Self::build(map, Some(Frontend { context, display, keypad, debugger: None }),
+                                          Box::new(speakers))?;
+
+        interpreter.map_given = given;
+        Ok(interpreter)
+    }
+
+    // Window, keypad and interpreter settings taken from the config
+    pub fn with_config(config: &Config) -> Result<Self, String>
+    {
+        config.validate()?;
+
+        let context     = sdl2::init()?;
+        let vsync       = config.frame_sync == FrameSync::Vsync;
+        let mut display = Display::with_scale(&context, config.scale, vsync)?;
+        let keypad      = Keypad::with_keymap(&context, config.keymap)?;
+        let speakers    = Speakers::new(&context)?;
+
+        display.set_palette(config.palette);
+
+        let frontend        = Frontend { context, display, keypad, debugger: None };
+        let mut interpreter = Self::build(config.memory_map(), Some(frontend), Box::new(speakers))?;
+
+        interpreter.apply_config(config)?;
+        Ok(interpreter)
+    }
+
+    // Interpreter without window, keyboard or audio device, driven with
+    // run_frames() and set_key(). The buzzer is rendered offline.
+    pub fn headless() -> Result<Self, String>
+    {
+        Self::headless_with_memory_map(MemoryMap::default())
+    }
+
+    pub fn headless_with_memory_map(map: MemoryMap) -> Result<Self, String>
+    {
+        let audio = OfflineAudio::new(DEFAULT_SAMPLE_RATE as u32);
+        let given = map != MemoryMap::default();
+
+        let mut interpreter = Self::build(map, None, Box::new(audio))?;
+
+        interpreter.map_given = given;
+        Ok(interpreter)
+    }
+
+    // Headless interpreter with the settings of the config. The window
+    // and keyboard ones are ignored.
+    pub fn headless_with_config(config: &Config) -> Result<Self, String>
+    {
+        config.validate()?;
+
+        let mut interpreter = Self::headless_with_memory_map(config.memory_map())?;
+
+        interpreter.apply_config(config)?;
+        Ok(interpreter)
+    }
+
+    pub fn is_headless(&self) -> bool
+    {
+        self.frontend.is_none()
+    }
+
+    // Loads a rom file, the standard input with "-", or the only rom of
+    // a zip archive
+    pub fn load_rom(&mut self, rom_file: &str) -> Result<(), String>
+    {
+        let file     = RomFile::new(rom_file, None);
+        let contents = file.read(self.ram.map().program_start)?;
+
+        self.load_rom_bytes(&contents)?;
+
+        if rom_file != loader::STDIN_PATH
+        {
+            self.set_rom_file(file);
+        }
+
+        Ok(())
+    }
+
+    // Loads a rom, resetting the machine to its power-on state first, so
+    // another rom can be loaded without creating a new interpreter
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) -> Result<(), String>
+    {
+        let detection = match self.detect_platform
+        {
+            true  => Some(self.detect_supported(rom)?),
+            false => None,
+        };
+
+        let map = match &detection
+        {
+            Some(detection) => self.detected_map(detection),
+            None            => self.ram.map().clone(),
+        };
+
+        // The current rom keeps running if the new one does not fit
+        let mut ram = Ram::with_map(map)?;
+        ram.dump(&rom.to_vec())?;
+
+        if let Some(detection) = &detection
+        {
+            if !self.quirks_given
+            {
+                self.use_quirks(detection.platform.map(Quirks::for_platform).unwrap_or_default());
+            }
+        }
+
+        self.power_on(ram);
+        self.detection = detection;
+        self.rom       = rom.to_vec();
+        self.rom_file  = None;
+
+        Ok(())
+    }
+
+    // File the loaded rom was read from, read again by reload_rom()
+    pub fn set_rom_file(&mut self, file: RomFile)
+    {
+        self.rom_modified = file.modified();
+        self.rom_file     = Some(file);
+    }
+
+    // Makes start() reload the rom when its file changes. Sources are
+    // assembled again.
+    pub fn set_hot_reload(&mut self, mode: Option<ReloadMode>)
+    {
+        self.hot_reload = mode;
+    }
+
+    // Reloads the rom if hot reload is enabled and the rom file changed
+    // since it was read. Returns how it was reloaded. A file that fails
+    // to load is not read again until it changes.
+    pub fn check_rom_file(&mut self) -> Result<Option<ReloadMode>, String>
+    {
+        let (mode, file) = match (self.hot_reload, self.rom_file.clone())
+        {
+            (Some(mode), Some(file)) => (mode, file),
+            _                        => return Ok(None),
+        };
+
+        let modified = file.modified();
+
+        if modified.is_none() || modified == self.rom_modified
+        {
+            return Ok(None);
+        }
+
+        self.rom_modified = modified;
+
+        let rom = file.read(self.ram.map().program_start)?;
+
+        let mode = match mode == ReloadMode::KeepState && self.patch_rom(&rom)
+        {
+            true  => ReloadMode::KeepState,
+            false => { self.load_rom_bytes(&rom)?; ReloadMode::Reset },
+        };
+
+        self.rom_file = Some(file);
+        Ok(Some(mode))
+    }
+
+    // Restarts the loaded rom from the power-on state: memory holding
+    // only the font and the rom, and cleared registers, stack, timers
+    // and screen
+    pub fn reset(&mut self) -> Result<(), String>
+    {
+        let mut ram = Ram::with_map(self.ram.map().clone())?;
+        ram.dump(&self.rom)?;
+
+        self.power_on(ram);
+        Ok(())
+    }
+
+    // Reads the rom file again, e.g. after editing it, and restarts it
+    pub fn reload_rom(&mut self) -> Result<(), String>
+    {
+        let file = self.rom_file.clone().ok_or("The rom was not loaded from a file")?;
+        let rom  = file.read(self.ram.map().program_start)?;
+
+        self.load_rom_bytes(&rom)?;
+        self.set_rom_file(file);
+
+        Ok(())
+    }
+
+    // Seeds the random numbers of CXNN, so runs can be reproduced
+    pub fn set_seed(&mut self, seed: u64)
+    {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    // Presses or releases a keypad key. Keys are read from the keyboard
+    // every frame unless the interpreter is headless.
+    pub fn set_key(&mut self, key: u8, pressed: bool) -> Result<(), String>
+    {
+        if key >= NUM_KEYS_KEYPAD
+        {
+            return Err(format!("Invalid key {:#X}", key));
+        }
+
+        match pressed
+        {
+            true  => self.keys |= 1 << key,
+            false => self.keys &= !(1 << key),
+        }
+
+        Ok(())
+    }
+
+    // Emulates the given amount of frames as fast as possible
+    pub fn run_frames(&mut self, frames: u32) -> Result<(), String>
+    {
+        for _ in 0..frames
+        {
+            self.cpu_cycle()?;
+            self.present_frame()?;
+
+            if self.break_address.is_some()
+            {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn screen(&self) -> &PixelGrid
+    {
+        self.screen.grid()
+    }
+
+    pub fn is_sound_playing(&self) -> bool
+    {
+        self.audio.is_playing()
+    }
+
+    // Buzzer output rendered so far, which only headless interpreters keep
+    pub fn offline_audio(&self) -> Option<&OfflineAudio>
+    {
+        self.audio.offline()
+    }
+
+    // Selects the font stored in system memory, used by FX29 and FX30.
+    // It's kept when load_rom() detects the platform.
+    pub fn set_font(&mut self, font: FontConfig) -> Result<(), String>
+    {
+        self.ram.set_font(font)?;
+        self.map_given = true;
+
+        Ok(())
+    }
+
+    // Behaviors that differ between platforms, see Quirks. They are kept
+    // when load_rom() detects the platform.
+    pub fn set_quirks(&mut self, quirks: Quirks)
+    {
+        self.use_quirks(quirks);
+        self.quirks_given = true;
+    }
+
+    pub fn quirks(&self) -> Quirks
+    {
+        self.quirks
+    }
+
+    // Makes load_rom() scan the rom and switch to the SCHIP or XO-CHIP
+    // memory map, font and quirks when it uses their instructions. It's
+    // enabled when created from a config without platform.
+    pub fn set_platform_detection(&mut self, enabled: bool)
+    {
+        self.detect_platform = enabled;
+    }
+
+    // Platform found by the last load_rom(), when detection is enabled
+    pub fn detection(&self) -> Option<&Detection>
+    {
+        self.detection.as_ref()
+    }
+
+    // Decides what happens when a jump, call or skip leaves program memory
+    pub fn set_address_policy(&mut self, policy: AddressPolicy)
+    {
+        self.pc.set_policy(policy);
+    }
+
+    // Lists the last executed instructions, to be printed when
+    // the interpreter stops because of an error
+    pub fn crash_report(&self) -> String
+    {
+        let memory  = self.ram.peek();
+        let history = self.pc.history();
+
+        let mut report = format!("Last {} executed instructions:\n", history.len());
+
+        for &address in history.iter()
+        {
+            let msb = memory[address];
+            let lsb = memory[(address + 1) % memory.len()];
+
+            let disassembly = match OpCode::new(msb, lsb)
+            {
+                Ok(opcode) => opcode.disassembly(),
+                Err(_)     => String::from("???"),
+            };
+
+            report += &format!("{:#06X}: {:02X}{:02X}  {}\n", address, msb, lsb, disassembly);
+        }
+
+        return report;
+    }
+
+    // Instructions executed before each timers tick. Most roms expect
+    // between 8 and 20, SCHIP and XO-CHIP ones usually many more.
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) -> Result<(), String>
+    {
+        self.scheduler.set_instructions_per_frame(instructions_per_frame)
+    }
+
+    pub fn set_timing_model(&mut self, timing: TimingModel)
+    {
+        self.timing     = timing;
+        self.cycle_debt = 0;
+    }
+
+    // Machine cycles a COSMAC VIP would have taken so far, whatever the
+    // timing model, e.g. to know how long the rom takes on real hardware
+    pub fn machine_cycles(&self) -> u64
+    {
+        self.cycles
+    }
+
+    // Logs every executed instruction until the trace is stopped
+    pub fn start_trace(&mut self, tracer: Tracer)
+    {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn stop_trace(&mut self) -> Result<(), String>
+    {
+        match self.tracer.take()
+        {
+            Some(tracer) => tracer.finish(),
+            None         => Ok(()),
+        }
+    }
+
+    // Counts executed addresses and instructions of the loaded rom. The
+    // report is written to the given path when profiling stops.
+    pub fn start_profiling(&mut self, path: PathBuf)
+    {
+        let map     = self.ram.map();
+        let program = map.program_start..map.program_start + self.rom.len();
+
+        self.profiler = Some(Profiler::new(path, map.ram_size, program));
+    }
+
+    pub fn stop_profiling(&mut self) -> Result<(), String>
+    {
+        match self.profiler.take()
+        {
+            Some(profiler) => profiler.finish(),
+            None           => Ok(()),
+        }
+    }
+
+    // Vsync needs a window created with it, see Config::frame_sync
+    pub fn set_frame_sync(&mut self, sync: FrameSync)
+    {
+        self.scheduler.set_sync(sync);
+    }
+
+    // A paused interpreter only runs frames with the frame advance hotkey
+    pub fn set_paused(&mut self, paused: bool)
+    {
+        self.scheduler.set_paused(paused);
+    }
+
+    // Draws the machine state over the screen, also toggled with a hotkey
+    pub fn set_hud_visible(&mut self, visible: bool)
+    {
+        self.hud_visible = visible;
+    }
+
+    // Opens or closes the debugger window, next to the game window
+    pub fn show_debugger(&mut self, visible: bool) -> Result<(), String>
+    {
+        let program_start = self.ram.map().program_start;
+
+        let frontend = match self.frontend.as_mut()
+        {
+            Some(frontend) => frontend,
+            None           => return Err(String::from("Headless interpreters have no debugger window")),
+        };
+
+        frontend.debugger = match visible
+        {
+            true  => Some(Debugger::new(&frontend.context, frontend.display.window_rect(), program_start)?),
+            false => None,
+        };
+
+        Ok(())
+    }
+
+    pub fn is_debugger_visible(&self) -> bool
+    {
+        self.frontend.as_ref().map(|frontend| frontend.debugger.is_some()).unwrap_or(false)
+    }
+
+    // Pauses the interpreter before it runs the instruction at the address.
+    // run_frames() returns early when it stops at a breakpoint.
+    pub fn add_breakpoint(&mut self, address: usize)
+    {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool
+    {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize>
+    {
+        &self.breakpoints
+    }
+
+    // Pauses the interpreter when the condition of the watchpoint becomes
+    // true. Conditions are checked before each instruction.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint)
+    {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, expression: &str) -> bool
+    {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.expression() != expression.trim());
+
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint]
+    {
+        &self.watchpoints
+    }
+
+    // Address of the breakpoint or watchpoint the interpreter is stopped at
+    pub fn stopped_at(&self) -> Option<usize>
+    {
+        self.break_address
+    }
+
+    // Runs the loaded rom until the window is closed
+    pub fn start(&mut self) -> Result<(), String>
+    {
+        if self.is_headless()
+        {
+            return Err(String::from("Headless interpreters run with run_frames()"));
+        }
+
+        self.running = true;
+        self.scheduler.restart();
+
+        while self.running
+        {
+            let frames = self.scheduler.wait_frames();
+
+            self.handle_hotkeys()?;
+            self.poll_rom_file();
+
+            // A breakpoint pauses in the middle of the frames
+            for _ in 0..frames
+            {
+                if self.scheduler.is_paused() { break; }
+                self.cpu_cycle()?;
+            }
+
+            if self.scheduler.is_turbo()
+            {
+                self.run_uncapped()?;
+            }
+
+            let status = self.status();
+
+            if let Some(frontend) = self.frontend.as_mut()
+            {
+                frontend.display.set_status(status)?;
+            }
+
+            self.present_frame()?;
+        }
+
+        self.stop_trace()?;
+        self.stop_profiling()?;
+        self.stop_recording()
+    }
+
+    // Recorder of the screen in the colors of the palette, and of the
+    // buzzer as the speakers play it
+    pub fn new_recorder(&self, format: RecordingFormat) -> Recorder
+    {
+        Recorder::new(format, self.palette, DEFAULT_RECORDING_SCALE, self.audio.control())
+    }
+
+    // Where the record hotkey saves recordings. By default, a gif named
+    // after the time the recording started.
+    pub fn set_recording_format(&mut self, format: RecordingFormat)
+    {
+        self.record_to = Some(format);
+    }
+
+    pub fn start_recording(&mut self, recorder: Recorder)
+    {
+        self.recorder = Some(recorder);
+    }
+
+    // Stops the current recording, if any, and writes it to disk
+    pub fn stop_recording(&mut self) -> Result<(), String>
+    {
+        match self.recorder.take()
+        {
+            Some(recorder) => recorder.finish(),
+            None           => Ok(()),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool
+    {
+        self.recorder.is_some()
+    }
+
+    // Waveform, frequency, volume and mute state of the buzzer
+    pub fn tone_control(&self) -> ToneControl
+    {
+        self.audio.control()
+    }
+}
+
+// Private
+impl Interpreter
+{
+    fn build(map: MemoryMap, frontend: Option<Frontend>, audio: Box<dyn AudioSink>)
+        -> Result<Self, String>
+    {
+        let pc             = ProgramCounter::with_map(&map);
+        let ram            = Ram::with_map(map)?;
+        let screen         = GridEditor::new();
+        let keys           = 0;
+        let rng            = StdRng::from_entropy();
+        let i_register     = IRegister::new();
+        let data_registers = DataRegister::all();
+        let stack          = Stack::new();
+        let scheduler      = FrameScheduler::new(DEFAULT_INSTRUCTIONS_PER_FRAME)?;
+        let delay_timer    = Timer::new();
+        let sound_timer    = Timer::new();
+        let recorder       = None;
+        let running        = false;
+        let timing         = TimingModel::default();
+        let quirks         = Quirks::default();
+
+        let interpreter = Interpreter
+                {
+                  ram, pc, frontend, screen, keys, audio,
+                  rng, i_register, data_registers,
+                  stack, scheduler, delay_timer,
+                  sound_timer, recorder, running,
+                  palette: Palette::default(), record_to: None,
+                  timing, quirks, cycles: 0, cycle_debt: 0,
+                  tracer: None, instructions: 0,
+                  profiler: None, rom: Vec::new(),
+                  rom_file: None, notice: None,
+                  hot_reload: None, rom_modified: None,
+                  rom_polled: Instant::now(),
+                  detect_platform: false, detection: None,
+                  quirks_given: false, map_given: false, hud_visible: false,
+                  meter: RateMeter::new(),
+                  breakpoints: BTreeSet::new(), break_address: None,
+                  watchpoints: Vec::new()
+                };
+
+        Ok(interpreter)
+    }
+
+    fn apply_config(&mut self, config: &Config) -> Result<(), String>
+    {
+        self.set_instructions_per_frame(config.instructions_per_frame)?;
+        self.set_timing_model(config.timing);
+        self.set_frame_sync(config.frame_sync);
+        self.use_quirks(config.effective_quirks());
+        self.audio.control().set_muted(config.mute);
+        self.palette = config.palette.unwrap_or_default();
+
+        if let Some(font) = config.font_config()
+        {
+            self.set_font(font)?;
+        }
+
+        self.quirks_given    = config.quirks.is_some();
+        self.detect_platform = config.platform.is_none();
+
+        if let Some(seed) = config.seed
+        {
+            self.set_seed(seed);
+        }
+
+        Ok(())
+    }
+
+    fn use_quirks(&mut self, quirks: Quirks)
+    {
+        let sprite_mode = if quirks.sprite_wrap { SpriteMode::Wrap } else { SpriteMode::Clip };
+
+        self.screen.set_sprite_mode(sprite_mode);
+        self.quirks = quirks;
+    }
+
+    // Switches to the given memory and clears registers, stack, timers
+    // and screen, keeping the window, the audio device and the settings
+    fn power_on(&mut self, ram: Ram)
+    {
+        self.ram            = ram;
+        self.pc             = ProgramCounter::with_policy(self.ram.map(), self.pc.policy());
+        self.i_register     = IRegister::new();
+        self.data_registers = DataRegister::all();
+        self.stack          = Stack::new();
+        self.keys           = 0;
+        self.cycles         = 0;
+        self.cycle_debt     = 0;
+
+        self.screen.clear();
+        self.delay_timer.set_value(0);
+        self.set_sound_timer(0);
+    }
+
+    // Platform of a rom, which must not use the SCHIP and XO-CHIP
+    // instructions the interpreter cannot run
+    fn detect_supported(&self, rom: &[u8]) -> Result<Detection, String>
+    {
+        let analysis = RomAnalysis::new(rom, self.ram.map());
+
+        if let Some(finding) = analysis.schip.iter().chain(analysis.xochip.iter()).next()
+        {
+            return Err(format!("SCHIP/XO-CHIP instructions are not supported, found {:04X} at {:#05X}",
+                               finding.word, finding.address));
+        }
+
+        Ok(analysis.detect())
+    }
+
+    // Memory map of the platform found in a rom, unless the map or the
+    // font were chosen explicitly. Roms of the default platform get the
+    // default map.
+    fn detected_map(&self, detection: &Detection) -> MemoryMap
+    {
+        if self.map_given
+        {
+            return self.ram.map().clone();
+        }
+
+        detection.platform.filter(|&platform| platform != Platform::default())
+                          .map(MemoryMap::for_platform)
+                          .unwrap_or_default()
+    }
+
+    // Writes a new version of the rom over the running one, when the
+    // machine state still makes sense with it
+    fn patch_rom(&mut self, rom: &[u8]) -> bool
+    {
+        let map = self.ram.map().clone();
+
+        let same_platform = match self.detect_platform
+        {
+            true  => self.detect_supported(rom).is_ok_and(|detection| self.detected_map(&detection) == map),
+            false => true,
+        };
+
+        let rom_end = map.program_start + rom.len();
+
+        if !same_platform || !map.fits(rom.len()) || self.pc.value() >= rom_end
+        {
+            return false;
+        }
+
+        // Bytes of a longer previous version are cleared
+        for offset in 0..rom.len().max(self.rom.len())
+        {
+            self.ram.write(map.program_start + offset, rom.get(offset).copied().unwrap_or(0));
+        }
+
+        self.rom = rom.to_vec();
+        true
+    }
+
+    // Reloads the rom file if it changed, every ROM_POLL_INTERVAL
+    fn poll_rom_file(&mut self)
+    {
+        if self.hot_reload.is_none() || self.rom_polled.elapsed() < ROM_POLL_INTERVAL
+        {
+            return;
+        }
+
+        self.rom_polled = Instant::now();
+
+        match self.check_rom_file()
+        {
+            Ok(Some(ReloadMode::Reset))     => self.show_notice(String::from("Rom reloaded")),
+            Ok(Some(ReloadMode::KeepState)) => self.show_notice(String::from("Rom reloaded, state kept")),
+            Ok(None)                        => (),
+            Err(e)                          => self.show_notice(format!("Reload failed: {}", e)),
+        }
+    }
+
+    // Starts recording, or stops and saves the current recording
+    fn toggle_recording(&mut self) -> Result<(), String>
+    {
+        if self.is_recording()
+        {
+            self.stop_recording()?;
+            self.show_notice(String::from("Recording saved"));
+
+            return Ok(());
+        }
+
+        let format = self.record_to.clone().unwrap_or_else(||
+        {
+            let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+            RecordingFormat::Gif(PathBuf::from(format!("chust8-{}.gif", seconds)))
+        });
+
+        self.start_recording(self.new_recorder(format));
+        self.show_notice(String::from("Recording"));
+
+        Ok(())
+    }
+
+    fn show_notice(&mut self, text: String)
+    {
+        self.notice = Some((text, Instant::now()));
+    }
+
+    // Text for the window title: a recent notice, or the speed
+    fn status(&self) -> Option<String>
+    {
+        match &self.notice
+        {
+            Some((text, shown)) if shown.elapsed() < NOTICE_DURATION => Some(text.clone()),
+            _                                                       => self.scheduler.speed_label(),
+        }
+    }
+
+    // Emulates a single frame
+    fn cpu_cycle(&mut self) -> Result<(), String>
+    {
+        match self.timing
+        {
+            TimingModel::Uniform =>
+                for _ in 0..self.scheduler.instructions_per_frame()
+                {
+                    // The frame ends early, without ticking the timers
+                    if self.at_breakpoint() { return Ok(()); }
+                    self.cpu_step()?;
+                },
+
+            TimingModel::CosmacVip => if !self.run_vip_frame()? { return Ok(()); },
+        }
+
+        self.tick_timers()?;
+        self.audio.end_frame(self.scheduler.frame_rate());
+
+        Ok(())
+    }
+
+    // Runs instructions until the machine cycles available in a frame
+    // are spent, or a sprite waits for the vertical blank. False when it
+    // stopped at a breakpoint instead.
+    fn run_vip_frame(&mut self) -> Result<bool, String>
+    {
+        let mut spent = self.cycle_debt;
+
+        while spent < VIP_AVAILABLE_CYCLES
+        {
+            // Only the cycles left run when resumed
+            if self.at_breakpoint()
+            {
+                self.cycle_debt = spent;
+                return Ok(false);
+            }
+
+            let cycles_before = self.cycles;
+            let opcode        = self.cpu_step()?;
+
+            // The sprite is drawn after the vertical blank, taking its
+            // cycles from the next frame
+            if timing::waits_for_vblank(&opcode)
+            {
+                self.cycle_debt = (self.cycles - cycles_before) as u32;
+                return Ok(true);
+            }
+
+            spent += (self.cycles - cycles_before) as u32;
+        }
+
+        self.cycle_debt = spent - VIP_AVAILABLE_CYCLES;
+        Ok(true)
+    }
+
+    // Pauses when the pc reaches a breakpoint or a watchpoint triggers,
+    // unless the interpreter is being resumed from there
+    fn at_breakpoint(&mut self) -> bool
+    {
+        let pc        = self.pc.value();
+        let triggered = self.check_watchpoints();
+
+        if self.break_address == Some(pc)
+        {
+            return false;
+        }
+
+        let notice = match triggered
+        {
+            Some(expression)                       => format!("Watchpoint {} at {:#05X}", expression, pc),
+            None if self.breakpoints.contains(&pc) => format!("Breakpoint at {:#05X}", pc),
+            None                                   => return false,
+        };
+
+        self.break_address = Some(pc);
+        self.scheduler.set_paused(true);
+        self.show_notice(notice);
+
+        true
+    }
+
+    // Updates the watchpoints against the current state, returning the
+    // expression of the first one that triggered
+    fn check_watchpoints(&mut self) -> Option<String>
+    {
+        if self.watchpoints.is_empty()
+        {
+            return None;
+        }
+
+        let pc     = self.pc.value();
+        let state  = self.machine_state();
+        let opcode = OpCode::new(self.ram.read(pc), self.ram.read(pc + 1)).ok();
+
+        let context = WatchContext { pc, state: &state, memory: self.ram.peek(), opcode,
+                                     writes: self.ram.writes() };
+
+        let mut triggered = None;
+
+        for watchpoint in self.watchpoints.iter_mut()
+        {
+            if watchpoint.check(&context) && triggered.is_none()
+            {
+                triggered = Some(watchpoint.expression().to_string());
+            }
+        }
+
+        triggered
+    }
+
+    // Runs frames back to back until it's time to present one
+    fn run_uncapped(&mut self) -> Result<(), String>
     {
-        let context        = sdl2::init()?; 
-        let ram            = Ram::new();
-        let pc             = ProgramCounter::new();
-        let display        = Display::from_context(&context)?;
-        let keypad         = Keypad::new(&context)?;
-        let speakers       = Speakers::new(&context)?;
-        let i_register     = IRegister::new();
-        let data_registers = DataRegister::all();
-        let stack          = Stack::new();
-        let cpu_limiter    = RateLimiter::new(DEFAULT_CPU_FREQUENCY);
-        let timer_limiter  = RateLimiter::new(DEFAULT_TIMERS_FREQUENCY);
-        let delay_timer    = Timer::new();
-        let sound_timer    = Timer::new();
+        let deadline = Instant::now() + DEFAULT_TIMERS_FREQUENCY.period();
 
-        let mut interpreter = Interpreter
-                {
-                  ram, pc, context, display, keypad,
-                  speakers, i_register, data_registers,
-                  stack, cpu_limiter, timer_limiter,
-                  delay_timer, sound_timer
-                };
+        while Instant::now() < deadline && !self.scheduler.is_paused()
+        {
+            self.cpu_cycle()?;
+        }
 
-        Ok(interpreter)
+        Ok(())
     }
 
-    pub fn load_rom(&mut self, rom_file: &str) -> Result<(), String>
+    fn cpu_step(&mut self) -> Result<OpCode, String>
+    {
+        let address = self.pc.value();
+
+        // Extract two bytes from ram and parse the opcode
+        let (msb, lsb) = self.extract_opcode_bytes()?;
+        let opcode = OpCode::new(msb, lsb)?;
+
+        // Variable costs depend on VX before the instruction changes it
+        let vx     = self.data_registers[(msb & 0xF) as usize].get();
+        let before = self.tracer.as_ref().map(|_| self.machine_state());
+
+        // Get and execute the associated instruction, keeping its writes
+        // for the watchpoints
+        self.ram.clear_writes();
+        instructions::execute_opcode(opcode, self)?;
+
+        if let Some(before) = before
+        {
+            let after = self.machine_state();
+            let raw   = u16::from_be_bytes([msb, lsb]);
+
+            if let Some(tracer) = self.tracer.as_mut()
+            {
+                tracer.log(self.instructions, address, &opcode, raw, &before, &after)?;
+            }
+        }
+
+        if let Some(profiler) = self.profiler.as_mut()
+        {
+            profiler.record(address, &opcode, self.delay_timer.get_value());
+        }
+
+        self.instructions += 1;
+        self.break_address = None;
+
+        let skipped  = self.pc.value() == address + 2 * INSTRUCTION_SIZE;
+        self.cycles += timing::vip_cycles(&opcode, vx, skipped) as u64;
+
+        Ok(opcode)
+    }
+
+    fn machine_state(&self) -> MachineState
+    {
+        let mut registers = [0; NUM_DATA_REGISTERS];
+
+        for (value, register) in registers.iter_mut().zip(self.data_registers.iter())
+        {
+            *value = register.get();
+        }
+
+        MachineState { registers,
+                       i     : self.i_register.get(),
+                       sp    : self.stack.len(),
+                       delay : self.delay_timer.get_value(),
+                       sound : self.sound_timer.get_value(),
+                     }
+    }
+
+    // Machine state for the overlay, with the instruction at the pc
+    fn hud_info(&self) -> HudInfo
     {
-        let contents = match fs::read(rom_file)
+        let pc     = self.pc.value();
+        let memory = self.ram.peek();
+
+        let instruction = match (memory.get(pc), memory.get(pc + 1))
         {
-            Ok(data) => data,
-            Err(e)   => return Err(e.to_string()),
+            (Some(&msb), Some(&lsb)) =>
+            {
+                let disassembly = OpCode::new(msb, lsb).map(|opcode| opcode.disassembly())
+                                                       .unwrap_or(String::from("???"));
+
+                format!("{:02X}{:02X} {}", msb, lsb, disassembly)
+            },
+
+            _ => String::from("????"),
         };
 
-        self.ram.dump(&contents)?;
-        Ok(())
+        let state = self.machine_state();
+
+        HudInfo { fps       : self.meter.fps(),
+                  ips       : self.meter.ips(),
+                  pc, instruction,
+                  registers : state.registers,
+                  i         : state.i,
+                  delay     : state.delay,
+                  sound     : state.sound,
+                  keys      : self.keys,
+                }
     }
 
-    pub fn start(&mut self) -> Result<(), String>
+    fn present_frame(&mut self) -> Result<(), String>
     {
-        /*
-        'codes: loop
+        self.meter.frame(self.instructions);
+
+        let hud = match self.hud_visible
+        {
+            true  => Some(self.hud_info()),
+            false => None,
+        };
+
+        let state = self.machine_state();
+
+        if let Some(frontend) = self.frontend.as_mut()
         {
+            frontend.display.set_hud(hud);
+            frontend.display.present(self.screen.grid())?;
 
+            if let Some(debugger) = frontend.debugger.as_mut()
+            {
+                let view = DebugView { pc          : self.pc.value(),
+                                       registers   : state.registers,
+                                       i           : state.i,
+                                       stack       : self.stack.entries(),
+                                       delay       : state.delay,
+                                       sound       : state.sound,
+                                       memory      : self.ram.peek(),
+                                       last_write  : self.ram.last_write(),
+                                       breakpoints : &self.breakpoints,
+                                       watchpoints : &self.watchpoints,
+                                       paused      : self.scheduler.is_paused(),
+                                     };
 
+                debugger.draw(&view)?;
+            }
         }
-        */
 
-        self.cpu_cycle()?;
+        if let Some(recorder) = self.recorder.as_mut()
+        {
+            recorder.capture(self.screen.grid());
+        }
 
         Ok(())
     }
-}
 
-// Private
-impl Interpreter
-{
-    fn cpu_cycle(&mut self) -> Result<(), String>
+    fn tick_timers(&mut self) -> Result<(), String>
     {
+        self.delay_timer.tick();
+
+        let sound_status = self.sound_timer.tick();
+        self.update_sound(sound_status);
+
         Ok(())
     }
 
-    fn cpu_step(&mut self) -> Result<(), String>
+    fn set_sound_timer(&mut self, value: u8)
     {
-        // Extract two bytes from ram and parse the opcode
-        let (msb, lsb) = self.extract_opcode_bytes()?;
-        let opcode = OpCode::new(msb, lsb)?;
+        let sound_status = self.sound_timer.set_value(value);
+        self.update_sound(sound_status);
+    }
+
+    // The buzzer plays while the sound timer is running
+    fn update_sound(&mut self, sound_status: TimerStatus)
+    {
+        self.audio.update(&sound_status);
+    }
+
+    // Reads the keyboard, which only exists outside headless mode
+    fn handle_hotkeys(&mut self) -> Result<(), String>
+    {
+        let (hotkeys, keys, turbo) = match self.frontend.as_mut()
+        {
+            Some(frontend) => (frontend.keypad.poll_hotkeys(),
+                               frontend.keypad.pressed_keys(),
+                               frontend.keypad.is_hotkey_held(Hotkey::Turbo)),
+            None           => return Ok(()),
+        };
+
+        self.keys = keys;
+        self.scheduler.set_turbo(turbo);
+
+        for hotkey in hotkeys
+        {
+            self.handle_hotkey(hotkey)?;
+        }
+
+        self.handle_window_input()
+    }
+
+    fn handle_hotkey(&mut self, hotkey: Hotkey) -> Result<(), String>
+    {
+        match hotkey
+        {
+            Hotkey::Mute           => { self.audio.control().toggle_mute(); },
+            Hotkey::Quit           => self.running = false,
+            Hotkey::TogglePause    => self.scheduler.set_paused(!self.scheduler.is_paused()),
+            Hotkey::SpeedUp        => { self.scheduler.speed_up(); },
+            Hotkey::SlowDown       => { self.scheduler.slow_down(); },
+            Hotkey::NormalSpeed    => self.scheduler.reset_speed(),
+            Hotkey::Turbo          => (),
+            Hotkey::ToggleHud      => self.hud_visible = !self.hud_visible,
+            Hotkey::ToggleDebugger => self.show_debugger(!self.is_debugger_visible())?,
+            Hotkey::Step           => self.debug_action(DebugAction::Step)?,
+            Hotkey::MemoryPageUp   => self.scroll_memory(-1),
+            Hotkey::MemoryPageDown => self.scroll_memory(1),
+
+            Hotkey::Reset          =>
+            {
+                self.reset()?;
+                self.show_notice(String::from("Reset"));
+            },
+
+            // A failed reload leaves the current rom running, so the
+            // file can be fixed
+            Hotkey::ReloadRom      => match self.reload_rom()
+            {
+                Ok(())   => self.show_notice(String::from("Rom reloaded")),
+                Err(e)   => self.show_notice(format!("Reload failed: {}", e)),
+            },
 
-        println!("Cpu step done!");
-        // Get and execute the associated instruction
-        //instructions::execute_opcode(opcodes::OpCode::_0NNN(2), self)?;
-        //instructions::execute_opcode(opcodes::OpCode::_3XNN(2, 3), self)?;
+            Hotkey::ToggleRecording => self.toggle_recording()?,
+
+            Hotkey::FrameAdvance   => if self.scheduler.is_paused()
+            {
+                self.cpu_cycle()?;
+            },
+        }
 
         Ok(())
     }
 
-    fn tick_timers(&mut self) -> Result<(), String>
+    // Clicks on the debugger buttons and disassembly, and closed windows
+    fn handle_window_input(&mut self) -> Result<(), String>
+    {
+        let frontend = match self.frontend.as_mut()
+        {
+            Some(frontend) => frontend,
+            None           => return Ok(()),
+        };
+
+        let input    = frontend.keypad.take_window_input();
+        let debugger = frontend.debugger.as_ref();
+
+        let mut actions        = Vec::new();
+        let mut close_debugger = false;
+
+        for event in input
+        {
+            match event
+            {
+                WindowInput::Click { window, x, y } =>
+                    actions.extend(debugger.filter(|debugger| debugger.window_id() == window)
+                                           .and_then(|debugger| debugger.action_at(x, y))),
+
+                // Closing the game window quits, even with the debugger open
+                WindowInput::Close { window } =>
+                    match debugger.map(|debugger| debugger.window_id()) == Some(window)
+                    {
+                        true  => close_debugger = true,
+                        false => self.running = false,
+                    },
+            }
+        }
+
+        if close_debugger
+        {
+            self.show_debugger(false)?;
+        }
+
+        for action in actions
+        {
+            self.debug_action(action)?;
+        }
+
+        Ok(())
+    }
+
+    fn debug_action(&mut self, action: DebugAction) -> Result<(), String>
     {
-        //self.delay_timer.unwrap().tick()?;
-        //self.sound_timer.unwrap().tick()?;
+        match action
+        {
+            // Steps run the instruction even when it has a breakpoint
+            DebugAction::Step                      =>
+            {
+                self.scheduler.set_paused(true);
+                self.cpu_step()?;
+                self.check_watchpoints();
+            },
+
+            DebugAction::Run                       => self.scheduler.set_paused(false),
+            DebugAction::Pause                     => self.scheduler.set_paused(true),
+            DebugAction::ToggleBreakpoint(address) => if !self.remove_breakpoint(address)
+            {
+                self.add_breakpoint(address);
+            },
+        }
 
         Ok(())
     }
 
-    fn extract_opcode_bytes(&mut self) -> Result<(u8, u8), String>
+    fn scroll_memory(&mut self, pages: isize)
     {
-        let memory = self.ram.peek();
+        let memory_size = self.ram.peek().len();
+
+        if let Some(debugger) = self.frontend.as_mut().and_then(|frontend| frontend.debugger.as_mut())
+        {
+            debugger.scroll_memory(pages, memory_size);
+        }
+    }
 
-        let msb = memory[self.pc.value()];
-        self.pc.advance(None)?;
+    fn extract_opcode_bytes(&mut self) -> Result<(u8, u8), String>
+    {
+        let address = self.pc.next_instruction()?;
+        let memory  = self.ram.peek();
 
-        let lsb = memory[self.pc.value()];
-        self.pc.advance(None)?;
+        let msb = memory[address];
+        let lsb = memory[(address + 1) % memory.len()];
 
         Ok((msb, lsb))
     }
@@ -138,7 +1309,9 @@ impl Interpreter
 mod tests
 {
     use super::*;
+    use std::fs;
     use crate::helpers::tests::*;
+    use crate::font::FontSet;
 
     #[test]
     fn test_load_rom() -> Result<(), String>
@@ -154,6 +1327,263 @@ mod tests
         Ok(())
     }
 
+    #[test]
+    fn test_platform_detection() -> Result<(), String>
+    {
+        // Loops forever, but does not fit in the memory of CHIP-8
+        let mut rom = vec![0; MemoryMap::default().program_size() + 2];
+        rom[..2].copy_from_slice(&[ 0x12, 0x00 ]);
+
+        let mut interpreter = Interpreter::headless_with_config(&Config::default())?;
+        interpreter.load_rom_bytes(&rom)?;
+
+        let detection = interpreter.detection().unwrap();
+        assert_eq!(detection.platform, Some(Platform::XoChip));
+        assert_eq!(interpreter.quirks(), Quirks::for_platform(Platform::XoChip));
+        assert_eq!(interpreter.ram.map(), &MemoryMap::for_platform(Platform::XoChip));
+
+        // Quirks given explicitly are kept
+        let config          = Config { quirks: Some(Quirks::default()), ..Config::default() };
+        let mut interpreter = Interpreter::headless_with_config(&config)?;
+        interpreter.load_rom_bytes(&rom)?;
+        assert_eq!(interpreter.quirks(), Quirks::default());
+
+        // High resolution, big sprite, then loops forever. SCHIP roms fail
+        // to load instead of failing to run.
+        let rom = [ 0x00, 0xFF, 0xD0, 0x10, 0x12, 0x04 ];
+
+        let mut interpreter = Interpreter::headless_with_config(&Config::default())?;
+        assert_eq!(interpreter.load_rom_bytes(&rom).unwrap_err(),
+                   "SCHIP/XO-CHIP instructions are not supported, found 00FF at 0x200");
+
+        // No detection with a platform given
+        let mut interpreter = Interpreter::headless_with_config(&Config::for_platform(Platform::CosmacVip))?;
+        interpreter.load_rom_bytes(&rom)?;
+        assert!(interpreter.detection().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detection_keeps_font() -> Result<(), String>
+    {
+        // Loops forever
+        let rom  = [ 0x12, 0x00 ];
+        let font = FontConfig::new(FontSet::Dream6800, 0x50);
+
+        let mut interpreter = Interpreter::headless_with_config(&Config::default())?;
+        interpreter.set_font(font)?;
+        interpreter.load_rom_bytes(&rom)?;
+
+        assert_eq!(interpreter.detection().unwrap().platform, None);
+        assert_eq!(interpreter.ram.font(), &font);
+        assert_eq!(interpreter.ram.read(0x50), FontSet::Dream6800.small_glyphs()[0]);
+
+        // And so is a memory map given explicitly
+        let map             = MemoryMap::for_platform(Platform::Eti660);
+        let mut interpreter = Interpreter::headless_with_memory_map(map.clone())?;
+        interpreter.set_platform_detection(true);
+        interpreter.load_rom_bytes(&rom)?;
+
+        assert_eq!(interpreter.ram.map(), &map);
+        assert_eq!(interpreter.pc.value(), map.program_start);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_rom() -> Result<(), String>
+    {
+        let mut interpreter = Interpreter::headless()?;
+
+        // Sets V0, I and the delay timer, draws the 0 glyph and loops
+        interpreter.load_rom_bytes(&[ 0x60, 0x05, 0xF0, 0x15, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x08 ])?;
+        interpreter.run_frames(1)?;
+
+        assert!(interpreter.screen().peek().iter().any(|&pixel| pixel));
+
+        // Loops at once
+        interpreter.load_rom_bytes(&[ 0x12, 0x00 ])?;
+
+        assert_eq!(interpreter.pc.value(), 0x200);
+        assert!(interpreter.screen().peek().iter().all(|&pixel| !pixel));
+        assert_eq!(interpreter.data_registers[0].get(), 0);
+        assert_eq!(interpreter.i_register.get(), 0);
+        assert_eq!(interpreter.delay_timer.get_value(), 0);
+
+        // Nothing is left of the longer rom
+        assert_eq!(interpreter.ram.read(0x202), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reset() -> Result<(), String>
+    {
+        let mut interpreter = Interpreter::headless()?;
+
+        // Draws the 0 glyph, overwrites its own first instruction and loops
+        let rom = [ 0xF0, 0x29, 0xD0, 0x05, 0xA2, 0x00, 0xF0, 0x55, 0x12, 0x08 ];
+
+        interpreter.load_rom_bytes(&rom)?;
+        interpreter.run_frames(1)?;
+        assert_eq!(interpreter.ram.read(0x200), 0);
+
+        interpreter.reset()?;
+
+        assert_eq!(interpreter.pc.value(), 0x200);
+        assert_eq!(&interpreter.ram.peek()[0x200..0x20A], &rom);
+        assert_eq!(interpreter.ram.read(0x000), 0xF0, "Font missing");
+        assert!(interpreter.screen().peek().iter().all(|&pixel| !pixel));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_breakpoints() -> Result<(), String>
+    {
+        let mut interpreter = Interpreter::headless()?;
+
+        // Counts in V0 forever, then stores it at 0x300 on each turn
+        interpreter.load_rom_bytes(&[ 0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00 ])?;
+        interpreter.add_breakpoint(0x206);
+
+        interpreter.run_frames(5)?;
+        assert_eq!(interpreter.stopped_at(), Some(0x206));
+        assert_eq!(interpreter.data_registers[0].get(), 1);
+        assert_eq!(interpreter.ram.last_write(), Some(0x300));
+
+        // Resuming runs the instruction at the breakpoint once
+        interpreter.run_frames(5)?;
+        assert_eq!(interpreter.stopped_at(), Some(0x206));
+        assert_eq!(interpreter.data_registers[0].get(), 2);
+
+        interpreter.debug_action(DebugAction::Step)?;
+        assert_eq!(interpreter.stopped_at(), None);
+        assert_eq!(interpreter.pc.value(), 0x200);
+
+        interpreter.debug_action(DebugAction::ToggleBreakpoint(0x206))?;
+        assert!(interpreter.breakpoints().is_empty());
+
+        interpreter.run_frames(2)?;
+        assert_eq!(interpreter.stopped_at(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_watchpoints() -> Result<(), String>
+    {
+        let mut interpreter = Interpreter::headless()?;
+
+        // Counts in V0 forever, then stores it at 0x300 on each turn
+        interpreter.load_rom_bytes(&[ 0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00 ])?;
+        interpreter.add_watchpoint(Watchpoint::new("V0 == 3")?);
+
+        interpreter.run_frames(5)?;
+        assert_eq!(interpreter.stopped_at(), Some(0x202));
+        assert_eq!(interpreter.data_registers[0].get(), 3);
+
+        assert!(interpreter.remove_watchpoint("V0 == 3"));
+        interpreter.add_watchpoint(Watchpoint::new("write in [0x300, 0x301)")?);
+
+        // Stops after the instruction writing there
+        interpreter.run_frames(5)?;
+        assert_eq!(interpreter.stopped_at(), Some(0x206));
+        assert_eq!(interpreter.ram.read(0x300), 3);
+
+        interpreter.add_watchpoint(Watchpoint::new("op == _FX55")?);
+
+        interpreter.run_frames(5)?;
+        assert_eq!(interpreter.stopped_at(), Some(0x204));
+        assert_eq!(interpreter.data_registers[0].get(), 4);
+        assert_eq!(interpreter.ram.read(0x300), 3);
+
+        // Draws two sprites back to back, stopping before each of them
+        let mut interpreter = Interpreter::headless()?;
+        interpreter.load_rom_bytes(&[ 0xD0, 0x15, 0xD0, 0x15, 0x12, 0x04 ])?;
+        interpreter.add_watchpoint(Watchpoint::new("op == DXYN")?);
+
+        interpreter.run_frames(1)?;
+        assert_eq!(interpreter.stopped_at(), Some(0x200));
+
+        interpreter.run_frames(1)?;
+        assert_eq!(interpreter.stopped_at(), Some(0x202));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_from_file() -> Result<(), String>
+    {
+        let path = std::env::temp_dir().join("chust8_reload_test.ch8");
+        let path = path.to_str().unwrap();
+
+        let mut interpreter = Interpreter::headless()?;
+        assert!(interpreter.reload_rom().is_err(), "Reloaded without rom file");
+
+        fs::write(path, [ 0x12, 0x00 ]).map_err(|e| e.to_string())?;
+        interpreter.load_rom(path)?;
+
+        fs::write(path, [ 0x60, 0x01, 0x12, 0x02 ]).map_err(|e| e.to_string())?;
+        interpreter.reload_rom()?;
+        assert_eq!(interpreter.ram.read(0x200), 0x60);
+
+        // A rom that does not fit leaves the loaded one
+        fs::write(path, vec![0; 4096]).map_err(|e| e.to_string())?;
+        assert!(interpreter.reload_rom().is_err());
+        assert_eq!(interpreter.ram.read(0x200), 0x60);
+
+        let _ = fs::remove_file(path);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hot_reload() -> Result<(), String>
+    {
+        let path = std::env::temp_dir().join("chust8_hot_reload_test.asm");
+        let path = path.to_str().unwrap();
+
+        // Writes a new version, dated later than the previous ones
+        let edit = |source: &str, age: u64|
+        {
+            fs::write(path, source).unwrap();
+            fs::File::options().write(true).open(path).unwrap()
+                .set_modified(SystemTime::now() + Duration::from_secs(age)).unwrap();
+        };
+
+        edit("LD V0, 1\nloop: JP loop", 1);
+
+        let mut interpreter = Interpreter::headless()?;
+        interpreter.load_rom(path)?;
+        interpreter.set_hot_reload(Some(ReloadMode::KeepState));
+        interpreter.run_frames(1)?;
+
+        assert_eq!(interpreter.check_rom_file()?, None);
+
+        // The loop is still in the new version
+        edit("LD V0, 2\nloop: JP loop", 2);
+        assert_eq!(interpreter.check_rom_file()?, Some(ReloadMode::KeepState));
+        assert_eq!(interpreter.data_registers[0].get(), 1);
+        assert_eq!(interpreter.ram.read(0x201), 2);
+
+        // Now the program counter is past the end
+        edit("start: JP start", 3);
+        assert_eq!(interpreter.check_rom_file()?, Some(ReloadMode::Reset));
+        assert_eq!(interpreter.data_registers[0].get(), 0);
+        assert_eq!(interpreter.ram.read(0x202), 0);
+
+        // Broken sources are reported once
+        edit("NOP", 4);
+        assert!(interpreter.check_rom_file().is_err());
+        assert_eq!(interpreter.check_rom_file()?, None);
+
+        let _ = fs::remove_file(path);
+
+        Ok(())
+    }
+
     #[test]
     fn test_cpu_cycle() -> Result<(), String>
     {
@@ -161,8 +1591,87 @@ mod tests
 
         let mut interpreter = Interpreter::new()?;
 
+        // Jump to itself
+        interpreter.ram.dump(&vec![0x12, 0x00])?;
+
+        interpreter.cpu_cycle()?;
+        assert_eq!(interpreter.pc.value(), 0x200);
+        assert_eq!(interpreter.pc.history().len(), DEFAULT_INSTRUCTIONS_PER_FRAME as usize);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vip_timing() -> Result<(), String>
+    {
+        let mutex = test_lock()?;
+
+        let mut interpreter = Interpreter::new()?;
+        interpreter.set_timing_model(TimingModel::CosmacVip);
+
+        // Jump to itself
+        interpreter.ram.dump(&vec![0x12, 0x00])?;
+
+        let jump_cycles = timing::vip_cycles(&OpCode::_1NNN(0x200), 0, false) as u64;
+
+        // Jumps run until the frame budget is spent. The last one takes
+        // a few cycles from the next frame.
         interpreter.cpu_cycle()?;
+
+        assert!(interpreter.machine_cycles() >= VIP_AVAILABLE_CYCLES as u64);
+        assert!(interpreter.machine_cycles() <  VIP_AVAILABLE_CYCLES as u64 + jump_cycles);
+
+        interpreter.cpu_cycle()?;
+        assert!(interpreter.machine_cycles() >= 2 * VIP_AVAILABLE_CYCLES as u64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vip_sprite_cycles() -> Result<(), String>
+    {
+        // Draws a 15 rows sprite at V0, then counts in V2 until the frame ends
+        let counted_at = | x: u8 | -> Result<u8, String>
+        {
+            let mut interpreter = Interpreter::headless()?;
+            interpreter.set_timing_model(TimingModel::CosmacVip);
+            interpreter.ram.dump(&vec![0x60, x, 0xD0, 0x1F, 0x72, 0x01, 0x12, 0x04])?;
+
+            interpreter.run_frames(2)?;
+            Ok(interpreter.data_registers[2].get())
+        };
+
+        // The sprite waits for the vertical blank and is drawn in the next
+        // frame, leaving less time to count when it's not byte aligned
+        assert!(counted_at(7)? < counted_at(0)?, "Unaligned sprite took no longer");
+
         Ok(())
     }
 
+    #[test]
+    fn test_headless_frames() -> Result<(), String>
+    {
+        let mut interpreter = Interpreter::headless()?;
+        assert!(interpreter.is_headless());
+        assert!(interpreter.start().is_err(), "Started a headless interpreter");
+
+        // Wait for key 5, then show its glyph
+        interpreter.load_rom_bytes(&[0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06])?;
+
+        interpreter.run_frames(2)?;
+        assert_eq!(interpreter.pc.value(), 0x200);
+        assert!(interpreter.screen().to_ascii().chars().all(|c| c != '#'));
+
+        interpreter.set_key(5, true)?;
+        interpreter.run_frames(1)?;
+        assert!(interpreter.screen().to_ascii().starts_with("####"));
+
+        assert!(interpreter.set_key(0x10, true).is_err());
+
+        // The buzzer is rendered for every frame run
+        let audio = interpreter.offline_audio().unwrap();
+        assert_eq!(audio.samples().len(), 3 * audio.sample_rate() as usize / 60);
+
+        Ok(())
+    }
 }
@@ -1,83 +1,588 @@
+#![allow(non_snake_case)]
+
+use rand::Rng;
 
 use super::Interpreter;
 use crate::opcodes::OpCode;
 
+const FLAG_REGISTER : u8 = 0xF;
 
-pub fn execute_opcode(opcode : OpCode, interpreter : &Interpreter) -> Result<(), String>
+pub fn execute_opcode(opcode : OpCode, interpreter : &mut Interpreter) -> Result<(), String>
 {
     use OpCode::*;
     match opcode
     {
-        _0NNN(nnn)      => execute_0NNN(interpreter, nnn)?,
-        _00EE           => execute_00EE(interpreter)?,
-        _00E0           => execute_00E0(interpreter)?,
-        _1NNN(u16),
-        _2NNN(u16),
-        _3XNN(u8, u8),
-        _4XNN(u8, u8),
-        _5XY0(u8, u8),
-        _6XNN(u8, u8),
-        _7XNN(u8, u8),
-        _8XY0(u8, u8),
-        _8XY1(u8, u8),
-        _8XY2(u8, u8),
-        _8XY3(u8, u8),
-        _8XY4(u8, u8),
-        _8XY5(u8, u8),
-        _8XY6(u8, u8),
-        _8XY7(u8, u8),
-        _8XYE(u8, u8),
-        _9XY0(u8, u8),
-        _ANNN(u16),
-        _BNNN(u16),
-        _CXNN(u8, u8),
-        _DXYN(u8, u8, u8),
-        _EX9E(u8),
-        _EXA1(u8),
-        _FX07(u8),
-        _FX0A(u8),
-        _FX15(u8),
-        _FX18(u8),
-        _FX1E(u8),
-        _FX29(u8),
-        _FX33(u8),
-        _FX55(u8),
-        _FX65(u8),
+        _0NNN(nnn)      => execute_0NNN(interpreter, nnn),
+        _00EE           => execute_00EE(interpreter),
+        _00E0           => execute_00E0(interpreter),
+        _1NNN(nnn)      => interpreter.pc.jump(nnn),
+        _2NNN(nnn)      => execute_2NNN(interpreter, nnn),
+        _3XNN(x, nn)    => skip_if(interpreter, register(interpreter, x) == nn),
+        _4XNN(x, nn)    => skip_if(interpreter, register(interpreter, x) != nn),
+        _5XY0(x, y)     => skip_if(interpreter, register(interpreter, x) == register(interpreter, y)),
+        _6XNN(x, nn)    => set_register(interpreter, x, nn),
+        _7XNN(x, nn)    => execute_7XNN(interpreter, x, nn),
+        _8XY0(x, y)     => set_register(interpreter, x, register(interpreter, y)),
+        _8XY1(x, y)     => execute_logic(interpreter, x, y, |vx, vy| vx | vy),
+        _8XY2(x, y)     => execute_logic(interpreter, x, y, |vx, vy| vx & vy),
+        _8XY3(x, y)     => execute_logic(interpreter, x, y, |vx, vy| vx ^ vy),
+        _8XY4(x, y)     => execute_8XY4(interpreter, x, y),
+        _8XY5(x, y)     => execute_8XY5(interpreter, x, y),
+        _8XY6(x, y)     => execute_8XY6(interpreter, x, y),
+        _8XY7(x, y)     => execute_8XY7(interpreter, x, y),
+        _8XYE(x, y)     => execute_8XYE(interpreter, x, y),
+        _9XY0(x, y)     => skip_if(interpreter, register(interpreter, x) != register(interpreter, y)),
+        _ANNN(nnn)      => { interpreter.i_register.set(nnn); Ok(()) },
+        _BNNN(nnn)      => execute_BNNN(interpreter, nnn),
+        _CXNN(x, nn)    => execute_CXNN(interpreter, x, nn),
+        _DXYN(x, y, n)  => execute_DXYN(interpreter, x, y, n),
+        _EX9E(x)        => skip_if(interpreter, is_key_pressed(interpreter, x)),
+        _EXA1(x)        => skip_if(interpreter, !is_key_pressed(interpreter, x)),
+        _FX07(x)        => execute_FX07(interpreter, x),
+        _FX0A(x)        => execute_FX0A(interpreter, x),
+        _FX15(x)        => execute_FX15(interpreter, x),
+        _FX18(x)        => execute_FX18(interpreter, x),
+        _FX1E(x)        => execute_FX1E(interpreter, x),
+        _FX29(x)        => execute_FX29(interpreter, x),
+        _FX30(x)        => execute_FX30(interpreter, x),
+        _FX33(x)        => execute_FX33(interpreter, x),
+        _FX55(x)        => execute_FX55(interpreter, x),
+        _FX65(x)        => execute_FX65(interpreter, x),
+    }
+}
+
+fn execute_0NNN(_interpreter : &mut Interpreter, _nnn : u16) -> Result<(), String>
+{
+    Err(String::from("Instruction 0NNN not supported"))
+}
+
+// Return from a subroutine
+fn execute_00EE(interpreter : &mut Interpreter) -> Result<(), String>
+{
+    let address = interpreter.stack.pop()?;
+    interpreter.pc.set(address as usize)
+}
+
+fn execute_00E0(interpreter : &mut Interpreter) -> Result<(), String>
+{
+    interpreter.screen.clear();
+    Ok(())
+}
+
+// Call the subroutine at NNN
+fn execute_2NNN(interpreter : &mut Interpreter, nnn : u16) -> Result<(), String>
+{
+    interpreter.stack.push(interpreter.pc.value() as u16)?;
+    interpreter.pc.jump(nnn)
+}
+
+// Add NN to VX. The carry flag is not affected.
+fn execute_7XNN(interpreter : &mut Interpreter, x : u8, nn : u8) -> Result<(), String>
+{
+    interpreter.data_registers[x as usize].add(nn);
+    Ok(())
+}
+
+// OR, AND and XOR. With the VF reset quirk, VF is cleared.
+fn execute_logic(interpreter : &mut Interpreter, x : u8, y : u8, operation : fn(u8, u8) -> u8)
+    -> Result<(), String>
+{
+    let value = operation(register(interpreter, x), register(interpreter, y));
+    set_register(interpreter, x, value)?;
+
+    if interpreter.quirks.vf_reset
+    {
+        set_register(interpreter, FLAG_REGISTER, 0)?;
+    }
+
+    Ok(())
+}
+
+// Add VY to VX. VF is set to 1 on carry.
+fn execute_8XY4(interpreter : &mut Interpreter, x : u8, y : u8) -> Result<(), String>
+{
+    let value = register(interpreter, y);
+    let carry = interpreter.data_registers[x as usize].add(value);
+
+    set_register(interpreter, FLAG_REGISTER, carry as u8)
+}
+
+// Subtract VY from VX. VF is set to 0 on borrow, 1 otherwise.
+fn execute_8XY5(interpreter : &mut Interpreter, x : u8, y : u8) -> Result<(), String>
+{
+    let value  = register(interpreter, y);
+    let borrow = interpreter.data_registers[x as usize].substract(value);
+
+    set_register(interpreter, FLAG_REGISTER, !borrow as u8)
+}
+
+// Shift VX right, or VY into VX with the shift quirk. VF gets the bit
+// shifted out.
+fn execute_8XY6(interpreter : &mut Interpreter, x : u8, y : u8) -> Result<(), String>
+{
+    shift_source(interpreter, x, y);
+
+    let bit = interpreter.data_registers[x as usize].shift_right();
+    set_register(interpreter, FLAG_REGISTER, bit)
+}
+
+// Set VX to VY minus VX. VF is set to 0 on borrow, 1 otherwise.
+fn execute_8XY7(interpreter : &mut Interpreter, x : u8, y : u8) -> Result<(), String>
+{
+    let value = register(interpreter, x);
+    let vy    = register(interpreter, y);
+
+    interpreter.data_registers[x as usize].set(vy);
+    let borrow = interpreter.data_registers[x as usize].substract(value);
+
+    set_register(interpreter, FLAG_REGISTER, !borrow as u8)
+}
+
+// Shift VX left, or VY into VX with the shift quirk. VF gets the bit
+// shifted out.
+fn execute_8XYE(interpreter : &mut Interpreter, x : u8, y : u8) -> Result<(), String>
+{
+    shift_source(interpreter, x, y);
+
+    let bit = interpreter.data_registers[x as usize].shift_left();
+    set_register(interpreter, FLAG_REGISTER, bit)
+}
+
+// Jump to NNN plus V0, or to XNN plus VX with the jump quirk
+fn execute_BNNN(interpreter : &mut Interpreter, nnn : u16) -> Result<(), String>
+{
+    let x = match interpreter.quirks.jump_uses_vx
+    {
+        true  => (nnn >> 8) as u8,
+        false => 0,
     };
 
+    interpreter.pc.jump(nnn + register(interpreter, x) as u16)
+}
+
+// Random number masked with NN
+fn execute_CXNN(interpreter : &mut Interpreter, x : u8, nn : u8) -> Result<(), String>
+{
+    let value = interpreter.rng.gen::<u8>() & nn;
+    set_register(interpreter, x, value)
+}
+
+// Draw the N bytes sprite at I on (VX, VY). VF is set to 1 on collision.
+fn execute_DXYN(interpreter : &mut Interpreter, x : u8, y : u8, n : u8) -> Result<(), String>
+{
+    let address = interpreter.i_register.get() as usize;
+    let sprite: Vec<u8> = (0..n as usize).map(|row| interpreter.ram.read(address + row)).collect();
+
+    let col       = register(interpreter, x);
+    let row       = register(interpreter, y);
+    let collision = interpreter.screen.draw_sprite(col, row, &sprite);
+
+    set_register(interpreter, FLAG_REGISTER, collision as u8)
+}
+
+// Read the delay timer into VX
+fn execute_FX07(interpreter : &mut Interpreter, x : u8) -> Result<(), String>
+{
+    let value = interpreter.delay_timer.get_value();
+    interpreter.data_registers[x as usize].set(value);
     Ok(())
 }
 
-fn execute_0NNN(interpreter : &mut Interpreter, nnn : u16) -> Result<(), String>
+// Wait for a key press and store the key in VX. Waiting is done by
+// running the instruction again until a key is held.
+fn execute_FX0A(interpreter : &mut Interpreter, x : u8) -> Result<(), String>
 {
-    Err(String::from("Instruction 0NNN not supported"))
+    match (0..16).find(|&key| interpreter.keys & (1 << key) != 0)
+    {
+        Some(key) => set_register(interpreter, x, key),
+        None      => interpreter.pc.rewind(),
+    }
 }
 
-fn execute_00EE(interpreter : &mut Interpreter) -> Result<(), String>
+fn execute_FX15(interpreter : &mut Interpreter, x : u8) -> Result<(), String>
+{
+    let value = register(interpreter, x);
+    interpreter.delay_timer.set_value(value);
+    Ok(())
+}
+
+// Setting the sound timer starts or stops the buzzer
+fn execute_FX18(interpreter : &mut Interpreter, x : u8) -> Result<(), String>
+{
+    let value = register(interpreter, x);
+    interpreter.set_sound_timer(value);
+    Ok(())
+}
+
+// Add VX to I. VF is not affected.
+fn execute_FX1E(interpreter : &mut Interpreter, x : u8) -> Result<(), String>
+{
+    let value = register(interpreter, x);
+    interpreter.i_register.add(value as u16);
+    Ok(())
+}
+
+// Point I to the small font glyph of the digit in VX
+fn execute_FX29(interpreter : &mut Interpreter, x : u8) -> Result<(), String>
+{
+    let digit   = interpreter.data_registers[x as usize].get();
+    let address = interpreter.ram.font().small_glyph_address(digit);
+
+    interpreter.i_register.set(address);
+    Ok(())
+}
+
+// Point I to the big SCHIP font glyph of the digit in VX
+fn execute_FX30(interpreter : &mut Interpreter, x : u8) -> Result<(), String>
+{
+    let digit   = interpreter.data_registers[x as usize].get();
+    let address = interpreter.ram.font().big_glyph_address(digit)?;
+
+    interpreter.i_register.set(address);
+    Ok(())
+}
+
+// Store the hundreds, tens and units of VX at I, I+1 and I+2
+fn execute_FX33(interpreter : &mut Interpreter, x : u8) -> Result<(), String>
+{
+    let value   = register(interpreter, x);
+    let address = interpreter.i_register.get() as usize;
+
+    interpreter.ram.write(address,     value / 100);
+    interpreter.ram.write(address + 1, (value / 10) % 10);
+    interpreter.ram.write(address + 2, value % 10);
+
+    Ok(())
+}
+
+// Store V0 to VX in memory starting at I. I is left unchanged unless
+// the memory quirk is enabled.
+fn execute_FX55(interpreter : &mut Interpreter, x : u8) -> Result<(), String>
+{
+    let address = interpreter.i_register.get() as usize;
+
+    for index in 0..=x
+    {
+        let value = register(interpreter, index);
+        interpreter.ram.write(address + index as usize, value);
+    }
+
+    increment_i(interpreter, x);
+    Ok(())
+}
+
+// Load V0 to VX from memory starting at I. I is left unchanged unless
+// the memory quirk is enabled.
+fn execute_FX65(interpreter : &mut Interpreter, x : u8) -> Result<(), String>
+{
+    let address = interpreter.i_register.get() as usize;
+
+    for index in 0..=x
+    {
+        let value = interpreter.ram.read(address + index as usize);
+        interpreter.data_registers[index as usize].set(value);
+    }
+
+    increment_i(interpreter, x);
+    Ok(())
+}
+
+fn register(interpreter : &Interpreter, x : u8) -> u8
+{
+    interpreter.data_registers[x as usize].get()
+}
+
+fn set_register(interpreter : &mut Interpreter, x : u8, value : u8) -> Result<(), String>
+{
+    interpreter.data_registers[x as usize].set(value);
+    Ok(())
+}
+
+fn shift_source(interpreter : &mut Interpreter, x : u8, y : u8)
+{
+    if interpreter.quirks.shift_uses_vy
+    {
+        let value = register(interpreter, y);
+        interpreter.data_registers[x as usize].set(value);
+    }
+}
+
+fn increment_i(interpreter : &mut Interpreter, x : u8)
 {
-    Err(String::from("Instruction 00EE not implemented"))
+    if interpreter.quirks.memory_increments_i
+    {
+        interpreter.i_register.add(x as u16 + 1);
+    }
 }
 
-fn execute_00E0(interpreter : &mut Interpreter, nnn : u16) -> Result<(), String>
+fn is_key_pressed(interpreter : &Interpreter, x : u8) -> bool
 {
-    Err(String::from("Instruction 0NNN not implemented"))
+    let key = register(interpreter, x) & 0xF;
+    interpreter.keys & (1 << key) != 0
+}
+
+fn skip_if(interpreter : &mut Interpreter, condition : bool) -> Result<(), String>
+{
+    if condition
+    {
+        interpreter.pc.skip()?;
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests
 {
     use super::*;
+    use crate::font::{ FontConfig, FontSet };
+    use crate::helpers::tests::*;
+    use crate::memory::AddressPolicy;
+
+    #[test]
+    fn control_flow() -> Result<(), String>
+    {
+        let mutex = test_lock()?;
+
+        let mut interpreter = Interpreter::new()?;
+
+        execute_opcode(OpCode::_1NNN(0x300), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x300);
+
+        // Calls push the return address
+        execute_opcode(OpCode::_2NNN(0x400), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x400);
+
+        execute_opcode(OpCode::_00EE, &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x300);
+        assert!(execute_opcode(OpCode::_00EE, &mut interpreter).is_err(),
+                "Returning with an empty stack did not fail");
+
+        // Skips
+        interpreter.data_registers[1].set(0x10);
+        interpreter.data_registers[2].set(0x10);
+
+        execute_opcode(OpCode::_3XNN(1, 0x10), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x302);
+
+        execute_opcode(OpCode::_4XNN(1, 0x10), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x302);
+
+        execute_opcode(OpCode::_5XY0(1, 2), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x304);
+
+        execute_opcode(OpCode::_9XY0(1, 2), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x304);
+
+        // Jump with offset
+        interpreter.data_registers[0].set(0x20);
+        execute_opcode(OpCode::_BNNN(0x300), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x320);
+
+        // Jumps into the interpreter area fail by default
+        assert!(execute_opcode(OpCode::_1NNN(0x100), &mut interpreter).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn timers() -> Result<(), String>
+    {
+        let mutex = test_lock()?;
+
+        let mut interpreter = Interpreter::new()?;
+
+        interpreter.data_registers[0].set(2);
+        execute_opcode(OpCode::_FX15(0), &mut interpreter)?;
+        execute_opcode(OpCode::_FX07(1), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[1].get(), 2);
+
+        // The buzzer follows the sound timer
+        execute_opcode(OpCode::_FX18(0), &mut interpreter)?;
+        assert!(interpreter.is_sound_playing());
+
+        interpreter.tick_timers()?;
+        assert!(interpreter.is_sound_playing());
+
+        interpreter.tick_timers()?;
+        assert!(!interpreter.is_sound_playing());
+
+        Ok(())
+    }
+
+    #[test]
+    fn font_addresses() -> Result<(), String>
+    {
+        let mutex = test_lock()?;
+
+        let mut interpreter = Interpreter::new()?;
+        interpreter.set_font(FontConfig::new(FontSet::Schip, 0x50))?;
+
+        interpreter.data_registers[3].set(0xB);
+        execute_opcode(OpCode::_FX29(3), &mut interpreter)?;
+        assert_eq!(interpreter.i_register.get(), 0x50 + 0xB * 5);
+
+        interpreter.data_registers[4].set(7);
+        execute_opcode(OpCode::_FX30(4), &mut interpreter)?;
+        assert_eq!(interpreter.i_register.get(), 0x50 + 80 + 7 * 10);
+
+        // There are no big glyphs for letters
+        interpreter.data_registers[4].set(0xB);
+        assert!(execute_opcode(OpCode::_FX30(4), &mut interpreter).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn arithmetic() -> Result<(), String>
+    {
+        let mutex = test_lock()?;
+
+        let mut interpreter = Interpreter::new()?;
+
+        execute_opcode(OpCode::_6XNN(0, 0xF0), &mut interpreter)?;
+        execute_opcode(OpCode::_6XNN(1, 0x20), &mut interpreter)?;
+
+        // Carry
+        execute_opcode(OpCode::_8XY4(0, 1), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[0].get(), 0x10);
+        assert_eq!(interpreter.data_registers[0xF].get(), 1);
+
+        // No borrow sets the flag
+        execute_opcode(OpCode::_8XY5(1, 0), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[1].get(), 0x10);
+        assert_eq!(interpreter.data_registers[0xF].get(), 1);
+
+        execute_opcode(OpCode::_6XNN(2, 0x30), &mut interpreter)?;
+        execute_opcode(OpCode::_8XY7(2, 1), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[2].get(), 0xE0);
+        assert_eq!(interpreter.data_registers[0xF].get(), 0);
+
+        // The shifted out bit goes to VF
+        execute_opcode(OpCode::_6XNN(3, 0x05), &mut interpreter)?;
+        execute_opcode(OpCode::_8XY6(3, 0), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[3].get(), 0x02);
+        assert_eq!(interpreter.data_registers[0xF].get(), 1);
+
+        // 7XNN leaves VF alone
+        execute_opcode(OpCode::_7XNN(3, 0xFF), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[3].get(), 0x01);
+        assert_eq!(interpreter.data_registers[0xF].get(), 1);
+
+        Ok(())
+    }
 
-    /*
     #[test]
-    fn opcode_parsing() -> Result<(), String>
+    fn memory_access() -> Result<(), String>
     {
-        // TODO: implement this test properly
-        let result = parse_opcode(0x00, 0xEE)?;
+        let mutex = test_lock()?;
+
+        let mut interpreter = Interpreter::new()?;
+
+        execute_opcode(OpCode::_ANNN(0x300), &mut interpreter)?;
+        execute_opcode(OpCode::_6XNN(0, 254), &mut interpreter)?;
+        execute_opcode(OpCode::_FX33(0), &mut interpreter)?;
+
+        execute_opcode(OpCode::_FX65(2), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[0].get(), 2);
+        assert_eq!(interpreter.data_registers[1].get(), 5);
+        assert_eq!(interpreter.data_registers[2].get(), 4);
+        assert_eq!(interpreter.i_register.get(), 0x300);
+
+        execute_opcode(OpCode::_FX55(1), &mut interpreter)?;
+        execute_opcode(OpCode::_FX1E(1), &mut interpreter)?;
+        assert_eq!(interpreter.i_register.get(), 0x305);
+
+        Ok(())
+    }
+
+    #[test]
+    fn drawing_and_keys() -> Result<(), String>
+    {
+        let mutex = test_lock()?;
+
+        let mut interpreter = Interpreter::new()?;
+
+        // Glyph of 0 drawn twice erases itself and collides
+        execute_opcode(OpCode::_FX29(0), &mut interpreter)?;
+        execute_opcode(OpCode::_DXYN(0, 0, 5), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[0xF].get(), 0);
+        assert!(interpreter.screen.grid().to_ascii().starts_with("####"));
+
+        execute_opcode(OpCode::_DXYN(0, 0, 5), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[0xF].get(), 1);
+        assert!(interpreter.screen.grid().to_ascii().starts_with("...."));
+
+        // FX0A runs again until a key is pressed
+        interpreter.pc.set(0x202)?;
+        execute_opcode(OpCode::_FX0A(5), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x200);
+
+        interpreter.keys |= 1 << 0xA;
+        interpreter.pc.set(0x202)?;
+        execute_opcode(OpCode::_FX0A(5), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x202);
+        assert_eq!(interpreter.data_registers[5].get(), 0xA);
+
+        execute_opcode(OpCode::_EX9E(5), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x204);
+        execute_opcode(OpCode::_EXA1(5), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x204);
+
+        // Seeded random numbers are masked
+        interpreter.set_seed(1);
+        execute_opcode(OpCode::_CXNN(6, 0x0F), &mut interpreter)?;
+        assert!(interpreter.data_registers[6].get() <= 0x0F);
+
+        execute_opcode(OpCode::_00E0, &mut interpreter)?;
+        Ok(())
+    }
+
+    #[test]
+    fn key_wait_wraps() -> Result<(), String>
+    {
+        let mut interpreter = Interpreter::headless()?;
+        interpreter.set_address_policy(AddressPolicy::Wrap);
+
+        // FX0A in the last word of memory runs again from there
+        interpreter.pc.set(0x1000)?;
+        assert_eq!(interpreter.pc.value(), 0);
+
+        execute_opcode(OpCode::_FX0A(5), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0xFFE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn quirks() -> Result<(), String>
+    {
+        use crate::platform::Platform;
+        use crate::quirks::Quirks;
+
+        let mut interpreter = Interpreter::headless()?;
+        interpreter.set_quirks(Quirks::for_platform(Platform::CosmacVip));
+
+        interpreter.data_registers[0xF].set(1);
+        execute_opcode(OpCode::_8XY1(0, 1), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[0xF].get(), 0);
+
+        // VY is shifted into VX
+        interpreter.data_registers[1].set(0x81);
+        execute_opcode(OpCode::_8XYE(0, 1), &mut interpreter)?;
+        assert_eq!(interpreter.data_registers[0].get(), 0x02);
+        assert_eq!(interpreter.data_registers[0xF].get(), 1);
+
+        interpreter.i_register.set(0x300);
+        execute_opcode(OpCode::_FX55(2), &mut interpreter)?;
+        assert_eq!(interpreter.i_register.get(), 0x303);
 
-        assert_eq!(result, OpCode::_00EE);
+        // BXNN adds VX
+        interpreter.set_quirks(Quirks::for_platform(Platform::Schip));
+        interpreter.data_registers[3].set(0x10);
+        execute_opcode(OpCode::_BNNN(0x320), &mut interpreter)?;
+        assert_eq!(interpreter.pc.value(), 0x330);
 
         Ok(())
     }
-    */
 }
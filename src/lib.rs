@@ -2,10 +2,24 @@ pub mod audio;
 pub mod registers;
 pub mod input;
 pub mod memory;
+pub mod font;
+pub mod platform;
+pub mod quirks;
+pub mod config;
+pub mod romdb;
+pub mod analysis;
+pub mod loader;
 pub mod interpreter;
 pub mod opcodes;
 pub mod timer;
 pub mod stack;
 pub mod display;
 pub mod clock;
+pub mod timing;
+pub mod trace;
+pub mod debugger;
+pub mod profile;
+pub mod recorder;
+pub mod assembler;
+pub mod selftest;
 mod helpers;
@@ -0,0 +1,249 @@
+use std::fs;
+use std::io::{ self, Cursor, Read };
+use std::path::Path;
+use std::time::SystemTime;
+
+use zip::ZipArchive;
+
+use crate::assembler::assemble;
+
+// Path that reads the rom from the standard input
+pub const STDIN_PATH     : &str = "-";
+
+// Extensions of the zip entries taken as roms
+pub const ROM_EXTENSIONS : [&str; 4] = [ "ch8", "sc8", "xo8", "asm" ];
+
+// Extension of assembler sources, assembled when read
+pub const SOURCE_EXTENSION : &str = "asm";
+
+const ZIP_SIGNATURE      : &[u8] = b"PK\x03\x04";
+
+// Where a rom was read from, so it can be read again
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomFile
+{
+    pub path  : String,
+    // Rom picked from a zip archive
+    pub entry : Option<String>,
+}
+
+impl RomFile
+{
+    pub fn new(path: &str, entry: Option<&str>) -> Self
+    {
+        RomFile { path: path.to_string(), entry: entry.map(String::from) }
+    }
+
+    // Reads the rom, assembling sources for the given load address
+    pub fn read(&self, origin: usize) -> Result<Vec<u8>, String>
+    {
+        read_rom(&self.path, self.entry.as_deref(), origin)
+    }
+
+    // Last time the file was written
+    pub fn modified(&self) -> Option<SystemTime>
+    {
+        fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+// What happens to the running rom when its file changes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReloadMode
+{
+    // Restarts the new rom from its power-on state
+    Reset,
+    // Replaces the program in memory, keeping registers, stack, timers,
+    // screen and the rest of memory, unless the program counter falls
+    // out of the new rom or the platform changes
+    KeepState,
+}
+
+impl ReloadMode
+{
+    pub fn parse(name: &str) -> Result<Self, String>
+    {
+        match name
+        {
+            "reset" => Ok(ReloadMode::Reset),
+            "keep"  => Ok(ReloadMode::KeepState),
+            _       => Err(format!("Unknown reload mode '{}'. Valid modes are reset, keep", name)),
+        }
+    }
+}
+
+// Reads a rom from a file, from the standard input when the path is "-",
+// or from a zip archive. The entry picks the rom of archives with several.
+// Assembler sources are assembled for the origin address.
+pub fn read_rom(path: &str, entry: Option<&str>, origin: usize) -> Result<Vec<u8>, String>
+{
+    let data = read_file(path)?;
+
+    if !is_zip(&data)
+    {
+        return assemble_source(path, data, origin);
+    }
+
+    let name = match entry
+    {
+        Some(name) => name.to_string(),
+        None       => single_rom(path, &zip_roms(&data)?)?,
+    };
+
+    assemble_source(&name, read_zip_entry(&data, &name)?, origin)
+}
+
+// Contents of a file, or of the standard input
+pub fn read_file(path: &str) -> Result<Vec<u8>, String>
+{
+    if path == STDIN_PATH
+    {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data).map_err(|e| format!("stdin: {}", e))?;
+
+        return Ok(data);
+    }
+
+    fs::read(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn is_zip(data: &[u8]) -> bool
+{
+    data.starts_with(ZIP_SIGNATURE)
+}
+
+// Names of the archive entries with a rom extension, in archive order
+pub fn zip_roms(archive: &[u8]) -> Result<Vec<String>, String>
+{
+    let mut archive = open_zip(archive)?;
+    let mut names   = Vec::new();
+
+    for index in 0..archive.len()
+    {
+        let entry = archive.by_index(index).map_err(|e| e.to_string())?;
+
+        if is_rom_name(entry.name())
+        {
+            names.push(entry.name().to_string());
+        }
+    }
+
+    Ok(names)
+}
+
+pub fn read_zip_entry(archive: &[u8], name: &str) -> Result<Vec<u8>, String>
+{
+    let mut archive = open_zip(archive)?;
+    let mut entry   = archive.by_name(name).map_err(|e| format!("{}: {}", name, e))?;
+
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).map_err(|e| format!("{}: {}", name, e))?;
+
+    Ok(data)
+}
+
+// Assembles the data when the name is the one of a source
+fn assemble_source(name: &str, data: Vec<u8>, origin: usize) -> Result<Vec<u8>, String>
+{
+    if extension(name).as_deref() != Some(SOURCE_EXTENSION)
+    {
+        return Ok(data);
+    }
+
+    let source = String::from_utf8(data).map_err(|_| format!("{}: Source is not UTF-8", name))?;
+
+    assemble(&source, origin).map_err(|e| format!("{}: {}", name, e))
+}
+
+fn open_zip(archive: &[u8]) -> Result<ZipArchive<Cursor<&[u8]>>, String>
+{
+    ZipArchive::new(Cursor::new(archive)).map_err(|e| format!("Invalid zip archive: {}", e))
+}
+
+fn is_rom_name(name: &str) -> bool
+{
+    extension(name).map(|extension| ROM_EXTENSIONS.contains(&extension.as_str()))
+                   .unwrap_or(false)
+}
+
+// Lowercase extension of a file name
+fn extension(name: &str) -> Option<String>
+{
+    Path::new(name).extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase)
+}
+
+fn single_rom(path: &str, names: &[String]) -> Result<String, String>
+{
+    match names
+    {
+        [name] => Ok(name.clone()),
+        []     => Err(format!("{}: No rom found, expected a .{} file",
+                              path, ROM_EXTENSIONS.join(", ."))),
+        _      => Err(format!("{}: Several roms found, pick one of {}", path, names.join(", "))),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::io::Write;
+    use zip::write::{ FileOptions, ZipWriter };
+
+    fn archive(entries: &[(&str, &[u8])]) -> Vec<u8>
+    {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for (name, data) in entries
+        {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn zip_entries() -> Result<(), String>
+    {
+        let data = archive(&[ ("readme.txt", b"Pong"), ("games/pong.ch8", &[0x12, 0x00]),
+                              ("PONG2.SC8", &[0x00, 0xFF]) ]);
+
+        assert!(is_zip(&data));
+        assert!(!is_zip(&[0x12, 0x00]));
+
+        assert_eq!(zip_roms(&data)?, vec!["games/pong.ch8", "PONG2.SC8"]);
+        assert_eq!(read_zip_entry(&data, "PONG2.SC8")?, vec![0x00, 0xFF]);
+        assert!(read_zip_entry(&data, "missing.ch8").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn archive_roms() -> Result<(), String>
+    {
+        let path = std::env::temp_dir().join("chust8_loader_test.zip");
+        let path = path.to_str().unwrap();
+
+        fs::write(path, archive(&[ ("a.ch8", &[1]), ("b.xo8", &[2]) ])).unwrap();
+
+        let several = read_rom(path, None, 0x200).unwrap_err();
+        assert!(several.contains("a.ch8, b.xo8"), "{}", several);
+        assert_eq!(read_rom(path, Some("b.xo8"), 0x200)?, vec![2]);
+
+        fs::write(path, archive(&[ ("a.ch8", &[1]) ])).unwrap();
+        assert_eq!(read_rom(path, None, 0x200)?, vec![1]);
+
+        fs::write(path, [0x12, 0x00]).unwrap();
+        assert_eq!(read_rom(path, None, 0x200)?, vec![0x12, 0x00]);
+
+        fs::write(path, archive(&[ ("loop.ASM", b"start: JP start") ])).unwrap();
+        assert_eq!(read_rom(path, None, 0x600)?, vec![0x16, 0x00]);
+
+        let _ = fs::remove_file(path);
+
+        Ok(())
+    }
+}
@@ -1,18 +1,426 @@
-
+use std::collections::BTreeSet;
 use std::env;
+use std::fs;
+use std::io::{ self, IsTerminal };
+use std::ops::Range;
+use std::process;
+
+use chust8::analysis::{ Finding, RomAnalysis };
+use chust8::config::Config;
 use chust8::interpreter::Interpreter;
+use chust8::loader::{ self, RomFile };
+use chust8::opcodes::OpCode;
+use chust8::memory::INSTRUCTION_SIZE;
+use chust8::recorder::RecordingFormat;
+use chust8::romdb::{ rom_hash, RomSettings, SettingsDatabase };
+use chust8::timing;
+use chust8::trace::Tracer;
+
+mod cli;
+
+use cli::{ Command, Options };
 
 fn main() -> Result<(), String>
 {
     let args: Vec<String> = env::args().collect();
+    let program           = args.first().map(String::as_str).unwrap_or("chust8");
+
+    let mut options = match cli::parse(&args[1..])
+    {
+        Ok(options) => options,
+        Err(e)      =>
+        {
+            eprintln!("{}. See {} --help", e, program);
+            process::exit(2);
+        },
+    };
+
+    match options.command
+    {
+        Command::Help     => { print!("{}", cli::usage(program)); return Ok(()) },
+        Command::ImportDb => return import_database(&options),
+        _                 => (),
+    }
+
+    let rom                 = read_rom(&mut options)?;
+    let (options, settings) = with_rom_settings(&args[1..], options, &rom)?;
+
+    match options.command
+    {
+        Command::Help     => Ok(()),
+        Command::ImportDb => Ok(()),
+        Command::Disasm   => disassemble(&options, &rom),
+        Command::Info     => info(&options, &rom, settings.as_ref()),
+        Command::Headless => run_headless(&options, &rom),
+        Command::Run      => run(&options, &rom),
+        Command::Debug    => run(&options, &rom),
+    }
+}
+
+// Reads the rom, remembering the one picked from a zip archive
+fn read_rom(options: &mut Options) -> Result<Vec<u8>, String>
+{
+    if options.entry.is_none()
+    {
+        options.entry = pick_entry(&options.rom)?;
+    }
+
+    loader::read_rom(&options.rom, options.entry.as_deref(), options.config.memory_map().program_start)
+}
+
+// Asks which rom to run from a zip archive with several, when there is
+// someone to ask
+fn pick_entry(path: &str) -> Result<Option<String>, String>
+{
+    if path == loader::STDIN_PATH || !io::stdin().is_terminal()
+    {
+        return Ok(None);
+    }
+
+    let data  = loader::read_file(path)?;
+    let names = match loader::is_zip(&data)
+    {
+        true  => loader::zip_roms(&data)?,
+        false => return Ok(None),
+    };
+
+    if names.len() < 2
+    {
+        return Ok(None);
+    }
+
+    eprintln!("{} has several roms:", path);
+
+    for (index, name) in names.iter().enumerate()
+    {
+        eprintln!("  {}) {}", index + 1, name);
+    }
+
+    eprint!("Pick one [1-{}]: ", names.len());
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).map_err(|e| e.to_string())?;
+
+    match answer.trim().parse::<usize>()
+    {
+        Ok(choice) if choice >= 1 && choice <= names.len() => Ok(Some(names[choice - 1].clone())),
+        _ => Err(format!("Invalid choice '{}'", answer.trim())),
+    }
+}
+
+fn open_database(options: &Options) -> Result<SettingsDatabase, String>
+{
+    let path = options.database.clone()
+                      .or_else(SettingsDatabase::default_path)
+                      .ok_or("No settings database location, give one with --db")?;
+
+    SettingsDatabase::load(path)
+}
+
+// Parses the arguments again on top of the settings stored for the rom,
+// so the ones given win, and stores them when asked to
+fn with_rom_settings(args: &[String], options: Options, rom: &[u8])
+    -> Result<(Options, Option<RomSettings>), String>
+{
+    if !options.use_database && !options.save_settings
+    {
+        return Ok((options, None));
+    }
+
+    // Without a home directory there is no default database to read, which
+    // is only an error when the database was asked for
+    if options.database.is_none() && !options.save_settings && SettingsDatabase::default_path().is_none()
+    {
+        return Ok((options, None));
+    }
+
+    let hash         = rom_hash(rom);
+    let mut database = open_database(&options)?;
+
+    let settings = database.get(&hash).cloned().filter(|_| options.use_database);
+
+    let options = match &settings
+    {
+        Some(settings) =>
+        {
+            let mut base = Config::default();
+            settings.apply(&mut base).map_err(|e| format!("Stored settings of {}: {}", hash, e))?;
+
+            Options { entry: options.entry.clone(), ..cli::parse_onto(args, base)? }
+        },
+        None => options,
+    };
+
+    if options.save_settings
+    {
+        database.insert(&hash, RomSettings::from_config(&options.config));
+        database.save()?;
+
+        eprintln!("Saved the settings of {}", options.rom);
+    }
+
+    Ok((options, settings))
+}
+
+fn import_database(options: &Options) -> Result<(), String>
+{
+    let json         = fs::read_to_string(&options.rom).map_err(|e| format!("{}: {}", options.rom, e))?;
+    let mut database = open_database(options)?;
+
+    let imported = database.import_community(&json)?;
+    database.save()?;
+
+    println!("Imported the settings of {} roms, {} known", imported, database.len());
+    Ok(())
+}
+
+fn disassemble(options: &Options, rom: &[u8]) -> Result<(), String>
+{
+    let start = options.config.memory_map().program_start;
+
+    for (index, word) in rom.chunks(INSTRUCTION_SIZE).enumerate()
+    {
+        let address = start + index * INSTRUCTION_SIZE;
+
+        match word
+        {
+            &[msb, lsb] =>
+            {
+                let disassembly = OpCode::new(msb, lsb).map(|opcode| opcode.disassembly())
+                                                       .unwrap_or(String::from("???"));
+
+                println!("{:#06X}: {:02X}{:02X}  {}", address, msb, lsb, disassembly);
+            },
+
+            _ => println!("{:#06X}: {:02X}", address, word[0]),
+        }
+    }
+
+    Ok(())
+}
+
+fn info(options: &Options, rom: &[u8], settings: Option<&RomSettings>) -> Result<(), String>
+{
+    let config    = &options.config;
+    let map       = config.memory_map();
+    let analysis  = RomAnalysis::new(rom, &map);
+    let detection = analysis.detect();
+    let quirks    = config.effective_quirks().enabled();
+
+    if let Some(title) = settings.and_then(|settings| settings.title.as_ref())
+    {
+        println!("Title:         {}", title);
+    }
+
+    if let Some(author) = settings.and_then(|settings| settings.author.as_ref())
+    {
+        println!("Author:        {}", author);
+    }
 
-    if args.len() <= 1
+    println!("Size:          {} bytes ({} in {} bytes of program memory)",
+             rom.len(), if analysis.fits { "fits" } else { "does not fit" }, map.program_size());
+    println!("SHA-1:         {}", rom_hash(rom));
+    println!("Platform:      {}", config.platform.map(|platform| platform.name()).unwrap_or("default"));
+    println!("Quirks:        {}", if quirks.is_empty() { String::from("none") } else { quirks.join(", ") });
+    println!("IPF:           {}", config.instructions_per_frame);
+    println!("Timing:        {}", config.timing.name());
+    println!("VIP time:      {}", vip_time_of_run(options, rom));
+    println!();
+    println!("Detected:      {}", detection.summary());
+    println!("Instructions:  {} reached from {:#05X}", analysis.instructions, map.program_start);
+    println!("SCHIP:         {}", list_findings(&analysis.schip));
+    println!("XO-CHIP:       {}", list_findings(&analysis.xochip));
+    println!("Machine calls: {}", list_findings(&analysis.machine_calls));
+    println!("Undecodable:   {}", list_findings(&analysis.undecodable));
+    println!("Fonts:         {}", list_ranges(&analysis.fonts));
+    println!("Sprites:       {}", list_ranges(&analysis.sprites));
+    println!("Jump targets:  {}", list_addresses(&analysis.jump_targets));
+    println!("Subroutines:   {}", list_addresses(&analysis.call_targets));
+
+    Ok(())
+}
+
+// Real hardware time taken by the frames of the headless command, or
+// why they could not run
+fn vip_time_of_run(options: &Options, rom: &[u8]) -> String
+{
+    let mut interpreter = match Interpreter::headless_with_config(&options.config)
+    {
+        Ok(interpreter) => interpreter,
+        Err(e)          => return e,
+    };
+
+    let run = interpreter.load_rom_bytes(rom).and_then(|_| interpreter.run_frames(options.frames));
+
+    match run
+    {
+        Ok(_)  => format!("{} in {} frames", describe_vip_time(&interpreter), options.frames),
+        Err(e) => format!("unknown, {}", e),
+    }
+}
+
+// e.g. "~1.20 s (264240 machine cycles)"
+fn describe_vip_time(interpreter: &Interpreter) -> String
+{
+    let cycles = interpreter.machine_cycles();
+    format!("~{:.2} s ({} machine cycles)", timing::vip_run_time(cycles).as_secs_f64(), cycles)
+}
+
+// Words found, with their addresses, e.g. "00FF at 0x200"
+fn list_findings(findings: &[Finding]) -> String
+{
+    list(findings.iter().map(|finding| format!("{:04X} at {:#05X}", finding.word, finding.address)))
+}
+
+fn list_ranges(ranges: &[Range<usize>]) -> String
+{
+    list(ranges.iter().map(|range| format!("{:#05X}-{:#05X}", range.start, range.end - 1)))
+}
+
+fn list_addresses(addresses: &BTreeSet<usize>) -> String
+{
+    list(addresses.iter().map(|address| format!("{:#05X}", address)))
+}
+
+fn list<I: Iterator<Item = String>>(items: I) -> String
+{
+    let items: Vec<String> = items.collect();
+
+    match items.is_empty()
+    {
+        true  => String::from("none"),
+        false => items.join(", "),
+    }
+}
+
+// Tells the platform picked for the rom when none was given
+fn report_detection(interpreter: &Interpreter)
+{
+    if let Some(detection) = interpreter.detection()
+    {
+        eprintln!("Detected platform {}", detection.summary());
+
+        for reason in detection.reasons.iter()
+        {
+            eprintln!("  {}", reason);
+        }
+
+        for warning in detection.warnings.iter()
+        {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+}
+
+// Attaches the tracer, profiler, recorder, breakpoints and watchpoints
+// asked for in the options
+fn start_diagnostics(interpreter: &mut Interpreter, options: &Options) -> Result<(), String>
+{
+    match (&options.trace, options.command)
+    {
+        (Some(path), _)       => interpreter.start_trace(Tracer::to_file(path, options.filter.clone())?),
+        (None, Command::Debug) => interpreter.start_trace(Tracer::new(Box::new(io::stderr()),
+                                                                      options.filter.clone())),
+        (None, _)             => (),
+    }
+
+    if let Some(path) = &options.profile
+    {
+        interpreter.start_profiling(path.clone());
+    }
+
+    // The record hotkey saves to the same place
+    if let Some(path) = &options.record
+    {
+        let format   = RecordingFormat::from_path(path);
+        let recorder = interpreter.new_recorder(format.clone());
+
+        interpreter.set_recording_format(format);
+        interpreter.start_recording(recorder);
+    }
+
+    for &address in options.breakpoints.iter()
+    {
+        interpreter.add_breakpoint(address);
+    }
+
+    for watchpoint in options.watchpoints.iter()
+    {
+        interpreter.add_watchpoint(watchpoint.clone());
+    }
+
+    Ok(())
+}
+
+// Stops the diagnostics and prints the last instructions run
+fn report_crash(interpreter: &mut Interpreter, error: String) -> Result<(), String>
+{
+    interpreter.stop_trace()?;
+    interpreter.stop_profiling()?;
+    interpreter.stop_recording()?;
+    eprint!("{}", interpreter.crash_report());
+
+    Err(error)
+}
+
+fn run(options: &Options, rom: &[u8]) -> Result<(), String>
+{
+    let mut interpreter = Interpreter::with_config(&options.config)?;
+    interpreter.load_rom_bytes(rom)?;
+
+    // Read again by the reload hotkey
+    if options.rom != loader::STDIN_PATH
     {
-        return Err(format!("Missing rom file. Usage is {} /path/to/rom", args[0]));
+        interpreter.set_rom_file(RomFile::new(&options.rom, options.entry.as_deref()));
     }
 
-    let mut interpreter = Interpreter::new()?;
-    interpreter.load_rom(&args[1])?;
+    interpreter.set_hot_reload(options.watch);
+    report_detection(&interpreter);
+
+    start_diagnostics(&mut interpreter, options)?;
+
+    if options.command == Command::Debug
+    {
+        interpreter.set_paused(true);
+        interpreter.show_debugger(true)?;
+    }
+
+    if let Err(e) = interpreter.start()
+    {
+        return report_crash(&mut interpreter, e);
+    }
+
+    Ok(())
+}
+
+fn run_headless(options: &Options, rom: &[u8]) -> Result<(), String>
+{
+    let mut interpreter = Interpreter::headless_with_config(&options.config)?;
+    interpreter.load_rom_bytes(rom)?;
+    report_detection(&interpreter);
+
+    start_diagnostics(&mut interpreter, options)?;
+
+    if let Err(e) = interpreter.run_frames(options.frames)
+    {
+        return report_crash(&mut interpreter, e);
+    }
+
+    interpreter.stop_trace()?;
+    interpreter.stop_profiling()?;
+    interpreter.stop_recording()?;
+
+    if let Some(path) = &options.wav
+    {
+        interpreter.offline_audio().ok_or("Missing headless audio")?.write_wav(path)?;
+    }
+
+    if let Some(address) = interpreter.stopped_at()
+    {
+        eprintln!("Stopped at {:#05X}", address);
+    }
 
+    eprintln!("VIP time: {}", describe_vip_time(&interpreter));
+    println!("{}", interpreter.screen().to_ascii());
     Ok(())
 }
@@ -1,13 +1,132 @@
+use std::ops::Range;
+use std::collections::VecDeque;
+
+use crate::font::FontConfig;
+use crate::platform::Platform;
 
 const SYSTEM_RAM_SIZE   : usize = 4096;
 const BEGIN_PROGRAM_RAM : usize = 512;
-const PROGRAM_RAM_SIZE  : usize = SYSTEM_RAM_SIZE - BEGIN_PROGRAM_RAM;
 
-type InternalStorage = [u8; SYSTEM_RAM_SIZE];
+// The COSMAC VIP keeps the stack and the display buffer
+// in the top 352 bytes of its 4K of memory
+const VIP_RESERVED_SIZE : usize = 352;
+const ETI_660_PROGRAM   : usize = 0x600;
+const XO_CHIP_RAM_SIZE  : usize = 65536;
+
+type InternalStorage = Vec<u8>;
+
+// Describes how the interpreter memory is laid out. Programs are loaded
+// at program_start and can grow up to the end of ram or to the first
+// reserved region found after the start, whatever comes first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryMap
+{
+    pub ram_size      : usize,
+    pub program_start : usize,
+    pub font          : FontConfig,
+    pub reserved      : Vec<Range<usize>>,
+}
+
+impl MemoryMap
+{
+    pub fn for_platform(platform: Platform) -> Self
+    {
+        let font = FontConfig::for_platform(platform);
+
+        use Platform::*;
+
+        match platform
+        {
+            CosmacVip =>
+                MemoryMap { font, reserved: vec![SYSTEM_RAM_SIZE - VIP_RESERVED_SIZE..SYSTEM_RAM_SIZE],
+                            ..MemoryMap::default() },
+            Eti660    =>
+                MemoryMap { font, program_start: ETI_660_PROGRAM, ..MemoryMap::default() },
+            XoChip    =>
+                MemoryMap { font, ram_size: XO_CHIP_RAM_SIZE, ..MemoryMap::default() },
+            Dream6800 | Schip =>
+                MemoryMap { font, ..MemoryMap::default() },
+        }
+    }
+
+    // First address after the memory available for programs
+    pub fn program_end(&self) -> usize
+    {
+        self.reserved.iter()
+            .map(|region| region.start)
+            .filter(|&start| start >= self.program_start)
+            .fold(self.ram_size, usize::min)
+    }
+
+    pub fn program_size(&self) -> usize
+    {
+        self.program_end() - self.program_start
+    }
+
+    // Whether a rom of the given size can be loaded
+    pub fn fits(&self, rom_size: usize) -> bool
+    {
+        rom_size <= self.program_size()
+    }
+
+    pub fn is_reserved(&self, address: usize) -> bool
+    {
+        self.reserved.iter().any(|region| region.contains(&address))
+    }
+
+    pub fn validate(&self) -> Result<(), String>
+    {
+        if self.program_start >= self.ram_size
+        {
+            return Err(format!("Program start {:#X} is out of ram bounds {:#X}",
+                               self.program_start, self.ram_size));
+        }
+
+        let font_range = self.font.address..self.font.address + self.font.size();
+
+        // The program area goes from its start to the end of ram
+        if font_range.end > self.program_start
+        {
+            return Err(format!("Font at {:#X} with size {} overlaps the program area starting at {:#X}",
+                               self.font.address, self.font.size(), self.program_start));
+        }
+
+        if self.reserved.iter().any(|region| region.start < font_range.end
+                                          && font_range.start < region.end)
+        {
+            return Err(format!("Font at {:#X} overlaps a reserved memory region",
+                               self.font.address));
+        }
+
+        if self.reserved.iter().any(|region| region.end > self.ram_size)
+        {
+            return Err(String::from("Reserved memory region out of ram bounds"));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for MemoryMap
+{
+    fn default() -> Self
+    {
+        MemoryMap { ram_size      : SYSTEM_RAM_SIZE,
+                    program_start : BEGIN_PROGRAM_RAM,
+                    font          : FontConfig::default(),
+                    reserved      : Vec::new(),
+                  }
+    }
+}
 
 pub struct Ram
 {
-    data : InternalStorage,
+    data       : InternalStorage,
+    map        : MemoryMap,
+    // Address of the last byte written by an instruction, and the ones
+    // written since clear_writes()
+    last_write : Option<usize>,
+    writes     : Vec<usize>,
 }
 
 // Public impl
@@ -15,22 +134,63 @@ impl Ram
 {
     pub fn new() -> Self
     {
-        let mut new_ram = Ram { data : [0; SYSTEM_RAM_SIZE] };
+        let map         = MemoryMap::default();
+        let mut new_ram = Ram { data : vec![0; map.ram_size], map, last_write : None, writes : Vec::new() };
         new_ram.init_system_memory();
 
         return new_ram;
     }
 
+    pub fn with_map(map : MemoryMap) -> Result<Self, String>
+    {
+        map.validate()?;
+
+        let mut new_ram = Ram { data : vec![0; map.ram_size], map, last_write : None, writes : Vec::new() };
+        new_ram.init_system_memory();
+
+        Ok(new_ram)
+    }
+
+    pub fn with_font(font : FontConfig) -> Result<Self, String>
+    {
+        let mut new_ram = Ram::new();
+        new_ram.set_font(font)?;
+
+        Ok(new_ram)
+    }
+
+    // Replaces the font stored in system memory
+    pub fn set_font(&mut self, font : FontConfig) -> Result<(), String>
+    {
+        MemoryMap { font, ..self.map.clone() }.validate()?;
+
+        self.clear_font();
+        self.map.font = font;
+        self.init_system_memory();
+
+        Ok(())
+    }
+
+    pub fn font(&self) -> &FontConfig
+    {
+        &self.map.font
+    }
+
+    pub fn map(&self) -> &MemoryMap
+    {
+        &self.map
+    }
+
     pub fn dump(&mut self, rom : &Vec<u8>) -> Result<(), String>
     {
-        if rom.len() > PROGRAM_RAM_SIZE
+        if !self.map.fits(rom.len())
         {
             return Err(format!("Cannot dump to ram: rom size
-                               {} is larger than system memory {}",
-                               rom.len(), PROGRAM_RAM_SIZE));
+                               {} is larger than program memory {}",
+                               rom.len(), self.map.program_size()));
         }
 
-        for(dst, src) in self.data.iter_mut().skip(BEGIN_PROGRAM_RAM).zip(rom)
+        for(dst, src) in self.data.iter_mut().skip(self.map.program_start).zip(rom)
         {
             *dst = *src;
         }
@@ -38,79 +198,233 @@ impl Ram
        Ok(())
     }
 
-    pub fn peek(&self) -> &InternalStorage
+    pub fn peek(&self) -> &[u8]
     {
         &self.data
     }
-}
 
-static DEFAULT_SPRITES : [u8; (0xF + 1) * 5] =
-[
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
+    // Byte access used by instructions. Addresses past the end of the
+    // memory wrap around, like the 12 bits address bus of the VIP.
+    pub fn read(&self, address: usize) -> u8
+    {
+        self.data[address % self.data.len()]
+    }
+
+    pub fn write(&mut self, address: usize, value: u8)
+    {
+        let size = self.data.len();
+        self.data[address % size] = value;
+        self.last_write = Some(address % size);
+        self.writes.push(address % size);
+    }
+
+    pub fn last_write(&self) -> Option<usize>
+    {
+        self.last_write
+    }
+
+    pub fn writes(&self) -> &[usize]
+    {
+        &self.writes
+    }
+
+    pub fn clear_writes(&mut self)
+    {
+        self.writes.clear();
+    }
+}
 
 // Private impl
 impl Ram
 {
     fn init_system_memory(&mut self)
     {
-        for(dst, src) in self.data.iter_mut().zip(DEFAULT_SPRITES.iter())
+        let font   = self.map.font;
+        let glyphs = font.font_set.small_glyphs().iter().chain(font.font_set.big_glyphs());
+
+        for(dst, src) in self.data.iter_mut().skip(font.address).zip(glyphs)
         {
             *dst = *src;
         }
     }
+
+    fn clear_font(&mut self)
+    {
+        let font       = self.map.font;
+        let font_range = font.address..font.address + font.size();
+        self.data[font_range].iter_mut().for_each(|byte| *byte = 0);
+    }
+}
+
+pub const INSTRUCTION_SIZE : usize = 2;
+pub const PC_HISTORY_SIZE  : usize = 32;
+
+// What to do when the program counter is moved outside program memory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressPolicy
+{
+    // Moving outside program memory is an error
+    Strict,
+    // Addresses wrap around at the end of ram
+    Wrap,
+    // Allows running code below the program start, as some roms from
+    // the 0NNN era did with the interpreter area
+    AllowInterpreterArea,
 }
 
+// The program counter can point from the program start up to one past the
+// end of program memory, so the last instruction can be fetched. Reading an
+// instruction from an address out of that range fails.
 pub struct ProgramCounter
 {
-    counter : usize,
+    counter  : usize,
+    start    : usize,
+    end      : usize,
+    ram_size : usize,
+    policy   : AddressPolicy,
+    history  : VecDeque<usize>,
 }
 
 impl ProgramCounter
 {
     pub fn new() -> Self
     {
-        ProgramCounter { counter : BEGIN_PROGRAM_RAM }
+        Self::with_map(&MemoryMap::default())
+    }
+
+    pub fn with_map(map : &MemoryMap) -> Self
+    {
+        Self::with_policy(map, AddressPolicy::Strict)
+    }
+
+    pub fn with_policy(map : &MemoryMap, policy : AddressPolicy) -> Self
+    {
+        ProgramCounter { counter  : map.program_start,
+                         start    : map.program_start,
+                         end      : map.program_end(),
+                         ram_size : map.ram_size,
+                         history  : VecDeque::with_capacity(PC_HISTORY_SIZE),
+                         policy,
+                       }
     }
 
     pub fn advance(&mut self, step : Option<usize>) -> Result<(), String>
     {
         let step_value = step.unwrap_or(1);
 
-        if self.counter + step_value >= BEGIN_PROGRAM_RAM + PROGRAM_RAM_SIZE
+        self.set(self.counter + step_value)
+    }
+
+    // Moves to the given address according to the address policy
+    pub fn set(&mut self, address : usize) -> Result<(), String>
+    {
+        self.counter = self.resolve(address)?;
+        Ok(())
+    }
+
+    // Used by jump and call instructions
+    pub fn jump(&mut self, address : u16) -> Result<(), String>
+    {
+        self.set(address as usize)
+    }
+
+    // Skips the next instruction
+    pub fn skip(&mut self) -> Result<(), String>
+    {
+        self.advance(Some(INSTRUCTION_SIZE))
+    }
+
+    // Moves back to the instruction just executed, e.g. to run it again
+    pub fn rewind(&mut self) -> Result<(), String>
+    {
+        let address = match (self.counter.checked_sub(INSTRUCTION_SIZE), self.policy)
+        {
+            (Some(address), _)          => address,
+            (None, AddressPolicy::Wrap) => self.counter + self.ram_size - INSTRUCTION_SIZE,
+            (None, _)                   => return Err(String::from("Cannot move program counter before 0")),
+        };
+
+        self.set(address)
+    }
+
+    // Returns the address of the instruction to execute and moves past it.
+    // The address is also stored in the execution history.
+    pub fn next_instruction(&mut self) -> Result<usize, String>
+    {
+        let address = self.counter;
+
+        // Both instruction bytes have to be readable
+        let last_byte = self.resolve(address + INSTRUCTION_SIZE - 1)?;
+
+        if self.policy != AddressPolicy::Wrap && last_byte >= self.end
         {
-            return Err(String::from("Cannot advance program counter out of memory bounds"));
+            return Err(format!("Cannot fetch instruction at {:#X} out of program memory", address));
         }
 
-        self.counter += step_value;
-        Ok(())
+        self.skip()?;
+
+        if self.history.len() == PC_HISTORY_SIZE
+        {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(address);
+        Ok(address)
     }
 
     pub fn value(&self) -> usize
     {
         self.counter
     }
+
+    pub fn policy(&self) -> AddressPolicy
+    {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy : AddressPolicy)
+    {
+        self.policy = policy;
+    }
+
+    // Addresses of the last executed instructions, oldest first
+    pub fn history(&self) -> &VecDeque<usize>
+    {
+        &self.history
+    }
+}
+
+// private
+impl ProgramCounter
+{
+    fn resolve(&self, address : usize) -> Result<usize, String>
+    {
+        use AddressPolicy::*;
+
+        let lower_bound = match self.policy
+        {
+            Strict               => self.start,
+            AllowInterpreterArea => 0,
+            Wrap                 => return Ok(address % self.ram_size),
+        };
+
+        if address < lower_bound || address > self.end
+        {
+            return Err(format!("Cannot move program counter to {:#X}, out of memory bounds",
+                               address));
+        }
+
+        Ok(address)
+    }
 }
 
 #[cfg(test)]
 mod tests
 {
     use super::*;
+    use crate::font::{ FontSet, DEFAULT_SPRITES };
+
+    const PROGRAM_RAM_SIZE : usize = SYSTEM_RAM_SIZE - BEGIN_PROGRAM_RAM;
 
     #[test]
     fn ram_system_init()
@@ -132,6 +446,41 @@ mod tests
                 "Program memory not zero-initialized");
     }
 
+    #[test]
+    fn ram_font_selection() -> Result<(), String>
+    {
+        let font = FontConfig::new(FontSet::Schip, 0x50);
+        let mut ram = Ram::with_font(font)?;
+
+        assert_eq!(ram.font(), &font);
+
+        // Nothing is stored before the font address
+        assert!(ram.peek()[..0x50].iter().all(|&value| value == 0),
+                "Memory before the font address was modified");
+
+        // Small glyphs first, then the big ones
+        let small = font.font_set.small_glyphs();
+        let big   = font.font_set.big_glyphs();
+
+        assert_eq!(&ram.peek()[0x50..0x50 + small.len()], small,
+                   "Small font not loaded at the configured address");
+        assert_eq!(&ram.peek()[0x50 + small.len()..0x50 + font.size()], big,
+                   "Big font not loaded after the small one");
+
+        // Switching fonts removes the previous one
+        let new_font = FontConfig::new(FontSet::CosmacVip, 0x0);
+        ram.set_font(new_font)?;
+
+        assert_eq!(&ram.peek()[..new_font.size()], new_font.font_set.small_glyphs());
+        assert!(ram.peek().iter().skip(new_font.size()).all(|&value| value == 0),
+                "Previous font was not removed from memory");
+
+        // Fonts cannot overlap program memory
+        assert!(ram.set_font(FontConfig::new(FontSet::Schip, BEGIN_PROGRAM_RAM - 100)).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn ram_correct_dump() -> Result<(), String>
     {
@@ -172,7 +521,7 @@ mod tests
         let mut ram = Ram::new();
 
         // Copy all the ram memory before dumping
-        let previous_memory = ram.peek().clone();
+        let previous_memory = ram.peek().to_vec();
 
         // Dump to memory a vector that exceeds the maximum ram size and
         // check that an error is returned
@@ -190,6 +539,79 @@ mod tests
         Ok(())
     }
 
+    #[test]
+    fn memory_map_layouts() -> Result<(), String>
+    {
+        // Default layout matches the original 4K CHIP-8 memory
+        let map = MemoryMap::default();
+        assert_eq!(map.program_start, BEGIN_PROGRAM_RAM);
+        assert_eq!(map.program_size(), PROGRAM_RAM_SIZE);
+
+        // The VIP stack and display area cannot be used by programs
+        let vip = MemoryMap::for_platform(Platform::CosmacVip);
+        assert_eq!(vip.program_end(), SYSTEM_RAM_SIZE - VIP_RESERVED_SIZE);
+        assert!(vip.is_reserved(0xEA0) && vip.is_reserved(0xFFF) && !vip.is_reserved(0xE9F));
+
+        // ETI-660 programs start at 0x600
+        let eti = MemoryMap::for_platform(Platform::Eti660);
+        assert_eq!(eti.program_start, 0x600);
+        assert_eq!(eti.program_size(), SYSTEM_RAM_SIZE - 0x600);
+
+        let xo_chip = MemoryMap::for_platform(Platform::XoChip);
+        assert_eq!(Ram::with_map(xo_chip)?.peek().len(), XO_CHIP_RAM_SIZE);
+
+        // Reserved regions before the program start do not limit programs
+        let hybrid = MemoryMap { reserved: vec![0x100..0x200, 0xF00..0x1000], ..MemoryMap::default() };
+        assert_eq!(hybrid.program_end(), 0xF00);
+
+        // Invalid layouts
+        assert!(MemoryMap { program_start: 0x2000, ..MemoryMap::default() }.validate().is_err());
+        assert!(MemoryMap { reserved: vec![0x0..0x10], ..MemoryMap::default() }.validate().is_err());
+        assert!(MemoryMap { reserved: vec![0xF00..0x2000], ..MemoryMap::default() }.validate().is_err());
+
+        // Fonts must end before the program area
+        let font = | address | FontConfig::new(FontSet::Schip, address);
+
+        assert!(MemoryMap { font: font(0x14C), ..MemoryMap::default() }.validate().is_ok());
+        assert!(MemoryMap { font: font(0x14D), ..MemoryMap::default() }.validate().is_err());
+        assert!(MemoryMap { font: font(0x800), ..MemoryMap::default() }.validate().is_err());
+        assert!(MemoryMap { font: font(0x300), ..eti }.validate().is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn ram_dump_with_map() -> Result<(), String>
+    {
+        for platform in [Platform::CosmacVip, Platform::Eti660].iter()
+        {
+            let map     = MemoryMap::for_platform(*platform);
+            let mut ram = Ram::with_map(map.clone())?;
+
+            let too_big = vec![0xAA; map.program_size() + 1];
+            assert!(ram.dump(&too_big).is_err(), "{:?} rom too big was dumped", platform);
+
+            let rom = vec![0xAA; map.program_size()];
+            ram.dump(&rom)?;
+
+            assert_eq!(&ram.peek()[map.program_start..map.program_end()], &rom[..],
+                       "{:?} rom not dumped at the program start", platform);
+            assert!(ram.peek()[map.font.size()..map.program_start].iter().all(|&byte| byte == 0),
+                    "{:?} memory before program start was modified", platform);
+            assert!(ram.peek()[map.program_end()..].iter().all(|&byte| byte == 0),
+                    "{:?} reserved memory was modified", platform);
+
+            let mut program_counter = ProgramCounter::with_map(&map);
+            assert_eq!(program_counter.value(), map.program_start);
+
+            assert!(program_counter.advance(Some(map.program_size())).is_ok());
+            assert!(program_counter.advance(None).is_err(),
+                    "{:?} program counter advanced out of program memory", platform);
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn program_counter_advance() -> Result<(), String>
     {
@@ -202,12 +624,91 @@ mod tests
                    "Advance loop failed at step {}", x);
         }
 
-        // Next steps should be invalid
+        // Check that we are in the last valid memory position
+        assert_eq!(program_counter.value(), SYSTEM_RAM_SIZE - 1);
+
+        // The counter can move past the last byte, so it can be read,
+        // but no further
+        assert!(program_counter.advance(None).is_ok());
         assert!(program_counter.advance(None).is_err());
         assert!(program_counter.advance(Some(3)).is_err());
 
-        // Check that we are in the last valid memory position
-        assert_eq!(program_counter.value(), SYSTEM_RAM_SIZE - 1);
+        assert_eq!(program_counter.value(), SYSTEM_RAM_SIZE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn program_counter_fetch() -> Result<(), String>
+    {
+        let mut program_counter = ProgramCounter::new();
+
+        // The very last instruction in memory can be fetched
+        program_counter.set(SYSTEM_RAM_SIZE - INSTRUCTION_SIZE)?;
+        assert_eq!(program_counter.next_instruction()?, SYSTEM_RAM_SIZE - INSTRUCTION_SIZE);
+        assert_eq!(program_counter.value(), SYSTEM_RAM_SIZE);
+
+        // Nothing can be fetched after it, nor from half an instruction
+        assert!(program_counter.next_instruction().is_err());
+
+        program_counter.set(SYSTEM_RAM_SIZE - 1)?;
+        assert!(program_counter.next_instruction().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn program_counter_jumps() -> Result<(), String>
+    {
+        let mut program_counter = ProgramCounter::new();
+
+        program_counter.jump(0x300)?;
+        assert_eq!(program_counter.value(), 0x300);
+
+        program_counter.skip()?;
+        assert_eq!(program_counter.value(), 0x302);
+
+        // Strict policy: nothing outside program memory
+        assert!(program_counter.jump(0x100).is_err());
+        assert!(program_counter.set(SYSTEM_RAM_SIZE + 1).is_err());
+        assert_eq!(program_counter.value(), 0x302, "Failed jump moved the counter");
+
+        // The interpreter area can be used if allowed
+        program_counter.set_policy(AddressPolicy::AllowInterpreterArea);
+        program_counter.jump(0x100)?;
+        assert_eq!(program_counter.next_instruction()?, 0x100);
+        assert!(program_counter.set(SYSTEM_RAM_SIZE + 1).is_err());
+
+        // Addresses wrap around at the end of ram
+        program_counter.set_policy(AddressPolicy::Wrap);
+        program_counter.set(SYSTEM_RAM_SIZE - INSTRUCTION_SIZE)?;
+        program_counter.skip()?;
+        assert_eq!(program_counter.value(), 0);
+
+        program_counter.set(SYSTEM_RAM_SIZE - 1)?;
+        assert_eq!(program_counter.next_instruction()?, SYSTEM_RAM_SIZE - 1);
+        assert_eq!(program_counter.value(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn program_counter_history() -> Result<(), String>
+    {
+        let mut program_counter = ProgramCounter::new();
+
+        assert!(program_counter.history().is_empty());
+
+        for _ in 0..PC_HISTORY_SIZE + 3
+        {
+            program_counter.next_instruction()?;
+        }
+
+        // Only the last instructions are kept, oldest first
+        let history = program_counter.history();
+        assert_eq!(history.len(), PC_HISTORY_SIZE);
+        assert_eq!(history.front(), Some(&(BEGIN_PROGRAM_RAM + 3 * INSTRUCTION_SIZE)));
+        assert_eq!(history.back(), Some(&(BEGIN_PROGRAM_RAM + (PC_HISTORY_SIZE + 2) * INSTRUCTION_SIZE)));
 
         Ok(())
     }
@@ -2,7 +2,7 @@
 use strum_macros::EnumCount; // to get number of opcodes (OPCODE_COUNT variable)
 
 // PartialEq and Debug needed to test values
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 #[derive(EnumCount)]
 pub enum OpCode
 {
@@ -38,6 +38,7 @@ pub enum OpCode
     _FX18(u8),
     _FX1E(u8),
     _FX29(u8),
+    _FX30(u8),
     _FX33(u8),
     _FX55(u8),
     _FX65(u8),
@@ -85,6 +86,7 @@ impl OpCode
             (0xF, x, 0x1,   0x8) => Ok(_FX18(x)),
             (0xF, x, 0x1,   0xE) => Ok(_FX1E(x)),
             (0xF, x, 0x2,   0x9) => Ok(_FX29(x)),
+            (0xF, x, 0x3,   0x0) => Ok(_FX30(x)),
             (0xF, x, 0x3,   0x3) => Ok(_FX33(x)),
             (0xF, x, 0x5,   0x5) => Ok(_FX55(x)),
             (0xF, x, 0x6,   0x5) => Ok(_FX65(x)),
@@ -92,6 +94,77 @@ impl OpCode
         }
     }
 
+    // Pattern identifying the instruction, e.g. "DXYN"
+    pub fn name(&self) -> &'static str
+    {
+        use OpCode::*;
+
+        match *self
+        {
+            _0NNN(_)  => "0NNN", _00EE     => "00EE", _00E0     => "00E0",
+            _1NNN(_)  => "1NNN", _2NNN(_)  => "2NNN", _3XNN(..) => "3XNN",
+            _4XNN(..) => "4XNN", _5XY0(..) => "5XY0", _6XNN(..) => "6XNN",
+            _7XNN(..) => "7XNN", _8XY0(..) => "8XY0", _8XY1(..) => "8XY1",
+            _8XY2(..) => "8XY2", _8XY3(..) => "8XY3", _8XY4(..) => "8XY4",
+            _8XY5(..) => "8XY5", _8XY6(..) => "8XY6", _8XY7(..) => "8XY7",
+            _8XYE(..) => "8XYE", _9XY0(..) => "9XY0", _ANNN(_)  => "ANNN",
+            _BNNN(_)  => "BNNN", _CXNN(..) => "CXNN", _DXYN(..) => "DXYN",
+            _EX9E(_)  => "EX9E", _EXA1(_)  => "EXA1", _FX07(_)  => "FX07",
+            _FX0A(_)  => "FX0A", _FX15(_)  => "FX15", _FX18(_)  => "FX18",
+            _FX1E(_)  => "FX1E", _FX29(_)  => "FX29", _FX30(_)  => "FX30",
+            _FX33(_)  => "FX33", _FX55(_)  => "FX55", _FX65(_)  => "FX65",
+        }
+    }
+
+    // Raw instruction, the inverse of new()
+    pub fn encode(&self) -> u16
+    {
+        use OpCode::*;
+
+        let xnn = | prefix: u16, x: u8, nn: u8 | prefix << 12 | (x as u16 & 0xF) << 8 | nn as u16;
+        let xyn = | prefix: u16, x: u8, y: u8, n: u8 | xnn(prefix, x, (y & 0xF) << 4 | n & 0xF);
+
+        match *self
+        {
+            _0NNN(nnn)     => nnn & 0xFFF,
+            _00EE          => 0x00EE,
+            _00E0          => 0x00E0,
+            _1NNN(nnn)     => 0x1000 | nnn & 0xFFF,
+            _2NNN(nnn)     => 0x2000 | nnn & 0xFFF,
+            _3XNN(x, nn)   => xnn(0x3, x, nn),
+            _4XNN(x, nn)   => xnn(0x4, x, nn),
+            _5XY0(x, y)    => xyn(0x5, x, y, 0x0),
+            _6XNN(x, nn)   => xnn(0x6, x, nn),
+            _7XNN(x, nn)   => xnn(0x7, x, nn),
+            _8XY0(x, y)    => xyn(0x8, x, y, 0x0),
+            _8XY1(x, y)    => xyn(0x8, x, y, 0x1),
+            _8XY2(x, y)    => xyn(0x8, x, y, 0x2),
+            _8XY3(x, y)    => xyn(0x8, x, y, 0x3),
+            _8XY4(x, y)    => xyn(0x8, x, y, 0x4),
+            _8XY5(x, y)    => xyn(0x8, x, y, 0x5),
+            _8XY6(x, y)    => xyn(0x8, x, y, 0x6),
+            _8XY7(x, y)    => xyn(0x8, x, y, 0x7),
+            _8XYE(x, y)    => xyn(0x8, x, y, 0xE),
+            _9XY0(x, y)    => xyn(0x9, x, y, 0x0),
+            _ANNN(nnn)     => 0xA000 | nnn & 0xFFF,
+            _BNNN(nnn)     => 0xB000 | nnn & 0xFFF,
+            _CXNN(x, nn)   => xnn(0xC, x, nn),
+            _DXYN(x, y, n) => xyn(0xD, x, y, n),
+            _EX9E(x)       => xnn(0xE, x, 0x9E),
+            _EXA1(x)       => xnn(0xE, x, 0xA1),
+            _FX07(x)       => xnn(0xF, x, 0x07),
+            _FX0A(x)       => xnn(0xF, x, 0x0A),
+            _FX15(x)       => xnn(0xF, x, 0x15),
+            _FX18(x)       => xnn(0xF, x, 0x18),
+            _FX1E(x)       => xnn(0xF, x, 0x1E),
+            _FX29(x)       => xnn(0xF, x, 0x29),
+            _FX30(x)       => xnn(0xF, x, 0x30),
+            _FX33(x)       => xnn(0xF, x, 0x33),
+            _FX55(x)       => xnn(0xF, x, 0x55),
+            _FX65(x)       => xnn(0xF, x, 0x65),
+        }
+    }
+
     pub fn disassembly(&self) -> String
     {
         use OpCode::*;
@@ -130,6 +203,7 @@ impl OpCode
             _FX18(x)       => format!("MOV v{}, SOUND", x),
             _FX1E(x)       => format!("ADD I, v{}", x),
             _FX29(x)       => format!("MOV I, v{}", x),
+            _FX30(x)       => format!("MOV I, BIG v{}", x),
             _FX33(x)       => format!("BIN I, v{}", x),
             _FX55(x)       => format!("BATCH I, v{}", x),
             _FX65(x)       => format!("BATCH v{}, I", x),
@@ -204,6 +278,7 @@ mod tests
            (_FX18(5),       "MOV v5, SOUND"),
            (_FX1E(6),       "ADD I, v6"),
            (_FX29(7),       "MOV I, v7"),
+           (_FX30(7),       "MOV I, BIG v7"),
            (_FX33(8),       "BIN I, v8"),
            (_FX55(9),       "BATCH I, v9"),
            (_FX65(10),      "BATCH v10, I"),
@@ -215,4 +290,32 @@ mod tests
                        "Original opcode {:?}", opcode);
         }
     }
+
+    #[test]
+    fn names() -> Result<(), String>
+    {
+        assert_eq!(OpCode::new(0x00, 0xE0)?.name(), "00E0");
+        assert_eq!(OpCode::new(0xD1, 0x25)?.name(), "DXYN");
+        assert_eq!(OpCode::new(0x8A, 0xBE)?.name(), "8XYE");
+        assert_eq!(OpCode::new(0xF3, 0x65)?.name(), "FX65");
+
+        Ok(())
+    }
+
+    #[test]
+    fn encoding() -> Result<(), String>
+    {
+        // Every instruction encodes back to the bytes it was parsed from
+        for raw in &[0x00E0u16, 0x00EE, 0x0123, 0x1ABC, 0x2ABC, 0x3A12, 0x4A12, 0x5AB0,
+                     0x6A12, 0x7A12, 0x8AB0, 0x8AB1, 0x8AB2, 0x8AB3, 0x8AB4, 0x8AB5,
+                     0x8AB6, 0x8AB7, 0x8ABE, 0x9AB0, 0xAABC, 0xBABC, 0xCA12, 0xDAB5,
+                     0xEA9E, 0xEAA1, 0xFA07, 0xFA0A, 0xFA15, 0xFA18, 0xFA1E, 0xFA29,
+                     0xFA30, 0xFA33, 0xFA55, 0xFA65]
+        {
+            let [msb, lsb] = raw.to_be_bytes();
+            assert_eq!(OpCode::new(msb, lsb)?.encode(), *raw, "Wrong encoding of {:04X}", raw);
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,74 @@
+// Hardware or interpreter a rom was written for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Platform
+{
+    CosmacVip,
+    Dream6800,
+    Eti660,
+    Schip,
+    XoChip,
+}
+
+impl Default for Platform
+{
+    fn default() -> Self
+    {
+        Platform::CosmacVip
+    }
+}
+
+// Names accepted by parse()
+pub const PLATFORM_NAMES : [&str; 5] = [ "chip8", "dream6800", "eti660", "schip", "xochip" ];
+
+impl Platform
+{
+    pub fn parse(name: &str) -> Result<Self, String>
+    {
+        use Platform::*;
+
+        match name.to_lowercase().as_str()
+        {
+            "chip8" | "vip" | "cosmac-vip" => Ok(CosmacVip),
+            "dream6800"                    => Ok(Dream6800),
+            "eti660"                       => Ok(Eti660),
+            "schip" | "superchip"          => Ok(Schip),
+            "xochip" | "xo-chip"           => Ok(XoChip),
+            _                              => Err(format!("Unknown platform '{}'. Valid platforms are {}",
+                                                          name, PLATFORM_NAMES.join(", "))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        use Platform::*;
+
+        match self
+        {
+            CosmacVip => "chip8",
+            Dream6800 => "dream6800",
+            Eti660    => "eti660",
+            Schip     => "schip",
+            XoChip    => "xochip",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn names() -> Result<(), String>
+    {
+        for &name in PLATFORM_NAMES.iter()
+        {
+            assert_eq!(Platform::parse(name)?.name(), name);
+        }
+
+        assert_eq!(Platform::parse("VIP")?, Platform::CosmacVip);
+        assert!(Platform::parse("gameboy").is_err());
+
+        Ok(())
+    }
+}
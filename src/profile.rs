@@ -0,0 +1,350 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::memory::INSTRUCTION_SIZE;
+use crate::opcodes::OpCode;
+
+// Entries listed in the hot loops section of the report
+const NUM_HOT_LOOPS : usize = 10;
+
+// Largest loop around FX07 counted as waiting for the delay timer, e.g.
+// FX07, a skip and a jump back
+const MAX_POLLING_LOOP_SIZE : usize = 4 * INSTRUCTION_SIZE;
+
+// A backward jump, and how many times it was taken
+#[derive(Clone, Debug, PartialEq)]
+pub struct HotLoop
+{
+    pub start      : usize,
+    pub end        : usize,
+    pub iterations : u64,
+    // Instructions executed inside the loop body
+    pub executed   : u64,
+}
+
+// Counts what a rom executes: how often each address runs, the mix of
+// instructions, and the time spent waiting for a key or the delay timer.
+// The report is written when the profiler finishes.
+pub struct Profiler
+{
+    path            : PathBuf,
+    program         : Range<usize>,
+    address_counts  : Vec<u64>,
+    opcode_counts   : BTreeMap<&'static str, u64>,
+    loops           : BTreeMap<(usize, usize), u64>,
+    // Address of the last instruction when it was a jump or a skip
+    previous_jump   : Option<usize>,
+    instructions    : u64,
+    key_wait        : u64,
+    delay_wait      : u64,
+    // Address of an FX07 that read a running timer, and the instructions
+    // run since then
+    delay_poll      : Option<usize>,
+    poll_length     : u64,
+}
+
+// Public impl
+impl Profiler
+{
+    // The program range is where the rom was loaded, the part of the
+    // memory checked for code that never ran
+    pub fn new(path: PathBuf, ram_size: usize, program: Range<usize>) -> Self
+    {
+        Profiler { path, program,
+                   address_counts : vec![0; ram_size],
+                   opcode_counts  : BTreeMap::new(),
+                   loops          : BTreeMap::new(),
+                   previous_jump  : None,
+                   instructions   : 0,
+                   key_wait       : 0,
+                   delay_wait     : 0,
+                   delay_poll     : None,
+                   poll_length    : 0,
+                 }
+    }
+
+    // Called for every executed instruction. The delay timer value is
+    // the one read by FX07, if that's the instruction.
+    pub fn record(&mut self, address: usize, opcode: &OpCode, delay_timer: u8)
+    {
+        self.instructions += 1;
+
+        if let Some(count) = self.address_counts.get_mut(address)
+        {
+            *count += 1;
+        }
+
+        *self.opcode_counts.entry(opcode.name()).or_insert(0) += 1;
+
+        // A jump or skip landing at or before itself closes a loop. Calls
+        // and returns do not.
+        if let Some(jump) = self.previous_jump.take()
+        {
+            if address <= jump
+            {
+                *self.loops.entry((address, jump)).or_insert(0) += 1;
+                self.close_polling_loop(address, jump);
+            }
+        }
+
+        if is_jump_or_skip(opcode)
+        {
+            self.previous_jump = Some(address);
+        }
+
+        match opcode
+        {
+            // FX0A runs again until a key is pressed
+            OpCode::_FX0A(_) => self.key_wait += 1,
+
+            // Reading a running delay timer may start a polling loop, which
+            // lasts until the timer is read as zero
+            OpCode::_FX07(_) =>
+            {
+                self.delay_poll  = if delay_timer > 0 { Some(address) } else { None };
+                self.poll_length = 0;
+            },
+
+            _ => (),
+        }
+
+        self.poll_length += 1;
+    }
+
+    pub fn instructions(&self) -> u64
+    {
+        self.instructions
+    }
+
+    pub fn execution_count(&self, address: usize) -> u64
+    {
+        self.address_counts.get(address).cloned().unwrap_or(0)
+    }
+
+    pub fn key_wait(&self) -> u64
+    {
+        self.key_wait
+    }
+
+    pub fn delay_wait(&self) -> u64
+    {
+        self.delay_wait
+    }
+
+    // Loops sorted by the instructions executed inside them
+    pub fn hot_loops(&self) -> Vec<HotLoop>
+    {
+        let mut loops: Vec<HotLoop> = self.loops.iter()
+            .map(|(&(start, end), &iterations)|
+            {
+                let executed = self.address_counts[start..=end].iter().sum();
+                HotLoop { start, end, iterations, executed }
+            })
+            .collect();
+
+        loops.sort_by(|a, b| b.executed.cmp(&a.executed).then(a.start.cmp(&b.start)));
+
+        return loops;
+    }
+
+    // Parts of the program that never ran. Most of the time they hold
+    // sprites or other data.
+    pub fn unexecuted_ranges(&self) -> Vec<Range<usize>>
+    {
+        let mut covered = vec![false; self.address_counts.len()];
+
+        for (address, &count) in self.address_counts.iter().enumerate()
+        {
+            if count == 0 { continue; }
+
+            for offset in 0..INSTRUCTION_SIZE
+            {
+                if let Some(byte) = covered.get_mut(address + offset)
+                {
+                    *byte = true;
+                }
+            }
+        }
+
+        let mut ranges = Vec::new();
+        let mut start  = None;
+
+        for address in self.program.clone()
+        {
+            match (covered[address], start)
+            {
+                (false, None)        => start = Some(address),
+                (true,  Some(first)) => { ranges.push(first..address); start = None; },
+                _                    => (),
+            }
+        }
+
+        if let Some(first) = start
+        {
+            ranges.push(first..self.program.end);
+        }
+
+        return ranges;
+    }
+
+    pub fn report(&self) -> String
+    {
+        let percent = | count: u64 | 100.0 * count as f64 / self.instructions.max(1) as f64;
+
+        let mut report = format!("Executed instructions: {}\n", self.instructions);
+
+        report += "\nHot loops:\n";
+
+        for hot_loop in self.hot_loops().iter().take(NUM_HOT_LOOPS)
+        {
+            report += &format!("  {:#06X}-{:#06X}  {:>10} iterations  {:>12} instructions ({:.1}%)\n",
+                               hot_loop.start, hot_loop.end, hot_loop.iterations,
+                               hot_loop.executed, percent(hot_loop.executed));
+        }
+
+        report += "\nNever executed:\n";
+
+        for range in self.unexecuted_ranges()
+        {
+            report += &format!("  {:#06X}-{:#06X}  {} bytes\n", range.start, range.end - 1, range.len());
+        }
+
+        report += "\nInstruction mix:\n";
+
+        let mut mix: Vec<_> = self.opcode_counts.iter().collect();
+        mix.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+        for (name, &count) in mix
+        {
+            report += &format!("  {}  {:>12}  {:5.1}%\n", name, count, percent(count));
+        }
+
+        report += "\nWaiting:\n";
+        report += &format!("  key (FX0A)   {:>12}  {:5.1}%\n", self.key_wait, percent(self.key_wait));
+        report += &format!("  delay timer  {:>12}  {:5.1}%\n", self.delay_wait, percent(self.delay_wait));
+
+        return report;
+    }
+
+    pub fn finish(self) -> Result<(), String>
+    {
+        fs::write(&self.path, self.report()).map_err(|e| e.to_string())
+    }
+}
+
+// Private impl
+impl Profiler
+{
+    // The instructions since a running delay timer was read were waiting
+    // for it when a small loop around the FX07 goes back to read it again
+    fn close_polling_loop(&mut self, start: usize, end: usize)
+    {
+        match self.delay_poll
+        {
+            Some(poll) if start <= poll && end - start < MAX_POLLING_LOOP_SIZE =>
+                self.delay_wait += self.poll_length,
+            _ =>
+                self.delay_poll = None,
+        }
+
+        self.poll_length = 0;
+    }
+}
+
+fn is_jump_or_skip(opcode: &OpCode) -> bool
+{
+    use OpCode::*;
+
+    matches!(opcode, _1NNN(_) | _BNNN(_) | _3XNN(..) | _4XNN(..) | _5XY0(..) | _9XY0(..) | _EX9E(_) | _EXA1(_))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn profiler() -> Profiler
+    {
+        Profiler::new(PathBuf::from("profile.txt"), 0x1000, 0x200..0x210)
+    }
+
+    #[test]
+    fn execution_counts()
+    {
+        let mut profiler = profiler();
+
+        // A two instruction loop run three times
+        for _ in 0..3
+        {
+            profiler.record(0x200, &OpCode::_7XNN(0, 1), 0);
+            profiler.record(0x202, &OpCode::_1NNN(0x200), 0);
+        }
+
+        assert_eq!(profiler.instructions(), 6);
+        assert_eq!(profiler.execution_count(0x200), 3);
+        assert_eq!(profiler.execution_count(0x204), 0);
+
+        let loops = profiler.hot_loops();
+
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0], HotLoop { start: 0x200, end: 0x202, iterations: 2, executed: 6 });
+
+        // Returning from a subroutine is not a loop
+        profiler.record(0x204, &OpCode::_2NNN(0x208), 0);
+        profiler.record(0x208, &OpCode::_00EE, 0);
+        profiler.record(0x206, &OpCode::_00E0, 0);
+
+        assert_eq!(profiler.hot_loops().len(), 1);
+    }
+
+    #[test]
+    fn coverage()
+    {
+        let mut profiler = profiler();
+
+        profiler.record(0x200, &OpCode::_00E0, 0);
+        profiler.record(0x206, &OpCode::_1NNN(0x206), 0);
+
+        // Both bytes of an instruction are covered
+        assert_eq!(profiler.unexecuted_ranges(), vec![0x202..0x206, 0x208..0x210]);
+    }
+
+    #[test]
+    fn waiting()
+    {
+        let mut profiler = profiler();
+
+        // Key wait
+        profiler.record(0x200, &OpCode::_FX0A(0), 0);
+        profiler.record(0x200, &OpCode::_FX0A(0), 0);
+
+        // Delay timer polling loop, until the timer reads zero
+        for delay in (0..3).rev()
+        {
+            profiler.record(0x202, &OpCode::_FX07(0), delay);
+            profiler.record(0x204, &OpCode::_3XNN(0, 0), 0);
+            profiler.record(0x206, &OpCode::_1NNN(0x202), 0);
+        }
+
+        profiler.record(0x208, &OpCode::_00E0, 0);
+
+        assert_eq!(profiler.key_wait(), 2);
+        assert_eq!(profiler.delay_wait(), 6);
+
+        let report = profiler.report();
+
+        assert!(report.contains("Hot loops:\n  0x0202-0x0206"), "Missing loop in report");
+        assert!(report.contains("Never executed:\n  0x020A-0x020F  6 bytes"), "Missing range");
+        assert!(report.contains("FX07             3"), "Missing instruction mix");
+
+        // Work done after reading a running timer is not waiting
+        profiler.record(0x300, &OpCode::_FX07(0), 5);
+        profiler.record(0x302, &OpCode::_7XNN(1, 1), 0);
+        profiler.record(0x304, &OpCode::_2NNN(0x400), 0);
+        profiler.record(0x400, &OpCode::_00EE, 0);
+
+        assert_eq!(profiler.delay_wait(), 6);
+    }
+}
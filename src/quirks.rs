@@ -0,0 +1,130 @@
+use crate::platform::Platform;
+
+// Behaviors that changed between CHIP-8 interpreters. Roms written for
+// one of them may break when run with the behavior of another.
+// By default none is enabled, matching the SCHIP behavior for shifts and
+// memory copies.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quirks
+{
+    // 8XY1, 8XY2 and 8XY3 reset VF
+    pub vf_reset           : bool,
+    // 8XY6 and 8XYE shift VY into VX, instead of shifting VX in place
+    pub shift_uses_vy      : bool,
+    // FX55 and FX65 leave I after the last copied address
+    pub memory_increments_i: bool,
+    // BNNN jumps to XNN plus VX, instead of NNN plus V0
+    pub jump_uses_vx       : bool,
+    // Sprites wrap around the screen edges instead of being clipped
+    pub sprite_wrap        : bool,
+}
+
+// Names accepted by parse(), in the order of the fields
+pub const QUIRK_NAMES : [&str; 5] = [ "vf-reset", "shift-vy", "memory-increment", "jump-vx", "wrap" ];
+
+impl Quirks
+{
+    pub fn for_platform(platform: Platform) -> Self
+    {
+        use Platform::*;
+
+        match platform
+        {
+            CosmacVip | Dream6800 | Eti660 =>
+                Quirks { vf_reset: true, shift_uses_vy: true, memory_increments_i: true,
+                         ..Quirks::default() },
+            Schip  =>
+                Quirks { jump_uses_vx: true, ..Quirks::default() },
+            XoChip =>
+                Quirks { shift_uses_vy: true, memory_increments_i: true, sprite_wrap: true,
+                         ..Quirks::default() },
+        }
+    }
+
+    // Applies a comma separated list of changes to the given quirks.
+    // Names enable a quirk, and disable it when prefixed with '-'.
+    // "none" disables all of them, e.g. "none,shift-vy,-wrap".
+    pub fn parse(spec: &str, base: Quirks) -> Result<Self, String>
+    {
+        let mut quirks = base;
+
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty())
+        {
+            if item == "none"
+            {
+                quirks = Quirks::default();
+                continue;
+            }
+
+            let (name, enabled) = match item.strip_prefix('-')
+            {
+                Some(name) => (name, false),
+                None       => (item.strip_prefix('+').unwrap_or(item), true),
+            };
+
+            *quirks.flag_mut(name)? = enabled;
+        }
+
+        Ok(quirks)
+    }
+
+    // Names of the enabled quirks
+    pub fn enabled(&self) -> Vec<&'static str>
+    {
+        let flags = [ self.vf_reset, self.shift_uses_vy, self.memory_increments_i,
+                      self.jump_uses_vx, self.sprite_wrap ];
+
+        QUIRK_NAMES.iter().zip(flags.iter())
+                   .filter(|(_, &enabled)| enabled)
+                   .map(|(&name, _)| name)
+                   .collect()
+    }
+
+    fn flag_mut(&mut self, name: &str) -> Result<&mut bool, String>
+    {
+        match name
+        {
+            "vf-reset"         => Ok(&mut self.vf_reset),
+            "shift-vy"         => Ok(&mut self.shift_uses_vy),
+            "memory-increment" => Ok(&mut self.memory_increments_i),
+            "jump-vx"          => Ok(&mut self.jump_uses_vx),
+            "wrap"             => Ok(&mut self.sprite_wrap),
+            _                  => Err(format!("Unknown quirk '{}'. Valid quirks are {}",
+                                              name, QUIRK_NAMES.join(", "))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn platform_quirks()
+    {
+        let vip = Quirks::for_platform(Platform::CosmacVip);
+        assert_eq!(vip.enabled(), vec!["vf-reset", "shift-vy", "memory-increment"]);
+
+        assert_eq!(Quirks::for_platform(Platform::Schip).enabled(), vec!["jump-vx"]);
+        assert!(Quirks::for_platform(Platform::XoChip).sprite_wrap);
+        assert!(Quirks::default().enabled().is_empty());
+    }
+
+    #[test]
+    fn parsing() -> Result<(), String>
+    {
+        let vip    = Quirks::for_platform(Platform::CosmacVip);
+        let quirks = Quirks::parse("-vf-reset, wrap", vip)?;
+
+        assert_eq!(quirks.enabled(), vec!["shift-vy", "memory-increment", "wrap"]);
+
+        let quirks = Quirks::parse("none,+jump-vx", vip)?;
+        assert_eq!(quirks.enabled(), vec!["jump-vx"]);
+
+        assert_eq!(Quirks::parse("", vip)?, vip);
+        assert!(Quirks::parse("shift", vip).is_err(), "Unknown quirk accepted");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,330 @@
+use std::fs::{ self, File };
+use std::io::BufWriter;
+use std::path::{ Path, PathBuf };
+
+use crate::audio::{ OfflineAudio, ToneControl, DEFAULT_SAMPLE_RATE };
+use crate::clock::DEFAULT_TIMERS_FREQUENCY;
+use crate::display::{ Palette, PixelGrid };
+
+pub const DEFAULT_RECORDING_SCALE : u16 = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordingFormat
+{
+    // A single animated gif. Consecutive identical frames are merged
+    // into one with a longer delay.
+    Gif(PathBuf),
+    // A directory containing one numbered png per frame plus an
+    // audio.wav file with the speakers output, ready to be muxed.
+    PngSequence(PathBuf),
+}
+
+impl RecordingFormat
+{
+    // A gif for paths ending in .gif, a png sequence directory otherwise
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self
+    {
+        let path   = path.as_ref();
+        let is_gif = path.extension().map(|extension| extension.eq_ignore_ascii_case("gif"))
+                                     .unwrap_or(false);
+
+        match is_gif
+        {
+            true  => RecordingFormat::Gif(path.to_path_buf()),
+            false => RecordingFormat::PngSequence(path.to_path_buf()),
+        }
+    }
+}
+
+struct RecordedFrame
+{
+    pixels  : Vec<bool>,
+    repeats : u32,
+}
+
+pub struct Recorder
+{
+    format  : RecordingFormat,
+    palette : Palette,
+    scale   : u16,
+    width   : usize,
+    height  : usize,
+    frames  : Vec<RecordedFrame>,
+    audio   : OfflineAudio,
+}
+
+// Public impl
+impl Recorder
+{
+    // The audio track follows the given buzzer control, the one of the
+    // speakers being recorded
+    pub fn new(format: RecordingFormat, palette: Palette, scale: u16, tone: ToneControl) -> Self
+    {
+        Recorder { format, palette,
+                   scale   : scale.max(1),
+                   width   : 0,
+                   height  : 0,
+                   frames  : Vec::new(),
+                   audio   : OfflineAudio::with_control(DEFAULT_SAMPLE_RATE as u32, tone),
+                 }
+    }
+
+    // Stores a presented frame. Must be called once per frame, at the
+    // timers frequency, so the recording keeps the original timing.
+    pub fn capture(&mut self, grid: &PixelGrid)
+    {
+        self.width  = grid.width();
+        self.height = grid.height();
+
+        match self.frames.last_mut()
+        {
+            Some(last) if last.pixels[..] == grid.peek()[..] => last.repeats += 1,
+            _ => self.frames.push(RecordedFrame { pixels: grid.peek().to_vec(), repeats: 1 }),
+        }
+
+        // The audio is always rendered so the envelope fades the tone
+        // out like the speakers would
+        if let RecordingFormat::PngSequence(_) = self.format
+        {
+            self.audio.render_frame(DEFAULT_TIMERS_FREQUENCY);
+        }
+    }
+
+    pub fn num_frames(&self) -> u32
+    {
+        self.frames.iter().map(|frame| frame.repeats).sum()
+    }
+
+    pub fn num_unique_frames(&self) -> usize
+    {
+        self.frames.len()
+    }
+
+    pub fn finish(self) -> Result<(), String>
+    {
+        match &self.format
+        {
+            RecordingFormat::Gif(path)             => self.write_gif(path),
+            RecordingFormat::PngSequence(directory) => self.write_png_sequence(directory),
+        }
+    }
+}
+
+// Private impl
+impl Recorder
+{
+    // Expands a frame into one byte per pixel, each one holding the
+    // value returned by the given closure
+    fn scaled_pixels<F>(&self, frame: &RecordedFrame, bytes: F) -> Vec<u8>
+        where F: Fn(bool) -> Vec<u8>
+    {
+        let scale     = self.scale as usize;
+        let mut image = Vec::new();
+
+        for row in 0..self.height * scale
+        {
+            for col in 0..self.width * scale
+            {
+                let pixel = frame.pixels[(row / scale) * self.width + col / scale];
+                image.extend(bytes(pixel));
+            }
+        }
+
+        return image;
+    }
+
+    fn write_gif(&self, path: &Path) -> Result<(), String>
+    {
+        let width   = (self.width  * self.scale as usize) as u16;
+        let height  = (self.height * self.scale as usize) as u16;
+        let palette = [ self.palette.off.r, self.palette.off.g, self.palette.off.b,
+                        self.palette.on.r,  self.palette.on.g,  self.palette.on.b ];
+
+        let file        = File::create(path).map_err(|e| e.to_string())?;
+        let mut encoder = gif::Encoder::new(BufWriter::new(file), width, height, &palette)
+                            .map_err(|e| e.to_string())?;
+
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(|e| e.to_string())?;
+
+        // Gif delays are expressed in hundredths of a second. The frame
+        // boundaries are rounded from the absolute time to avoid drifting.
+        let frame_rate   = DEFAULT_TIMERS_FREQUENCY.value();
+        let to_hundredth = | frame: u32 | (frame as f64 * 100.0 / frame_rate).round() as u16;
+        let mut elapsed  = 0;
+
+        for recorded in self.frames.iter()
+        {
+            let pixels    = self.scaled_pixels(recorded, |pixel| vec![pixel as u8]);
+            let mut frame = gif::Frame::from_indexed_pixels(width, height, &pixels, None);
+
+            frame.delay = to_hundredth(elapsed + recorded.repeats) - to_hundredth(elapsed);
+            elapsed    += recorded.repeats;
+
+            encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    fn write_png_sequence(&self, directory: &Path) -> Result<(), String>
+    {
+        fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+
+        let width       = (self.width  * self.scale as usize) as u32;
+        let height      = (self.height * self.scale as usize) as u32;
+        let mut counter = 0;
+
+        for recorded in self.frames.iter()
+        {
+            let color  = | pixel | { let c = self.palette.color(pixel); vec![c.r, c.g, c.b] };
+            let pixels = self.scaled_pixels(recorded, color);
+
+            for _ in 0..recorded.repeats
+            {
+                let path = directory.join(format!("frame_{:05}.png", counter));
+                let file = File::create(path).map_err(|e| e.to_string())?;
+
+                let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+                encoder.set_color(png::ColorType::RGB);
+                encoder.set_depth(png::BitDepth::Eight);
+
+                let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+                writer.write_image_data(&pixels).map_err(|e| e.to_string())?;
+
+                counter += 1;
+            }
+        }
+
+        self.audio.write_wav(directory.join("audio.wav"))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::audio::Tone;
+    use crate::display::GridEditor;
+
+    fn output_path(name: &str) -> PathBuf
+    {
+        std::env::temp_dir().join(format!("chust8_recorder_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn frame_deduplication()
+    {
+        let mut editor   = GridEditor::new();
+        let mut recorder = Recorder::new(RecordingFormat::Gif(output_path("dedup.gif")),
+                                         Palette::default(), 1, ToneControl::new(Tone::default()));
+
+        recorder.capture(editor.grid());
+        recorder.capture(editor.grid());
+        recorder.capture(editor.grid());
+
+        assert_eq!(recorder.num_frames(), 3, "Not all frames were recorded");
+        assert_eq!(recorder.num_unique_frames(), 1, "Identical frames were not merged");
+
+        editor.mut_grid().set(0, 0, true).unwrap();
+        recorder.capture(editor.grid());
+
+        assert_eq!(recorder.num_frames(), 4, "Not all frames were recorded");
+        assert_eq!(recorder.num_unique_frames(), 2, "Different frame was merged");
+    }
+
+    #[test]
+    fn gif_recording() -> Result<(), String>
+    {
+        let path         = output_path("recording.gif");
+        let mut editor   = GridEditor::new();
+        let mut recorder = Recorder::new(RecordingFormat::Gif(path.clone()),
+                                         Palette::default(), 2, ToneControl::new(Tone::default()));
+
+        for frame in 0..10
+        {
+            editor.mut_grid().set(0, frame, true)?;
+            recorder.capture(editor.grid());
+        }
+
+        recorder.finish()?;
+
+        let data = fs::read(&path).map_err(|e| e.to_string())?;
+        fs::remove_file(&path).map_err(|e| e.to_string())?;
+
+        assert_eq!(&data[0..6], b"GIF89a", "Unexpected gif header");
+
+        // Logical screen size is stored right after the header
+        assert_eq!(u16::from_le_bytes([data[6], data[7]]), 128, "Unexpected gif width");
+        assert_eq!(u16::from_le_bytes([data[8], data[9]]), 64,  "Unexpected gif height");
+
+        Ok(())
+    }
+
+    #[test]
+    fn png_sequence_recording() -> Result<(), String>
+    {
+        let directory    = output_path("sequence");
+        let editor       = GridEditor::new();
+        let tone         = ToneControl::new(Tone::default());
+        let mut recorder = Recorder::new(RecordingFormat::PngSequence(directory.clone()),
+                                         Palette::default(), 1, tone.clone());
+
+        tone.set_playing(true);
+        recorder.capture(editor.grid());
+        tone.set_playing(false);
+        recorder.capture(editor.grid());
+
+        recorder.finish()?;
+
+        let frame_0 = directory.join("frame_00000.png");
+        let frame_1 = directory.join("frame_00001.png");
+        let audio   = fs::read(directory.join("audio.wav")).map_err(|e| e.to_string())?;
+
+        assert!(frame_0.exists() && frame_1.exists(), "Missing frames in png sequence");
+
+        // Two frames worth of 16 bits samples plus the wav header
+        assert_eq!(audio.len(), 44 + 2 * 735 * 2, "Unexpected audio track length");
+
+        // The first frame had sound, the second one fades out to silence
+        assert!(audio[44..44 + 735 * 2].iter().any(|&byte| byte != 0),
+                "Sound frame was recorded as silence");
+        assert!(audio[44 + 735 * 2 + 441 * 2..].iter().all(|&byte| byte == 0),
+                "Silent frame was recorded with sound");
+
+        fs::remove_dir_all(&directory).map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn recorded_tone() -> Result<(), String>
+    {
+        let directory    = output_path("muted");
+        let editor       = GridEditor::new();
+        let tone         = ToneControl::new(Tone::default());
+        let mut recorder = Recorder::new(RecordingFormat::PngSequence(directory.clone()),
+                                         Palette::default(), 1, tone.clone());
+
+        // Muting the speakers mutes the recording
+        tone.set_playing(true);
+        tone.set_muted(true);
+        recorder.capture(editor.grid());
+        recorder.finish()?;
+
+        let audio = fs::read(directory.join("audio.wav")).map_err(|e| e.to_string())?;
+        fs::remove_dir_all(&directory).map_err(|e| e.to_string())?;
+
+        assert!(audio[44..].iter().all(|&byte| byte == 0), "Muted buzzer was recorded");
+
+        Ok(())
+    }
+
+    #[test]
+    fn format_from_path()
+    {
+        assert_eq!(RecordingFormat::from_path("run.gif"), RecordingFormat::Gif(PathBuf::from("run.gif")));
+        assert_eq!(RecordingFormat::from_path("run.GIF"), RecordingFormat::Gif(PathBuf::from("run.GIF")));
+        assert_eq!(RecordingFormat::from_path("frames"),
+                   RecordingFormat::PngSequence(PathBuf::from("frames")));
+    }
+}
@@ -33,7 +33,7 @@ impl IRegister
     }
 }
 
-const NUM_DATA_REGISTERS : usize = 16;
+pub const NUM_DATA_REGISTERS : usize = 16;
 
 pub type AllDataRegisters = ArrayVec::<[DataRegister; NUM_DATA_REGISTERS]>;
 
@@ -94,7 +94,7 @@ impl DataRegister
 
     pub fn shift_left(&mut self) -> u8
     {
-        let most_significant = (self.value >> 6) & 0x1;
+        let most_significant = (self.value >> 7) & 0x1;
         self.value <<= 1;
 
         return most_significant;
@@ -173,10 +173,11 @@ mod tests
         data_register.set(test_value);
         assert_eq!(data_register.get(), test_value);
 
-        assert_eq!(data_register.shift_left(), 0b1);
+        // The flag is the most significant bit, not the one after it
+        assert_eq!(data_register.shift_left(), 0b0);
         assert_eq!(data_register.get(), test_value << 1);
 
-        assert_eq!(data_register.shift_left(), 0b0);
+        assert_eq!(data_register.shift_left(), 0b1);
         assert_eq!(data_register.get(), test_value << 2);
     }
 
@@ -0,0 +1,442 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::display::Palette;
+use crate::font::FontSet;
+use crate::input::{ keymap_spec, parse_keymap, DEFAULT_KEY_MAPPING };
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+
+const DATABASE_FILE : &str = "roms.json";
+
+// SHA-1 of the rom contents, in lowercase hexadecimal. It's the key used
+// by the community CHIP-8 database too.
+pub fn rom_hash(rom: &[u8]) -> String
+{
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+// Settings remembered for a rom. Missing ones keep the defaults.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RomSettings
+{
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title        : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author       : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform     : Option<String>,
+    // Names of the enabled quirks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quirks       : Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font         : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_address : Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipf          : Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keymap       : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette      : Option<String>,
+}
+
+impl RomSettings
+{
+    // Settings of the config that differ from the defaults
+    pub fn from_config(config: &Config) -> Self
+    {
+        let defaults = Config::default();
+
+        RomSettings { title        : None,
+                      author       : None,
+                      platform     : config.platform.map(|platform| platform.name().to_string()),
+                      quirks       : config.quirks.map(|quirks| quirks.enabled().iter()
+                                                                      .map(|name| name.to_string())
+                                                                      .collect()),
+                      font         : config.font.map(|font| font.name().to_string()),
+                      font_address : config.font_address,
+                      ipf          : Some(config.instructions_per_frame)
+                                         .filter(|&ipf| ipf != defaults.instructions_per_frame),
+                      keymap       : Some(config.keymap)
+                                         .filter(|keymap| *keymap != DEFAULT_KEY_MAPPING)
+                                         .map(|keymap| keymap_spec(&keymap)),
+                      palette      : config.palette.map(|palette| palette.spec()),
+                    }
+    }
+
+    // Overwrites the config with the stored settings
+    pub fn apply(&self, config: &mut Config) -> Result<(), String>
+    {
+        if let Some(name) = &self.platform
+        {
+            config.platform = Some(Platform::parse(name)?);
+        }
+
+        if let Some(names) = &self.quirks
+        {
+            config.quirks = Some(Quirks::parse(&names.join(","), Quirks::default())?);
+        }
+
+        if let Some(name) = &self.font
+        {
+            config.font = Some(FontSet::parse(name)?);
+        }
+
+        if let Some(address) = self.font_address
+        {
+            config.font_address = Some(address);
+        }
+
+        if let Some(ipf) = self.ipf
+        {
+            config.instructions_per_frame = ipf;
+        }
+
+        if let Some(spec) = &self.keymap
+        {
+            config.keymap = parse_keymap(spec)?;
+        }
+
+        if let Some(spec) = &self.palette
+        {
+            config.palette = Some(Palette::parse(spec)?);
+        }
+
+        Ok(())
+    }
+
+    // Keeps the settings of self, filling the missing ones from other
+    fn merge(self, other: RomSettings) -> Self
+    {
+        RomSettings { title        : self.title.or(other.title),
+                      author       : self.author.or(other.author),
+                      platform     : self.platform.or(other.platform),
+                      quirks       : self.quirks.or(other.quirks),
+                      font         : self.font.or(other.font),
+                      font_address : self.font_address.or(other.font_address),
+                      ipf          : self.ipf.or(other.ipf),
+                      keymap       : self.keymap.or(other.keymap),
+                      palette      : self.palette.or(other.palette),
+                    }
+    }
+}
+
+// Settings of every known rom, keyed by rom_hash(), stored as json
+pub struct SettingsDatabase
+{
+    path    : PathBuf,
+    entries : BTreeMap<String, RomSettings>,
+}
+
+// Public impl
+impl SettingsDatabase
+{
+    // In the user configuration directory, e.g. ~/.config/chust8/roms.json
+    pub fn default_path() -> Option<PathBuf>
+    {
+        let config_dir = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+
+        Some(config_dir.join("chust8").join(DATABASE_FILE))
+    }
+
+    // Reads the database, which starts empty if the file does not exist
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String>
+    {
+        let path = path.as_ref().to_path_buf();
+
+        let entries = match fs::read_to_string(&path)
+        {
+            Ok(json) => serde_json::from_str(&json)
+                            .map_err(|e| format!("{}: {}", path.display(), e))?,
+            Err(_)   => BTreeMap::new(),
+        };
+
+        Ok(SettingsDatabase { path, entries })
+    }
+
+    pub fn save(&self) -> Result<(), String>
+    {
+        if let Some(dir) = self.path.parent()
+        {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+
+        let json = serde_json::to_string_pretty(&self.entries).map_err(|e| e.to_string())?;
+        fs::write(&self.path, json).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&RomSettings>
+    {
+        self.entries.get(hash)
+    }
+
+    // Stores the settings of a rom. Title and author are kept when the
+    // new settings don't have them.
+    pub fn insert(&mut self, hash: &str, settings: RomSettings)
+    {
+        let settings = match self.entries.remove(hash)
+        {
+            Some(previous) => RomSettings { title  : settings.title.clone().or(previous.title),
+                                            author : settings.author.clone().or(previous.author),
+                                            ..settings },
+            None           => settings,
+        };
+
+        self.entries.insert(hash.to_string(), settings);
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.entries.is_empty()
+    }
+
+    // Adds the roms of a programs.json file from the community CHIP-8
+    // database. Settings already in the database take precedence.
+    // Returns how many roms were read.
+    pub fn import_community(&mut self, json: &str) -> Result<usize, String>
+    {
+        let programs: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let programs = programs.as_array().ok_or("Expected a list of programs")?;
+
+        let mut imported = 0;
+
+        for program in programs
+        {
+            let title  = program["title"].as_str().map(String::from);
+            let author = program["authors"].as_array()
+                .map(|authors| authors.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", "))
+                .filter(|authors| !authors.is_empty());
+
+            let roms = match program["roms"].as_object()
+            {
+                Some(roms) => roms,
+                None       => continue,
+            };
+
+            for (hash, rom) in roms
+            {
+                let hash     = hash.to_lowercase();
+                let settings = RomSettings { title: title.clone(), author: author.clone(),
+                                             ..community_settings(rom) };
+
+                let settings = match self.entries.remove(&hash)
+                {
+                    Some(existing) => existing.merge(settings),
+                    None           => settings,
+                };
+
+                self.entries.insert(hash, settings);
+                imported += 1;
+            }
+        }
+
+        Ok(imported)
+    }
+}
+
+// Settings of a rom entry of the community database
+fn community_settings(rom: &Value) -> RomSettings
+{
+    // The first platform listed is the preferred one
+    let platform_name = rom["platforms"].as_array()
+        .and_then(|platforms| platforms.iter().filter_map(Value::as_str)
+                                       .find(|name| community_platform(name).is_some()));
+
+    let platform = platform_name.and_then(community_platform);
+
+    // Quirk flags given for the platform change its default quirks
+    let quirks = match (platform, platform_name)
+    {
+        (Some(platform), Some(name)) => rom["quirkyPlatforms"][name].as_object().map(|flags|
+        {
+            let flag  = | name: &str | flags.get(name).and_then(Value::as_bool);
+            let mut quirks = Quirks::for_platform(platform);
+
+            if let Some(value) = flag("logic")  { quirks.vf_reset      = value; }
+            if let Some(value) = flag("shift")  { quirks.shift_uses_vy = !value; }
+            if let Some(value) = flag("jump")   { quirks.jump_uses_vx  = value; }
+            if let Some(value) = flag("wrap")   { quirks.sprite_wrap   = value; }
+
+            if let Some(value) = flag("memoryLeaveIUnchanged") { quirks.memory_increments_i = !value; }
+
+            quirks.enabled().iter().map(|name| name.to_string()).collect()
+        }),
+        _ => None,
+    };
+
+    // Colors are listed from unlit to lit
+    let palette = rom["colors"]["pixels"].as_array()
+        .and_then(|pixels| match pixels.as_slice()
+        {
+            [off, on, ..] => Some(format!("{},{}", on.as_str()?, off.as_str()?)),
+            _             => None,
+        })
+        .filter(|spec| Palette::parse(spec).is_ok());
+
+    RomSettings { platform : platform.map(|platform| platform.name().to_string()),
+                  quirks,
+                  ipf      : rom["tickrate"].as_u64().map(|ipf| ipf as u32).filter(|&ipf| ipf > 0),
+                  palette,
+                  ..RomSettings::default()
+                }
+}
+
+// Platform of a community database name. Modern CHIP-8 is no real
+// platform, so its roms keep the default settings, and CHIP-8X roms use
+// instructions no supported platform has.
+fn community_platform(name: &str) -> Option<Platform>
+{
+    match name
+    {
+        "originalChip8" | "hybridVIP"         => Some(Platform::CosmacVip),
+        "chip48" | "superchip1" | "superchip" => Some(Platform::Schip),
+        "xochip"                              => Some(Platform::XoChip),
+        _                                     => None,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const COMMUNITY_JSON : &str = r##"
+    [
+        {
+            "title": "Space Racer",
+            "authors": ["A. Author", "B. Author"],
+            "roms": {
+                "0123456789ABCDEF0123456789abcdef01234567": {
+                    "platforms": ["megachip8", "superchip"],
+                    "quirkyPlatforms": { "superchip": { "shift": false, "jump": false, "wrap": true } },
+                    "tickrate": 30,
+                    "colors": { "pixels": ["#000000", "#ffffff"] }
+                }
+            }
+        },
+        {
+            "title": "No roms"
+        }
+    ]"##;
+
+    fn temp_path(name: &str) -> PathBuf
+    {
+        env::temp_dir().join(format!("chust8_{}_{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn hashing()
+    {
+        assert_eq!(rom_hash(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn settings_roundtrip() -> Result<(), String>
+    {
+        let config = Config { platform : Some(Platform::Schip),
+                              quirks   : Some(Quirks { sprite_wrap: true, ..Quirks::default() }),
+                              font     : Some(FontSet::Dream6800),
+                              font_address : Some(0x100),
+                              instructions_per_frame : 30,
+                              palette  : Some(Palette::parse("white")?),
+                              ..Config::default() };
+
+        let settings = RomSettings::from_config(&config);
+
+        assert_eq!(settings.platform.as_deref(), Some("schip"));
+        assert_eq!(settings.quirks, Some(vec![String::from("wrap")]));
+        assert_eq!(settings.font.as_deref(), Some("dream6800"));
+        assert_eq!(settings.font_address, Some(0x100));
+        assert_eq!(settings.keymap, None);
+
+        // As stored in the database
+        let json     = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+        let settings = serde_json::from_str::<RomSettings>(&json).map_err(|e| e.to_string())?;
+
+        let mut restored = Config::default();
+        settings.apply(&mut restored)?;
+
+        assert_eq!(restored, config);
+
+        Ok(())
+    }
+
+    #[test]
+    fn database_file() -> Result<(), String>
+    {
+        let path = temp_path("database");
+        let _    = fs::remove_file(&path);
+
+        let mut database = SettingsDatabase::load(&path)?;
+        assert!(database.is_empty());
+
+        let settings = RomSettings { title: Some(String::from("Pong")), ipf: Some(12),
+                                     ..RomSettings::default() };
+        database.insert("abcd", settings.clone());
+
+        // The title survives new settings without one
+        database.insert("abcd", RomSettings { ipf: Some(15), ..RomSettings::default() });
+        database.save()?;
+
+        let database = SettingsDatabase::load(&path)?;
+        let _        = fs::remove_file(&path);
+
+        assert_eq!(database.get("abcd"), Some(&RomSettings { ipf: Some(15), ..settings }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn community_import() -> Result<(), String>
+    {
+        let mut database = SettingsDatabase::load(temp_path("community"))?;
+        database.insert("0123456789abcdef0123456789abcdef01234567",
+                        RomSettings { ipf: Some(100), ..RomSettings::default() });
+
+        assert_eq!(database.import_community(COMMUNITY_JSON)?, 1);
+
+        let settings = database.get("0123456789abcdef0123456789abcdef01234567").unwrap();
+
+        assert_eq!(settings.title.as_deref(), Some("Space Racer"));
+        assert_eq!(settings.author.as_deref(), Some("A. Author, B. Author"));
+        assert_eq!(settings.platform.as_deref(), Some("schip"));
+        assert_eq!(settings.quirks, Some(vec![String::from("shift-vy"), String::from("wrap")]));
+        assert_eq!(settings.palette.as_deref(), Some("#ffffff,#000000"));
+
+        // Existing settings win
+        assert_eq!(settings.ipf, Some(100));
+
+        assert!(database.import_community("{}").is_err(), "Accepted a non list");
+
+        Ok(())
+    }
+
+    #[test]
+    fn community_platforms()
+    {
+        assert_eq!(community_platform("originalChip8"), Some(Platform::CosmacVip));
+        assert_eq!(community_platform("hybridVIP"),     Some(Platform::CosmacVip));
+        assert_eq!(community_platform("chip48"),        Some(Platform::Schip));
+        assert_eq!(community_platform("superchip1"),    Some(Platform::Schip));
+        assert_eq!(community_platform("superchip"),     Some(Platform::Schip));
+        assert_eq!(community_platform("xochip"),        Some(Platform::XoChip));
+
+        assert_eq!(community_platform("modernChip8"), None);
+        assert_eq!(community_platform("chip8x"),      None);
+        assert_eq!(community_platform("megachip8"),   None);
+    }
+}
@@ -0,0 +1,589 @@
+use std::collections::HashMap;
+
+use crate::display::PixelGrid;
+use crate::memory::INSTRUCTION_SIZE;
+use crate::opcodes::OpCode;
+use crate::opcodes::OpCode::*;
+
+// Diagnostic rom running every instruction and checking its result and
+// flags. Each test gets a cell on screen, eight per row: the leading
+// digit of the instruction under test, followed by a dot at the bottom
+// when it passed or a bar when it failed.
+//
+// The rom expects key A to be held, for FX0A, EX9E and EXA1, and a font
+// with big glyphs for FX30. 0NNN is not covered, as it calls machine
+// code of the original computer.
+
+const PROGRAM_START : usize = 0x200;
+
+// Registers used by the reporting routine. Tests use the rest.
+const TEST_ADDRESS  : u8 = 0x9;
+const RESULT        : u8 = 0xA;
+const DIGIT         : u8 = 0xB;
+const CELL_X        : u8 = 0xC;
+const CELL_Y        : u8 = 0xD;
+const FLAG          : u8 = 0xF;
+
+const CELL_WIDTH    : usize = 8;
+const CELL_HEIGHT   : usize = 6;
+const MARK_OFFSET   : usize = 5;
+const GLYPH_HEIGHT  : u8 = 5;
+
+// Key that has to be held while the rom runs
+pub const HELD_KEY  : u8 = 0xA;
+
+// Name of every test, in the order they appear on screen
+pub const TESTS : [&str; 39] =
+[
+    "00E0",        "2NNN",        "00EE",        "1NNN",
+    "3XNN",        "4XNN",        "5XY0",        "6XNN",
+    "7XNN",        "8XY0",        "8XY1",        "8XY2",
+    "8XY3",        "8XY4",        "8XY4 carry",  "8XY4 VF",
+    "8XY5",        "8XY5 borrow", "8XY6",        "8XY7",
+    "8XY7 borrow", "8XYE",        "9XY0",        "ANNN",
+    "BNNN",        "CXNN",        "DXYN",        "EX9E",
+    "EXA1",        "FX07",        "FX15",        "FX0A",
+    "FX18",        "FX1E",        "FX1E overflow", "FX29",
+    "FX30",        "FX33",        "FX55/FX65",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestResult
+{
+    Passed,
+    Failed,
+    // The rom crashed or stopped before reaching the test
+    NotRun,
+}
+
+// Builds the rom
+pub fn rom() -> Vec<u8>
+{
+    let mut rom = RomBuilder::new();
+
+    rom.op(_6XNN(CELL_X, 0));
+    rom.op(_6XNN(CELL_Y, 0));
+
+    rom.test(0x0, |rom|
+    {
+        // A pixel drawn again after clearing does not collide
+        rom.op(_6XNN(0, 0));
+        rom.jump(_ANNN, "dot");
+        rom.op(_DXYN(0, 0, 1));
+        rom.op(_00E0);
+        rom.op(_DXYN(0, 0, 1));
+        rom.expect(FLAG, 0);
+        rom.op(_DXYN(0, 0, 1));
+    });
+
+    rom.test(0x2, |rom|
+    {
+        rom.op(_6XNN(0, 0));
+        rom.jump(_2NNN, "set_v0");
+        rom.expect(0, 1);
+    });
+
+    rom.test(0x0, |rom|
+    {
+        // Execution continues right after the call
+        rom.op(_6XNN(1, 0));
+        rom.jump(_2NNN, "set_v1");
+        rom.op(_7XNN(1, 1));
+        rom.expect(1, 8);
+    });
+
+    rom.test(0x1, |rom|
+    {
+        rom.jump(_1NNN, "jump_target");
+        rom.fail();
+        rom.label("jump_target");
+    });
+
+    rom.test(0x3, |rom|
+    {
+        rom.op(_6XNN(0, 5));
+        rom.op(_3XNN(0, 5));
+        rom.fail();
+        rom.op(_6XNN(1, 0));
+        rom.op(_3XNN(0, 6));
+        rom.op(_6XNN(1, 1));
+        rom.expect(1, 1);
+    });
+
+    rom.test(0x4, |rom|
+    {
+        rom.op(_6XNN(0, 5));
+        rom.op(_4XNN(0, 6));
+        rom.fail();
+        rom.op(_6XNN(1, 0));
+        rom.op(_4XNN(0, 5));
+        rom.op(_6XNN(1, 1));
+        rom.expect(1, 1);
+    });
+
+    rom.test(0x5, |rom|
+    {
+        rom.op(_6XNN(0, 5));
+        rom.op(_6XNN(1, 5));
+        rom.op(_6XNN(2, 6));
+        rom.op(_5XY0(0, 1));
+        rom.fail();
+        rom.op(_6XNN(3, 0));
+        rom.op(_5XY0(0, 2));
+        rom.op(_6XNN(3, 1));
+        rom.expect(3, 1);
+    });
+
+    rom.test(0x6, |rom|
+    {
+        rom.op(_6XNN(0, 0xA5));
+        rom.expect(0, 0xA5);
+    });
+
+    rom.test(0x7, |rom|
+    {
+        // No carry flag
+        rom.op(_6XNN(0, 0xFF));
+        rom.op(_6XNN(FLAG, 5));
+        rom.op(_7XNN(0, 2));
+        rom.expect(0, 1);
+        rom.expect(FLAG, 5);
+    });
+
+    rom.alu_test(_8XY0(0, 1), 0x35, 0x0F, 0x0F, None);
+    rom.alu_test(_8XY1(0, 1), 0x35, 0x0F, 0x3F, None);
+    rom.alu_test(_8XY2(0, 1), 0x35, 0x0F, 0x05, None);
+    rom.alu_test(_8XY3(0, 1), 0x35, 0x0F, 0x3A, None);
+    rom.alu_test(_8XY4(0, 1), 0x12, 0x34, 0x46, Some(0));
+    rom.alu_test(_8XY4(0, 1), 0xF0, 0x20, 0x10, Some(1));
+
+    rom.test(0x8, |rom|
+    {
+        // The flag wins when VF is also the result
+        rom.op(_6XNN(FLAG, 0xFF));
+        rom.op(_6XNN(1, 2));
+        rom.op(_8XY4(FLAG, 1));
+        rom.expect(FLAG, 1);
+    });
+
+    rom.alu_test(_8XY5(0, 1), 0x34, 0x12, 0x22, Some(1));
+    rom.alu_test(_8XY5(0, 1), 0x12, 0x34, 0xDE, Some(0));
+
+    rom.test(0x8, |rom|
+    {
+        rom.op(_6XNN(0, 0x05));
+        rom.op(_8XY6(0, 0));
+        rom.expect(0, 0x02);
+        rom.expect(FLAG, 1);
+        rom.op(_8XY6(0, 0));
+        rom.expect(0, 0x01);
+        rom.expect(FLAG, 0);
+    });
+
+    rom.alu_test(_8XY7(0, 1), 0x12, 0x34, 0x22, Some(1));
+    rom.alu_test(_8XY7(0, 1), 0x34, 0x12, 0xDE, Some(0));
+
+    rom.test(0x8, |rom|
+    {
+        // The flag is the most significant bit
+        rom.op(_6XNN(0, 0x81));
+        rom.op(_8XYE(0, 0));
+        rom.expect(0, 0x02);
+        rom.expect(FLAG, 1);
+        rom.op(_6XNN(0, 0x40));
+        rom.op(_8XYE(0, 0));
+        rom.expect(0, 0x80);
+        rom.expect(FLAG, 0);
+    });
+
+    rom.test(0x9, |rom|
+    {
+        rom.op(_6XNN(0, 5));
+        rom.op(_6XNN(1, 6));
+        rom.op(_6XNN(2, 5));
+        rom.op(_9XY0(0, 1));
+        rom.fail();
+        rom.op(_6XNN(3, 0));
+        rom.op(_9XY0(0, 2));
+        rom.op(_6XNN(3, 1));
+        rom.expect(3, 1);
+    });
+
+    rom.test(0xA, |rom|
+    {
+        rom.jump(_ANNN, "data");
+        rom.op(_FX65(0));
+        rom.expect(0, 0x5A);
+    });
+
+    rom.test(0xB, |rom|
+    {
+        // Lands past the two failures
+        rom.op(_6XNN(0, 2 * INSTRUCTION_SIZE as u8));
+        rom.jump(_BNNN, "jump_base");
+        rom.label("jump_base");
+        rom.fail();
+        rom.fail();
+    });
+
+    rom.test(0xC, |rom|
+    {
+        rom.op(_CXNN(0, 0x00));
+        rom.expect(0, 0);
+        rom.op(_CXNN(1, 0x0F));
+        rom.op(_6XNN(2, 0xF0));
+        rom.op(_8XY2(2, 1));
+        rom.expect(2, 0);
+    });
+
+    rom.test(0xD, |rom|
+    {
+        // Below the cells, drawing twice collides and erases the pixel
+        rom.op(_6XNN(0, 60));
+        rom.op(_6XNN(1, 31));
+        rom.jump(_ANNN, "dot");
+        rom.op(_DXYN(0, 1, 1));
+        rom.expect(FLAG, 0);
+        rom.op(_DXYN(0, 1, 1));
+        rom.expect(FLAG, 1);
+    });
+
+    rom.test(0xE, |rom|
+    {
+        rom.op(_6XNN(0, HELD_KEY));
+        rom.op(_EX9E(0));
+        rom.fail();
+        rom.op(_6XNN(1, HELD_KEY + 1));
+        rom.op(_6XNN(2, 0));
+        rom.op(_EX9E(1));
+        rom.op(_6XNN(2, 1));
+        rom.expect(2, 1);
+    });
+
+    rom.test(0xE, |rom|
+    {
+        rom.op(_6XNN(1, HELD_KEY + 1));
+        rom.op(_EXA1(1));
+        rom.fail();
+        rom.op(_6XNN(0, HELD_KEY));
+        rom.op(_6XNN(2, 0));
+        rom.op(_EXA1(0));
+        rom.op(_6XNN(2, 1));
+        rom.expect(2, 1);
+    });
+
+    rom.test(0xF, |rom|
+    {
+        // The timer may tick once in between
+        rom.op(_6XNN(0, 0x20));
+        rom.op(_FX15(0));
+        rom.op(_FX07(1));
+        rom.op(_4XNN(1, 0x1F));
+        rom.op(_6XNN(1, 0x20));
+        rom.expect(1, 0x20);
+    });
+
+    rom.test(0xF, |rom|
+    {
+        rom.op(_6XNN(0, 0));
+        rom.op(_FX15(0));
+        rom.op(_6XNN(1, 0xFF));
+        rom.op(_FX07(1));
+        rom.expect(1, 0);
+    });
+
+    rom.test(0xF, |rom|
+    {
+        rom.op(_6XNN(0, 0));
+        rom.op(_FX0A(0));
+        rom.expect(0, HELD_KEY);
+    });
+
+    rom.test(0xF, |rom|
+    {
+        // Can't be checked from the rom, only that it runs
+        rom.op(_6XNN(0, 0));
+        rom.op(_FX18(0));
+    });
+
+    rom.test(0xF, |rom|
+    {
+        rom.op(_6XNN(FLAG, 5));
+        rom.jump(_ANNN, "data");
+        rom.op(_6XNN(0, 1));
+        rom.op(_FX1E(0));
+        rom.op(_FX65(0));
+        rom.expect(0, 0xC3);
+        rom.expect(FLAG, 5);
+    });
+
+    rom.test(0xF, |rom|
+    {
+        // I goes past the end of memory without setting VF, and memory
+        // accesses wrap around
+        rom.op(_6XNN(FLAG, 5));
+        rom.op(_ANNN(0xFFF));
+        rom.op(_6XNN(1, 0xF1));
+        rom.op(_FX1E(1));
+        rom.expect(FLAG, 5);
+        rom.op(_6XNN(0, 0x77));
+        rom.op(_FX55(0));
+        rom.op(_ANNN(0x0F0));
+        rom.op(_6XNN(0, 0));
+        rom.op(_FX65(0));
+        rom.expect(0, 0x77);
+    });
+
+    rom.test(0xF, |rom|
+    {
+        rom.op(_6XNN(0, 0));
+        rom.op(_FX29(0));
+        rom.op(_FX65(0));
+        rom.expect(0, 0xF0);
+    });
+
+    rom.test(0xF, |rom|
+    {
+        // Big glyphs are 10 bytes long
+        rom.op(_6XNN(3, 0));
+        rom.op(_FX30(3));
+        rom.op(_6XNN(4, 10));
+        rom.op(_FX1E(4));
+        rom.op(_FX65(0));
+        rom.op(_8XY0(5, 0));
+        rom.op(_6XNN(3, 1));
+        rom.op(_FX30(3));
+        rom.op(_FX65(0));
+        rom.op(_5XY0(0, 5));
+        rom.fail();
+    });
+
+    rom.test(0xF, |rom|
+    {
+        rom.op(_6XNN(0, 157));
+        rom.jump(_ANNN, "scratch");
+        rom.op(_FX33(0));
+        rom.op(_FX65(2));
+        rom.expect(0, 1);
+        rom.expect(1, 5);
+        rom.expect(2, 7);
+    });
+
+    rom.test(0xF, |rom|
+    {
+        // Only V0 to VX are copied, and I is left unchanged
+        rom.jump(_ANNN, "scratch");
+        rom.op(_6XNN(0, 0x11));
+        rom.op(_6XNN(1, 0x22));
+        rom.op(_6XNN(2, 0x33));
+        rom.op(_FX55(1));
+        rom.op(_6XNN(0, 0));
+        rom.op(_6XNN(1, 0));
+        rom.op(_FX65(2));
+        rom.expect(0, 0x11);
+        rom.expect(1, 0x22);
+        rom.expect(2, 7);
+    });
+
+    rom.label("end");
+    rom.jump(_1NNN, "end");
+
+    // Draws the digit and the result of a test, then moves to the next cell
+    rom.label("report");
+    rom.op(_FX29(DIGIT));
+    rom.op(_DXYN(CELL_X, CELL_Y, GLYPH_HEIGHT));
+    rom.op(_8XY0(TEST_ADDRESS, CELL_X));
+    rom.op(_7XNN(TEST_ADDRESS, MARK_OFFSET as u8));
+    rom.jump(_ANNN, "passed");
+    rom.op(_3XNN(RESULT, 0));
+    rom.jump(_ANNN, "failed");
+    rom.op(_DXYN(TEST_ADDRESS, CELL_Y, GLYPH_HEIGHT));
+    rom.op(_7XNN(CELL_X, CELL_WIDTH as u8));
+    rom.op(_3XNN(CELL_X, (CELL_WIDTH * 8) as u8));
+    rom.op(_00EE);
+    rom.op(_6XNN(CELL_X, 0));
+    rom.op(_7XNN(CELL_Y, CELL_HEIGHT as u8));
+    rom.op(_00EE);
+
+    rom.label("set_v0");
+    rom.op(_6XNN(0, 1));
+    rom.op(_00EE);
+
+    rom.label("set_v1");
+    rom.op(_6XNN(1, 7));
+    rom.op(_00EE);
+
+    rom.label("passed");
+    rom.bytes(&[0x00, 0x00, 0x00, 0x00, 0x80]);
+    rom.label("failed");
+    rom.bytes(&[0xC0, 0xC0, 0xC0, 0xC0, 0xC0]);
+    rom.label("dot");
+    rom.bytes(&[0x80]);
+    rom.label("data");
+    rom.bytes(&[0x5A, 0xC3]);
+    rom.label("scratch");
+    rom.bytes(&[0x00, 0x00, 0x00, 0x00]);
+
+    rom.build()
+}
+
+// Reads the result of every test from the screen
+pub fn results(screen: &PixelGrid) -> Vec<(&'static str, TestResult)>
+{
+    let lit = | row: usize, col: usize | screen.at(row, col).unwrap_or(false);
+
+    TESTS.iter().enumerate().map(|(index, &name)|
+    {
+        let x = (index % 8) * CELL_WIDTH + MARK_OFFSET;
+        let y = (index / 8) * CELL_HEIGHT;
+
+        let result = match (lit(y, x), lit(y + GLYPH_HEIGHT as usize - 1, x))
+        {
+            (true, _)      => TestResult::Failed,
+            (false, true)  => TestResult::Passed,
+            (false, false) => TestResult::NotRun,
+        };
+
+        (name, result)
+    })
+    .collect()
+}
+
+// Names of the tests that did not pass
+pub fn failures(screen: &PixelGrid) -> Vec<&'static str>
+{
+    results(screen).into_iter()
+                   .filter(|&(_, result)| result != TestResult::Passed)
+                   .map(|(name, _)| name)
+                   .collect()
+}
+
+// Offset of an instruction taking the address of a label
+type Fixup = (usize, &'static str, fn(u16) -> OpCode);
+
+// Emits instructions, resolving labels once the whole rom is known
+struct RomBuilder
+{
+    rom    : Vec<u8>,
+    labels : HashMap<&'static str, u16>,
+    fixups : Vec<Fixup>,
+}
+
+impl RomBuilder
+{
+    fn new() -> Self
+    {
+        RomBuilder { rom: Vec::new(), labels: HashMap::new(), fixups: Vec::new() }
+    }
+
+    fn op(&mut self, opcode: OpCode)
+    {
+        self.rom.extend_from_slice(&opcode.encode().to_be_bytes());
+    }
+
+    // Instruction taking the address of a label
+    fn jump(&mut self, opcode: fn(u16) -> OpCode, label: &'static str)
+    {
+        self.fixups.push((self.rom.len(), label, opcode));
+        self.op(_0NNN(0));
+    }
+
+    fn label(&mut self, label: &'static str)
+    {
+        self.labels.insert(label, (PROGRAM_START + self.rom.len()) as u16);
+    }
+
+    fn bytes(&mut self, bytes: &[u8])
+    {
+        self.rom.extend_from_slice(bytes);
+    }
+
+    // Marks the test as failed, when not skipped
+    fn fail(&mut self)
+    {
+        self.op(_6XNN(RESULT, 1));
+    }
+
+    fn expect(&mut self, x: u8, value: u8)
+    {
+        self.op(_3XNN(x, value));
+        self.fail();
+    }
+
+    fn test(&mut self, digit: u8, body: impl FnOnce(&mut Self))
+    {
+        self.op(_6XNN(RESULT, 0));
+        body(self);
+        self.op(_6XNN(DIGIT, digit));
+        self.jump(_2NNN, "report");
+    }
+
+    // VX = 0x8XYN of the given values, with the flag, if any
+    fn alu_test(&mut self, opcode: OpCode, vx: u8, vy: u8, result: u8, flag: Option<u8>)
+    {
+        self.test(0x8, |rom|
+        {
+            rom.op(_6XNN(0, vx));
+            rom.op(_6XNN(1, vy));
+            rom.op(_6XNN(FLAG, 5));
+            rom.op(opcode);
+            rom.expect(0, result);
+            rom.expect(FLAG, flag.unwrap_or(5));
+        });
+    }
+
+    fn build(mut self) -> Vec<u8>
+    {
+        for (offset, label, opcode) in self.fixups.drain(..).collect::<Vec<_>>()
+        {
+            let address = self.labels[label];
+            let bytes   = opcode(address).encode().to_be_bytes();
+
+            self.rom[offset..offset + INSTRUCTION_SIZE].copy_from_slice(&bytes);
+        }
+
+        self.rom
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::font::{ FontConfig, FontSet, DEFAULT_FONT_ADDRESS };
+
+    fn run(frames: u32) -> Result<Interpreter, String>
+    {
+        let mut interpreter = Interpreter::headless()?;
+
+        interpreter.set_font(FontConfig::new(FontSet::Schip, DEFAULT_FONT_ADDRESS))?;
+        interpreter.load_rom_bytes(&rom())?;
+        interpreter.set_key(HELD_KEY, true)?;
+        interpreter.run_frames(frames)?;
+
+        Ok(interpreter)
+    }
+
+    #[test]
+    fn all_tests_pass() -> Result<(), String>
+    {
+        let interpreter = run(300)?;
+        assert_eq!(failures(interpreter.screen()), Vec::<&str>::new());
+
+        Ok(())
+    }
+
+    #[test]
+    fn unfinished_run() -> Result<(), String>
+    {
+        // A few frames are not enough to reach the last tests
+        let interpreter = run(5)?;
+        let results     = results(interpreter.screen());
+
+        assert_eq!(results[0], ("00E0", TestResult::Passed));
+        assert_eq!(results[TESTS.len() - 1], ("FX55/FX65", TestResult::NotRun));
+
+        Ok(())
+    }
+}
@@ -3,7 +3,8 @@ use arrayvec::ArrayVec;
 
 pub const STACK_SIZE : usize = 64;
 
-type InternalStorage = ArrayVec<[u8; STACK_SIZE]>;
+// Stores return addresses for subroutine calls
+type InternalStorage = ArrayVec<[u16; STACK_SIZE]>;
 
 pub struct Stack
 {
@@ -17,7 +18,7 @@ impl Stack
         Stack { data: InternalStorage::new() }
     }
 
-    pub fn push(&mut self, value : u8) -> Result<(), String>
+    pub fn push(&mut self, value : u16) -> Result<(), String>
     {
         if self.data.is_full()
         {
@@ -28,7 +29,7 @@ impl Stack
         Ok(())
     }
 
-    pub fn pop(&mut self) -> Result<u8, String>
+    pub fn pop(&mut self) -> Result<u16, String>
     {
         let value = self.data.pop();
 
@@ -39,6 +40,23 @@ impl Stack
 
         Ok(value.unwrap())
     }
+
+    // Return addresses from the bottom of the stack to the top
+    pub fn entries(&self) -> &[u16]
+    {
+        &self.data
+    }
+
+    // Number of return addresses stored, i.e. the stack pointer
+    pub fn len(&self) -> usize
+    {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.data.is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -68,7 +86,7 @@ mod tests
         // Test filling up the stack
         for x in 1..=STACK_SIZE
         {
-            stack.push(x as u8)?;
+            stack.push(x as u16)?;
         }
 
         // We shouldn't be able to push anymore
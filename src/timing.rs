@@ -0,0 +1,208 @@
+use std::time::Duration;
+
+use crate::opcodes::OpCode;
+
+// The COSMAC VIP runs a CDP1802 at 1.7609 MHz. Each machine cycle
+// takes 8 clock cycles.
+pub const VIP_CYCLES_PER_SECOND : f64 = 1_760_900.0 / 8.0;
+pub const VIP_CYCLES_PER_FRAME  : u32 = 3668;
+
+// Taken every frame by the display interrupt routine and the 128
+// bytes DMA transfer to the CDP1861. The rest is left to the rom.
+pub const VIP_INTERRUPT_CYCLES   : u32 = 1096;
+pub const VIP_AVAILABLE_CYCLES   : u32 = VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES;
+
+// Fetching and decoding an instruction in the interpreter main loop
+const VIP_FETCH_CYCLES : u32 = 20;
+
+// How long instructions take to run
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TimingModel
+{
+    // Every instruction takes the same time, a fixed amount per frame
+    #[default]
+    Uniform,
+    // Each instruction takes as long as the VIP interpreter routine
+    // implementing it, and sprites wait for the vertical blank
+    CosmacVip,
+}
+
+// Names accepted by TimingModel::parse()
+pub const TIMING_NAMES : [&str; 2] = [ "uniform", "vip" ];
+
+impl TimingModel
+{
+    pub fn parse(name: &str) -> Result<Self, String>
+    {
+        match name.to_lowercase().as_str()
+        {
+            "uniform"            => Ok(TimingModel::Uniform),
+            "vip" | "cosmac-vip" => Ok(TimingModel::CosmacVip),
+            _                    => Err(format!("Unknown timing model '{}'. Valid models are {}",
+                                                name, TIMING_NAMES.join(", "))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            TimingModel::Uniform   => "uniform",
+            TimingModel::CosmacVip => "vip",
+        }
+    }
+}
+
+// Approximate machine cycles taken by the VIP interpreter to fetch and
+// run an instruction. The value of VX, before running it, and whether
+// it skipped the next instruction are needed for the variable costs.
+pub fn vip_cycles(opcode: &OpCode, vx: u8, skipped: bool) -> u32
+{
+    use OpCode::*;
+
+    let skip = if skipped { 4 } else { 0 };
+
+    let execution = match opcode
+    {
+        _0NNN(_)                     => 26,
+        _00E0                        => 24 + 256 * 8,
+        _00EE                        => 10,
+        _1NNN(_)                     => 12,
+        _2NNN(_)                     => 26,
+        _3XNN(..) | _4XNN(..)        => 10 + skip,
+        _5XY0(..) | _9XY0(..)        => 14 + skip,
+        _6XNN(..)                    => 6,
+        _7XNN(..)                    => 10,
+        _8XY0(..) | _8XY1(..) | _8XY2(..) | _8XY3(..) | _8XY4(..) |
+        _8XY5(..) | _8XY6(..) | _8XY7(..) | _8XYE(..) => 44,
+        _ANNN(_)                     => 12,
+        _BNNN(_)                     => 22,
+        _CXNN(..)                    => 36,
+        _DXYN(_, _, n)               => sprite_cycles(vx, *n),
+        _EX9E(_) | _EXA1(_)          => 14 + skip,
+        _FX07(_) | _FX0A(_)          => 10,
+        _FX15(_) | _FX18(_)          => 10,
+        _FX1E(_)                     => 16,
+        _FX29(_) | _FX30(_)          => 20,
+        // Digits are computed by repeated subtraction
+        _FX33(_)                     => 80 + 16 * digits_sum(vx),
+        _FX55(x) | _FX65(x)          => 14 + 14 * (*x as u32 + 1),
+    };
+
+    VIP_FETCH_CYCLES + execution
+}
+
+// The VIP interpreter waits for the display interrupt before drawing
+pub fn waits_for_vblank(opcode: &OpCode) -> bool
+{
+    matches!(opcode, OpCode::_DXYN(..))
+}
+
+// Real time taken by the given amount of machine cycles
+pub fn vip_duration(cycles: u64) -> Duration
+{
+    Duration::from_secs_f64(cycles as f64 / VIP_CYCLES_PER_SECOND)
+}
+
+// Real time a COSMAC VIP would take to run instructions of the given
+// machine cycles, the display interrupt taking its share of every frame.
+// Time spent waiting for the vertical blank is not counted.
+pub fn vip_run_time(cycles: u64) -> Duration
+{
+    vip_duration(cycles * VIP_CYCLES_PER_FRAME as u64 / VIP_AVAILABLE_CYCLES as u64)
+}
+
+// Each sprite row is shifted into place one bit at a time, and spans
+// two bytes of display memory when not aligned to a byte.
+fn sprite_cycles(x: u8, height: u8) -> u32
+{
+    let shift = (x % 8) as u32;
+    let rows  = height as u32;
+
+    let mut cycles = 68 + rows * (46 + 8 * shift);
+
+    if shift != 0
+    {
+        cycles += rows * 12;
+    }
+
+    cycles
+}
+
+fn digits_sum(value: u8) -> u32
+{
+    (value / 100 + (value / 10) % 10 + value % 10) as u32
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn instruction_costs()
+    {
+        assert_eq!(vip_cycles(&OpCode::_6XNN(0, 0x12), 0, false), 26);
+
+        // Skipping takes longer
+        let skip = OpCode::_3XNN(0, 0x12);
+        assert!(vip_cycles(&skip, 0, true) > vip_cycles(&skip, 0, false));
+
+        // As does converting bigger numbers to decimal
+        let bcd = OpCode::_FX33(0);
+        assert!(vip_cycles(&bcd, 199, false) > vip_cycles(&bcd, 100, false));
+
+        // And copying more registers
+        assert!(vip_cycles(&OpCode::_FX55(0xF), 0, false) > vip_cycles(&OpCode::_FX55(0), 0, false));
+    }
+
+    #[test]
+    fn sprite_costs()
+    {
+        let sprite = OpCode::_DXYN(0, 1, 5);
+
+        // Byte aligned sprites are the fastest to draw
+        let aligned   = vip_cycles(&sprite, 8, false);
+        let unaligned = vip_cycles(&sprite, 9, false);
+        let shifted   = vip_cycles(&sprite, 15, false);
+
+        assert!(aligned < unaligned, "Unaligned sprite is not slower");
+        assert!(unaligned < shifted, "Cost does not grow with the shift");
+
+        // Taller sprites take longer
+        assert!(vip_cycles(&OpCode::_DXYN(0, 1, 15), 8, false) > aligned);
+
+        assert!(waits_for_vblank(&sprite));
+        assert!(!waits_for_vblank(&OpCode::_00E0));
+    }
+
+    #[test]
+    fn model_names() -> Result<(), String>
+    {
+        for &name in TIMING_NAMES.iter()
+        {
+            assert_eq!(TimingModel::parse(name)?.name(), name);
+        }
+
+        assert_eq!(TimingModel::parse("COSMAC-VIP")?, TimingModel::CosmacVip);
+        assert!(TimingModel::parse("fast").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn frame_budget()
+    {
+        // About 85 simple instructions fit in a frame
+        let per_frame = VIP_AVAILABLE_CYCLES / vip_cycles(&OpCode::_7XNN(0, 1), 0, false);
+        assert_eq!(per_frame, 85);
+
+        // 60 frames take a second
+        let second = vip_duration(VIP_CYCLES_PER_FRAME as u64 * 60);
+        assert!((second.as_secs_f64() - 1.0).abs() < 0.001, "Unexpected frame duration");
+
+        // As do 60 frames of instructions, with the interrupts in between
+        let second = vip_run_time(VIP_AVAILABLE_CYCLES as u64 * 60);
+        assert!((second.as_secs_f64() - 1.0).abs() < 0.001, "Unexpected run time");
+    }
+}
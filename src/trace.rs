@@ -0,0 +1,213 @@
+use std::fs::File;
+use std::io::{ BufWriter, Write };
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::opcodes::OpCode;
+use crate::registers::NUM_DATA_REGISTERS;
+
+// Registers and timers compared before and after each instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineState
+{
+    pub registers : [u8; NUM_DATA_REGISTERS],
+    pub i         : u16,
+    pub sp        : usize,
+    pub delay     : u8,
+    pub sound     : u8,
+}
+
+// Instructions left out of the trace still count as executed, so the
+// cycle numbers of two filtered traces can be compared
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceFilter
+{
+    pub pc_range : Option<RangeInclusive<usize>>,
+    // Opcode names as returned by OpCode::name(), e.g. "DXYN"
+    pub opcodes  : Vec<String>,
+}
+
+impl TraceFilter
+{
+    // Parses a range of hexadecimal addresses such as "200-2FF"
+    pub fn parse_pc_range(text: &str) -> Result<RangeInclusive<usize>, String>
+    {
+        let parse = | value: &str |
+        {
+            let digits = value.trim().trim_start_matches("0x").trim_start_matches("0X");
+            usize::from_str_radix(digits, 16).map_err(|e| format!("Invalid address {}: {}", value, e))
+        };
+
+        match text.find('-')
+        {
+            Some(dash) => Ok(parse(&text[..dash])? ..= parse(&text[dash + 1..])?),
+            None       => Err(format!("Invalid address range {}, expected START-END", text)),
+        }
+    }
+
+    // Parses a comma separated list of opcode names, e.g. "DXYN,8XY4"
+    pub fn parse_opcodes(text: &str) -> Vec<String>
+    {
+        text.split(',')
+            .map(|name| name.trim().to_uppercase())
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+
+    pub fn matches(&self, address: usize, opcode: &OpCode) -> bool
+    {
+        let in_range = match &self.pc_range
+        {
+            Some(range) => range.contains(&address),
+            None        => true,
+        };
+
+        let selected = self.opcodes.is_empty()
+                    || self.opcodes.iter().any(|name| name == opcode.name());
+
+        in_range && selected
+    }
+}
+
+// Writes one line per executed instruction:
+//
+//   cycle    pc   word disassembly            changes      I      SP   DT    ST
+//   00000012 0204 7A01 ADD v10, 1             VA=03        I=0300 SP=0 DT=00 ST=00
+//
+// Only the registers changed by the instruction are listed.
+pub struct Tracer
+{
+    writer : Box<dyn Write>,
+    filter : TraceFilter,
+}
+
+// Public impl
+impl Tracer
+{
+    pub fn new(writer: Box<dyn Write>, filter: TraceFilter) -> Self
+    {
+        Tracer { writer, filter }
+    }
+
+    pub fn to_file<P: AsRef<Path>>(path: P, filter: TraceFilter) -> Result<Self, String>
+    {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        Ok(Self::new(Box::new(BufWriter::new(file)), filter))
+    }
+
+    pub fn log(&mut self, cycle: u64, address: usize, opcode: &OpCode, raw: u16,
+               before: &MachineState, after: &MachineState) -> Result<(), String>
+    {
+        if !self.filter.matches(address, opcode) { return Ok(()); }
+
+        let line = format_line(cycle, address, opcode, raw, before, after);
+        writeln!(self.writer, "{}", line).map_err(|e| e.to_string())
+    }
+
+    pub fn finish(mut self) -> Result<(), String>
+    {
+        self.writer.flush().map_err(|e| e.to_string())
+    }
+}
+
+pub fn format_line(cycle: u64, address: usize, opcode: &OpCode, raw: u16,
+                   before: &MachineState, after: &MachineState) -> String
+{
+    let changes: Vec<String> = (0..NUM_DATA_REGISTERS)
+        .filter(|&x| before.registers[x] != after.registers[x])
+        .map(|x| format!("V{:X}={:02X}", x, after.registers[x]))
+        .collect();
+
+    format!("{:08} {:04X} {:04X} {:<22} {:<12} I={:04X} SP={} DT={:02X} ST={:02X}",
+            cycle, address, raw, opcode.disassembly(), changes.join(" "),
+            after.i, after.sp, after.delay, after.sound)
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::{ Arc, Mutex };
+
+    // Writer whose contents can be read once the tracer is gone
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer
+    {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize>
+        {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()>
+        {
+            Ok(())
+        }
+    }
+
+    fn state() -> MachineState
+    {
+        MachineState { registers: [0; NUM_DATA_REGISTERS], i: 0x300, sp: 1, delay: 0x3C, sound: 0 }
+    }
+
+    #[test]
+    fn line_format()
+    {
+        let before    = state();
+        let mut after = state();
+
+        after.registers[0xA] = 0x03;
+        after.registers[0xF] = 0x01;
+
+        let line = format_line(12, 0x204, &OpCode::_8XY4(0xA, 0xB), 0x8AB4, &before, &after);
+
+        assert_eq!(line, "00000012 0204 8AB4 ADD v10, v11           VA=03 VF=01  \
+                          I=0300 SP=1 DT=3C ST=00");
+    }
+
+    #[test]
+    fn filtering() -> Result<(), String>
+    {
+        let filter = TraceFilter { pc_range : Some(TraceFilter::parse_pc_range("0x200-20F")?),
+                                   opcodes  : TraceFilter::parse_opcodes("dxyn, 7XNN"),
+                                 };
+
+        assert!(filter.matches(0x200, &OpCode::_DXYN(0, 1, 5)));
+        assert!(filter.matches(0x20F, &OpCode::_7XNN(0, 1)));
+        assert!(!filter.matches(0x210, &OpCode::_7XNN(0, 1)), "Address out of range traced");
+        assert!(!filter.matches(0x202, &OpCode::_00E0), "Filtered opcode traced");
+
+        assert!(TraceFilter::default().matches(0xFFF, &OpCode::_00E0));
+        assert!(TraceFilter::parse_pc_range("200").is_err());
+        assert!(TraceFilter::parse_pc_range("200-XYZ").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn filtered_trace() -> Result<(), String>
+    {
+        let buffer     = SharedBuffer::default();
+        let filter     = TraceFilter { pc_range: None, opcodes: vec![String::from("00E0")] };
+        let mut tracer = Tracer::new(Box::new(buffer.clone()), filter);
+
+        tracer.log(0, 0x200, &OpCode::_00E0, 0x00E0, &state(), &state())?;
+        tracer.log(1, 0x202, &OpCode::_1NNN(0x200), 0x1200, &state(), &state())?;
+        tracer.log(2, 0x200, &OpCode::_00E0, 0x00E0, &state(), &state())?;
+        tracer.finish()?;
+
+        let text  = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+
+        // Cycle numbers keep counting the filtered instructions
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("00000000 0200 00E0 CLEAR"));
+        assert!(lines[1].starts_with("00000002 0200 00E0 CLEAR"));
+
+        Ok(())
+    }
+}
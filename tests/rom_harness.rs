@@ -0,0 +1,297 @@
+// Runs the roms in tests/roms headless for a fixed amount of frames and
+// compares the final screen, or the sound, with the goldens in
+// tests/goldens.
+//
+// Roms are either raw .ch8 files, .asm sources assembled before running
+// or generated by the library, like the self-test rom. Goldens are
+// regenerated by running the tests with the CHUST8_UPDATE_GOLDENS
+// environment variable set:
+//
+//     CHUST8_UPDATE_GOLDENS=1 cargo test --test rom_harness
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use chust8::assembler;
+use chust8::audio::{ OfflineAudio, Waveform };
+use chust8::font::{ FontConfig, FontSet, DEFAULT_FONT_ADDRESS };
+use chust8::interpreter::Interpreter;
+use chust8::selftest;
+
+const UPDATE_VARIABLE : &str = "CHUST8_UPDATE_GOLDENS";
+const PROGRAM_START   : usize = 0x200;
+const DEFAULT_SEED    : u64 = 0xC8;
+
+// A key pressed or released before running the given frame
+struct KeyEvent
+{
+    frame   : u32,
+    key     : u8,
+    pressed : bool,
+}
+
+const fn press(frame: u32, key: u8) -> KeyEvent
+{
+    KeyEvent { frame, key, pressed: true }
+}
+
+const fn release(frame: u32, key: u8) -> KeyEvent
+{
+    KeyEvent { frame, key, pressed: false }
+}
+
+struct RomTest
+{
+    rom      : &'static str,
+    frames   : u32,
+    seed     : u64,
+    inputs   : &'static [KeyEvent],
+    font     : Option<FontSet>,
+    // Builds the rom instead of reading it from tests/roms
+    generate : Option<fn() -> Vec<u8>>,
+}
+
+impl RomTest
+{
+    const fn new(rom: &'static str, frames: u32) -> Self
+    {
+        RomTest { rom, frames, seed: DEFAULT_SEED, inputs: &[], font: None, generate: None }
+    }
+
+    const fn generated(rom: &'static str, generate: fn() -> Vec<u8>, frames: u32) -> Self
+    {
+        RomTest { generate: Some(generate), ..Self::new(rom, frames) }
+    }
+
+    const fn with_seed(self, seed: u64) -> Self
+    {
+        RomTest { seed, ..self }
+    }
+
+    const fn with_inputs(self, inputs: &'static [KeyEvent]) -> Self
+    {
+        RomTest { inputs, ..self }
+    }
+
+    const fn with_font(self, font: FontSet) -> Self
+    {
+        RomTest { font: Some(font), ..self }
+    }
+
+    // Runs the rom and returns the final screen
+    fn run(&self) -> Result<String, String>
+    {
+        let screen = self.interpreter()?.screen().clone();
+
+        Ok(format!("checksum: {:016X}\n{}\n", screen.checksum(), screen.to_ascii()))
+    }
+
+    // Runs the rom and returns the samples where the buzzer was heard,
+    // and a checksum of the whole sound
+    fn run_audio(&self) -> Result<String, String>
+    {
+        let interpreter = self.interpreter()?;
+        let audio       = interpreter.offline_audio().ok_or("Headless interpreter without audio")?;
+
+        Ok(describe_audio(audio))
+    }
+
+    // Runs the rom and returns the interpreter in its final state
+    fn interpreter(&self) -> Result<Interpreter, String>
+    {
+        let mut interpreter = Interpreter::headless()?;
+
+        // The square wave is computed without sin(), so the samples are
+        // the same on every platform
+        interpreter.tone_control().set_waveform(Waveform::Square);
+
+        if let Some(font) = self.font
+        {
+            interpreter.set_font(FontConfig::new(font, DEFAULT_FONT_ADDRESS))?;
+        }
+
+        interpreter.load_rom_bytes(&self.load()?)?;
+        interpreter.set_seed(self.seed);
+
+        for frame in 0..self.frames
+        {
+            for event in self.inputs.iter().filter(|event| event.frame == frame)
+            {
+                interpreter.set_key(event.key, event.pressed)?;
+            }
+
+            interpreter.run_frames(1)?;
+        }
+
+        Ok(interpreter)
+    }
+
+    fn load(&self) -> Result<Vec<u8>, String>
+    {
+        if let Some(generate) = self.generate
+        {
+            return Ok(generate());
+        }
+
+        let path = roms_dir().join(self.rom);
+        let data = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        match path.extension().and_then(|extension| extension.to_str())
+        {
+            Some("asm") =>
+            {
+                let source = String::from_utf8(data).map_err(|e| e.to_string())?;
+                assembler::assemble(&source, PROGRAM_START).map_err(|e| format!("{}: {}", self.rom, e))
+            },
+
+            _ => Ok(data),
+        }
+    }
+
+    fn check(&self) -> Result<(), String>
+    {
+        compare_golden(self.rom, "txt", &self.run()?)
+    }
+
+    fn check_audio(&self) -> Result<(), String>
+    {
+        compare_golden(self.rom, "audio.txt", &self.run_audio()?)
+    }
+}
+
+// Compares the output of a rom with the golden of the given extension
+fn compare_golden(rom: &str, extension: &str, output: &str) -> Result<(), String>
+{
+    let golden = goldens_dir().join(rom).with_extension(extension);
+
+    if env::var_os(UPDATE_VARIABLE).is_some()
+    {
+        return fs::write(&golden, output).map_err(|e| e.to_string());
+    }
+
+    let expected = fs::read_to_string(&golden)
+        .map_err(|e| format!("{}: {}. Run with {}=1 to create it", golden.display(), e, UPDATE_VARIABLE))?;
+
+    if output != expected
+    {
+        return Err(format!("{} does not match its golden.\nExpected:\n{}\nGot:\n{}",
+                           rom, expected, output));
+    }
+
+    Ok(())
+}
+
+// Sample ranges that are not silent, one per line, and a 64 bits FNV-1a
+// hash of the samples
+fn describe_audio(audio: &OfflineAudio) -> String
+{
+    let samples  = audio.samples();
+    let checksum = samples.iter().flat_map(|sample| sample.to_le_bytes().to_vec())
+                          .fold(0xCBF2_9CE4_8422_2325u64, | hash, byte |
+                          {
+                              (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+                          });
+
+    let mut description = format!("sample rate: {}\nsamples: {}\nchecksum: {:016X}\n",
+                                  audio.sample_rate(), samples.len(), checksum);
+    let mut start       = None;
+
+    for (index, &sample) in samples.iter().chain(&[0]).enumerate()
+    {
+        match (start, sample != 0)
+        {
+            (None, true)         => start = Some(index),
+            (Some(first), false) =>
+            {
+                description += &format!("sound: {}-{}\n", first, index - 1);
+                start = None;
+            },
+            _ => (),
+        }
+    }
+
+    description
+}
+
+fn roms_dir() -> PathBuf
+{
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("roms")
+}
+
+fn goldens_dir() -> PathBuf
+{
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("goldens")
+}
+
+#[test]
+fn font() -> Result<(), String>
+{
+    RomTest::new("font.asm", 30).check()
+}
+
+#[test]
+fn collision() -> Result<(), String>
+{
+    RomTest::new("collision.asm", 10).check()
+}
+
+#[test]
+fn keypad() -> Result<(), String>
+{
+    const INPUTS : [KeyEvent; 5] = [ press(5, 0x7), release(10, 0x7),
+                                     press(15, 0xC), release(20, 0xC),
+                                     press(25, 0x0) ];
+
+    RomTest::new("keypad.asm", 30).with_inputs(&INPUTS).check()
+}
+
+#[test]
+fn random() -> Result<(), String>
+{
+    RomTest::new("random.asm", 20).check()
+}
+
+#[test]
+fn random_seeds_differ() -> Result<(), String>
+{
+    let first  = RomTest::new("random.asm", 20).with_seed(1).run()?;
+    let second = RomTest::new("random.asm", 20).with_seed(2).run()?;
+
+    assert_ne!(first, second, "The seed does not change the random numbers");
+
+    // But the same seed always gives the same screen
+    assert_eq!(first, RomTest::new("random.asm", 20).with_seed(1).run()?);
+
+    Ok(())
+}
+
+#[test]
+fn timers() -> Result<(), String>
+{
+    RomTest::new("timers.asm", 12 * 60 + 30).check()
+}
+
+#[test]
+fn timers_sound() -> Result<(), String>
+{
+    // A short beep when starting and after every second
+    RomTest::new("timers.asm", 3 * 60 + 10).check_audio()
+}
+
+#[test]
+fn self_test() -> Result<(), String>
+{
+    const INPUTS : [KeyEvent; 1] = [ press(0, selftest::HELD_KEY) ];
+
+    let test = RomTest::generated("self_test", selftest::rom, 300)
+                       .with_inputs(&INPUTS)
+                       .with_font(FontSet::Schip);
+
+    let interpreter = test.interpreter()?;
+    let failures    = selftest::failures(interpreter.screen());
+
+    assert!(failures.is_empty(), "Self-test failures: {}", failures.join(", "));
+
+    test.check()
+}
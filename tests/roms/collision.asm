@@ -0,0 +1,36 @@
+; Draws overlapping and disjoint sprites, then prints the collision
+; flag of each as a digit below them
+
+        LD   I, box
+        LD   V0, 8
+        LD   V1, 4
+        DRW  V0, V1, 4      ; first box, no collision
+        LD   V4, VF
+        LD   V0, 10
+        LD   V1, 6
+        DRW  V0, V1, 4      ; overlaps the first one
+        LD   V5, VF
+        LD   V0, 30
+        DRW  V0, V1, 4      ; far from both
+        LD   V6, VF
+        LD   V0, 62
+        LD   V1, 20
+        DRW  V0, V1, 4      ; clipped at the screen edge
+        LD   V7, VF
+
+        LD   V1, 24         ; print the flags
+        LD   V0, 4
+        LD   F, V4
+        DRW  V0, V1, 5
+        LD   V0, 10
+        LD   F, V5
+        DRW  V0, V1, 5
+        LD   V0, 16
+        LD   F, V6
+        DRW  V0, V1, 5
+        LD   V0, 22
+        LD   F, V7
+        DRW  V0, V1, 5
+end:    JP   end
+
+box:    DB   %11110000, %10010000, %10010000, %11110000
@@ -0,0 +1,16 @@
+; Draws the 16 hexadecimal digits of the built-in font on two rows
+
+        LD   V0, 0          ; digit
+        LD   V1, 4          ; x
+        LD   V2, 4          ; y
+loop:   LD   F, V0
+        DRW  V1, V2, 5
+        ADD  V0, 1
+        ADD  V1, 7
+        SE   V0, 8
+        JP   next
+        LD   V1, 4          ; second row
+        LD   V2, 14
+next:   SE   V0, 16
+        JP   loop
+end:    JP   end
@@ -0,0 +1,23 @@
+; Waits for three key presses and prints each key. A bar is shown on
+; the right while the key is held, using SKNP.
+
+        LD   V1, 2          ; x of the next key
+        LD   V2, 2
+        LD   V3, 0          ; keys read
+        LD   V4, 56         ; bar position
+        LD   V5, 2
+read:   LD   V0, K
+        LD   F, V0
+        DRW  V1, V2, 5
+        ADD  V1, 6
+        LD   I, bar
+        DRW  V4, V5, 2      ; bar on
+hold:   SKNP V0
+        JP   hold
+        DRW  V4, V5, 2      ; bar off
+        ADD  V3, 1
+        SE   V3, 3
+        JP   read
+end:    JP   end
+
+bar:    DB   %11111111, %11111111
@@ -0,0 +1,13 @@
+; Scatters dots at random positions. The picture depends on the seed.
+
+        LD   I, dot
+        LD   V2, 40
+loop:   RND  V0, #3F
+        RND  V1, #1F
+        DRW  V0, V1, 1
+        ADD  V2, 255      ; decrement
+        SE   V2, 0
+        JP   loop
+end:    JP   end
+
+dot:    DB   %10000000
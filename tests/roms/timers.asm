@@ -0,0 +1,26 @@
+; Counts seconds with the delay timer and prints the count in decimal
+; with BCD, beeping at every tick
+
+        LD   V8, 0          ; seconds
+        LD   V7, 2          ; beep length
+        LD   V4, 10
+second: LD   V5, 60
+        LD   DT, V5
+        LD   ST, V7
+wait:   LD   V5, DT
+        SE   V5, 0
+        JP   wait
+        CLS
+        ADD  V8, 1
+        LD   I, digits
+        LD   B, V8
+        LD   V2, [I]        ; V0 to V2 get the digits
+        LD   V3, 10
+        LD   F, V1
+        DRW  V3, V4, 5
+        ADD  V3, 6
+        LD   F, V2
+        DRW  V3, V4, 5
+        JP   second
+
+digits: DB   0, 0, 0